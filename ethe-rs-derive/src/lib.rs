@@ -0,0 +1,206 @@
+//! `#[derive(RemoteClass)]`, views of Java objects in the target declared field by field.
+//!
+//! ```ignore
+//! #[derive(RemoteClass)]
+//! #[class("bll")]
+//! pub struct Entity {
+//!     #[address]
+//!     pub _address: usize,
+//!
+//!     #[field("s", "D")]
+//!     pub pos_x: f64,
+//! }
+//! ```
+//!
+//! Every `#[field(name, signature)]` gets a method of the same name reading the field from the
+//! target as it is now, e.g. `entity.pos_x(source)`, and `RemoteClass::read` reads all of them at
+//! once into the struct. The fields are looked up in the `#[class]` (or its supers) the first time
+//! they're read, after that their offsets are cached. The `#[address]` field holds the object.
+
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::{format_ident, quote};
+use syn::{
+    parse_macro_input, punctuated::Punctuated, Attribute, Data, DeriveInput, Error, Fields, LitStr,
+    Token,
+};
+
+#[proc_macro_derive(RemoteClass, attributes(class, field, address))]
+pub fn derive_remote_class(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    expand(&input)
+        .unwrap_or_else(|error| error.to_compile_error())
+        .into()
+}
+
+/// The string literals an attribute like `#[field("s", "D")]` holds
+fn strings(attribute: &Attribute) -> syn::Result<Vec<LitStr>> {
+    let parser = Punctuated::<LitStr, Token![,]>::parse_terminated;
+
+    Ok(attribute
+        .parse_args_with(parser)?
+        .into_iter()
+        .collect::<Vec<LitStr>>())
+}
+
+fn expand(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+
+    let class = match input.attrs.iter().find(|attr| attr.path.is_ident("class")) {
+        Some(attribute) => match strings(attribute)?.as_slice() {
+            [class] => class.clone(),
+            _ => {
+                return Err(Error::new_spanned(
+                    attribute,
+                    "expected #[class(\"internal/Name\")]",
+                ))
+            }
+        },
+        None => {
+            return Err(Error::new(
+                Span::call_site(),
+                "RemoteClass needs the Java class, e.g. #[class(\"java/lang/Thread\")]",
+            ))
+        }
+    };
+
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(Error::new_spanned(
+                    name,
+                    "RemoteClass needs a struct with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(Error::new_spanned(
+                name,
+                "RemoteClass can only be derived for structs",
+            ))
+        }
+    };
+
+    let mut address = None;
+    let mut remote_fields = Vec::new();
+
+    for field in fields {
+        let ident = field.ident.as_ref().expect("named fields have names");
+
+        if field.attrs.iter().any(|attr| attr.path.is_ident("address")) {
+            if address.is_some() {
+                return Err(Error::new_spanned(
+                    field,
+                    "only one field can be the #[address]",
+                ));
+            }
+
+            address = Some(ident);
+            continue;
+        }
+
+        let attribute = match field.attrs.iter().find(|attr| attr.path.is_ident("field")) {
+            Some(attribute) => attribute,
+            None => {
+                return Err(Error::new_spanned(
+                    field,
+                    "every field needs #[field(\"name\", \"signature\")] or #[address]",
+                ))
+            }
+        };
+
+        match strings(attribute)?.as_slice() {
+            [java_name, sig] => {
+                remote_fields.push((ident, &field.ty, java_name.clone(), sig.clone()))
+            }
+            _ => {
+                return Err(Error::new_spanned(
+                    attribute,
+                    "expected #[field(\"name\", \"signature\")]",
+                ))
+            }
+        }
+    }
+
+    let address = match address {
+        Some(address) => address,
+        None => {
+            return Err(Error::new_spanned(
+                name,
+                "RemoteClass needs a usize field marked #[address] to hold the object",
+            ))
+        }
+    };
+
+    // the fields resolve once for all instances, so they live in a static next to the struct
+    let table = format_ident!("__{}_REMOTE_FIELDS", name.to_string().to_uppercase());
+    let count = remote_fields.len();
+
+    let declarations = remote_fields.iter().map(|(_, _, java_name, sig)| {
+        quote! {
+            crate::api::sdk::remote::RemoteField::new(#class, #java_name, #sig)
+        }
+    });
+
+    let accessors = remote_fields
+        .iter()
+        .enumerate()
+        .map(|(index, (ident, ty, java_name, sig))| {
+            let doc = format!(
+                "`{}.{}` ({}) as the target has it now",
+                class.value(),
+                java_name.value(),
+                sig.value()
+            );
+
+            quote! {
+                #[doc = #doc]
+                pub fn #ident(
+                    &self,
+                    source: &dyn crate::api::processes::MemorySource,
+                ) -> Result<#ty, crate::api::sdk::remote::RemoteError> {
+                    #table[#index].read(source, self.#address)
+                }
+            }
+        });
+
+    let reads = remote_fields
+        .iter()
+        .enumerate()
+        .map(|(index, (ident, _, _, _))| {
+            quote! {
+                #ident: #table[#index].read(source, address)?
+            }
+        });
+
+    Ok(quote! {
+        #[doc(hidden)]
+        static #table: [crate::api::sdk::remote::RemoteField; #count] = [#(#declarations),*];
+
+        impl #name {
+            #(#accessors)*
+        }
+
+        impl crate::api::sdk::remote::RemoteClass for #name {
+            const CLASS: &'static str = #class;
+
+            fn read(
+                source: &dyn crate::api::processes::MemorySource,
+                address: usize,
+            ) -> Result<Self, crate::api::sdk::remote::RemoteError> {
+                Ok(Self {
+                    #address: address,
+                    #(#reads),*
+                })
+            }
+
+            fn address(&self) -> usize {
+                self.#address
+            }
+        }
+    })
+}
@@ -0,0 +1,498 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    path::Path,
+};
+
+use crate::api::processes::{MemorySource, ModuleEntry, ReadError, SourceId};
+
+use super::{invalid, u16_at, u32_at, u64_at, DumpError, Region, RegionMap};
+
+const ELF_MAGIC: &[u8; 4] = b"\x7fELF";
+const ELFCLASS64: u8 = 2;
+const ELFDATA2LSB: u8 = 1;
+const ET_CORE: u16 = 4;
+
+const PT_LOAD: u32 = 1;
+const PT_NOTE: u32 = 4;
+
+/// "FILE", the note describing every file backed mapping of the process
+const NT_FILE: u32 = 0x4649_4c45;
+
+const ELF_HEADER_SIZE: usize = 64;
+const PROGRAM_HEADER_SIZE: usize = 56;
+
+#[derive(Debug)]
+struct ProgramHeader {
+    kind: u32,
+    offset: u64,
+    address: u64,
+    file_size: u64,
+}
+
+/// One entry of the NT_FILE note
+#[derive(Debug)]
+struct FileMapping {
+    start: usize,
+    size: usize,
+    offset: u64,
+    path: String,
+}
+
+/// A Linux ELF core file (e.g. from `gcore <pid>`) we can read the frozen process through.
+///
+/// Mappings the kernel or gdb left out of the core (file backed code is excluded by the default
+/// coredump_filter) are read from the mapped files themselves when they still exist on disk.
+pub struct CoreDump {
+    regions: RegionMap,
+    modules: Vec<ModuleEntry>,
+    id: SourceId,
+}
+
+impl CoreDump {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, DumpError> {
+        let path = path.as_ref();
+        let mut file = File::open(path)?;
+
+        let mut header = [0u8; ELF_HEADER_SIZE];
+        file.read_exact(&mut header)
+            .map_err(|_| invalid("file too small for an ELF header"))?;
+
+        if &header[0..4] != ELF_MAGIC {
+            return Err(invalid("not an ELF file"));
+        }
+
+        if header[4] != ELFCLASS64 || header[5] != ELFDATA2LSB {
+            return Err(invalid("only 64-bit little endian cores are supported"));
+        }
+
+        if u16_at(&header, 0x10) != Some(ET_CORE) {
+            return Err(invalid("not a core file"));
+        }
+
+        let program_headers_offset = u64_at(&header, 0x20).unwrap_or_default();
+        let program_header_size = u16_at(&header, 0x36).unwrap_or_default() as usize;
+        let program_header_count = u16_at(&header, 0x38).unwrap_or_default() as usize;
+
+        if program_header_size < PROGRAM_HEADER_SIZE {
+            return Err(invalid("program headers too small"));
+        }
+
+        let mut table = vec![0u8; program_header_size * program_header_count];
+        file.seek(SeekFrom::Start(program_headers_offset))?;
+        file.read_exact(&mut table)
+            .map_err(|_| invalid("truncated program header table"))?;
+
+        let program_headers = table
+            .chunks(program_header_size)
+            .map(|entry| ProgramHeader {
+                kind: u32_at(entry, 0).unwrap_or_default(),
+                offset: u64_at(entry, 8).unwrap_or_default(),
+                address: u64_at(entry, 16).unwrap_or_default(),
+                file_size: u64_at(entry, 32).unwrap_or_default(),
+            })
+            .collect::<Vec<_>>();
+
+        let mut regions = RegionMap::default();
+        let core = regions.add_file(path)?;
+
+        // only the part of a segment that made it into the file is readable
+        for header in program_headers.iter().filter(|h| h.kind == PT_LOAD) {
+            regions.add_region(Region {
+                address: header.address as usize,
+                size: header.file_size as usize,
+                file: core,
+                offset: header.offset,
+            });
+        }
+
+        let mut mappings = Vec::new();
+
+        let file_size = file.metadata()?.len();
+
+        for header in program_headers.iter().filter(|h| h.kind == PT_NOTE) {
+            // don't take the header's word for how much there is before allocating it
+            if header
+                .offset
+                .checked_add(header.file_size)
+                .is_none_or(|end| end > file_size)
+            {
+                return Err(invalid("truncated note segment"));
+            }
+
+            let mut notes = vec![0u8; header.file_size as usize];
+            file.seek(SeekFrom::Start(header.offset))?;
+            file.read_exact(&mut notes)
+                .map_err(|_| invalid("truncated note segment"))?;
+
+            mappings.extend(parse_notes(&notes)?);
+        }
+
+        // whatever is still missing might be in the files the process had mapped
+        let mut backing: HashMap<&str, Option<usize>> = HashMap::new();
+
+        for mapping in mappings.iter() {
+            let file = *backing
+                .entry(&mapping.path)
+                .or_insert_with(|| regions.add_file(Path::new(&mapping.path)).ok());
+
+            if let Some(file) = file {
+                regions.fill_gaps(mapping.start, mapping.size, file, mapping.offset);
+            }
+        }
+
+        Ok(Self {
+            regions,
+            modules: modules_from_mappings(&mappings),
+            id: SourceId::next(),
+        })
+    }
+}
+
+impl MemorySource for CoreDump {
+    fn read_bytes(&self, address: usize, buffer: &mut [u8]) -> Result<(), ReadError> {
+        self.regions.read_bytes(address, buffer)
+    }
+
+    fn id(&self) -> SourceId {
+        self.id
+    }
+
+    fn modules(&self) -> Vec<ModuleEntry> {
+        self.modules.clone()
+    }
+}
+
+/// Walk a PT_NOTE segment and pull out the NT_FILE mappings
+fn parse_notes(notes: &[u8]) -> Result<Vec<FileMapping>, DumpError> {
+    let align = |size: usize| (size + 3) & !3;
+
+    let mut mappings = Vec::new();
+    let mut offset = 0usize;
+
+    while offset + 12 <= notes.len() {
+        let name_size = u32_at(notes, offset).unwrap_or_default() as usize;
+        let desc_size = u32_at(notes, offset + 4).unwrap_or_default() as usize;
+        let kind = u32_at(notes, offset + 8).unwrap_or_default();
+
+        let desc_offset = offset + 12 + align(name_size);
+        let desc = notes
+            .get(desc_offset..desc_offset + desc_size)
+            .ok_or_else(|| invalid("truncated note"))?;
+
+        if kind == NT_FILE {
+            mappings.extend(parse_file_note(desc)?);
+        }
+
+        offset = desc_offset + align(desc_size);
+    }
+
+    Ok(mappings)
+}
+
+/// NT_FILE: count, page size, count * (start, end, page offset), then count file names
+fn parse_file_note(desc: &[u8]) -> Result<Vec<FileMapping>, DumpError> {
+    let malformed = || invalid("malformed NT_FILE note");
+
+    let count = u64_at(desc, 0).ok_or_else(malformed)? as usize;
+    let page_size = u64_at(desc, 8).ok_or_else(malformed)?;
+
+    let names_offset = count
+        .checked_mul(24)
+        .and_then(|size| size.checked_add(16))
+        .ok_or_else(malformed)?;
+    let mut names = desc
+        .get(names_offset..)
+        .ok_or_else(malformed)?
+        .split(|&c| c == 0);
+
+    (0..count)
+        .map(|idx| {
+            let entry = 16 + idx * 24;
+
+            let start = u64_at(desc, entry).ok_or_else(malformed)? as usize;
+            let end = u64_at(desc, entry + 8).ok_or_else(malformed)? as usize;
+            let page = u64_at(desc, entry + 16).ok_or_else(malformed)?;
+
+            Ok(FileMapping {
+                start,
+                size: end.checked_sub(start).ok_or_else(malformed)?,
+                offset: page.checked_mul(page_size).ok_or_else(malformed)?,
+                path: String::from_utf8_lossy(names.next().ok_or_else(malformed)?).to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Collapse the mappings of every file into one module spanning all of them
+fn modules_from_mappings(mappings: &[FileMapping]) -> Vec<ModuleEntry> {
+    let mut modules: Vec<ModuleEntry> = Vec::new();
+    let mut indices: HashMap<&str, usize> = HashMap::new();
+
+    for mapping in mappings {
+        if let Some(&idx) = indices.get(mapping.path.as_str()) {
+            let module = &mut modules[idx];
+            let module_end = (module.base + module.size).max(mapping.start + mapping.size);
+
+            module.base = module.base.min(mapping.start);
+            module.size = module_end - module.base;
+        } else {
+            indices.insert(&mapping.path, modules.len());
+            modules.push(ModuleEntry {
+                name: mapping.path.rsplit('/').next().unwrap_or_default().to_string(),
+                base: mapping.start,
+                size: mapping.size,
+            });
+        }
+    }
+
+    modules
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Write;
+
+    use crate::{
+        api::sdk::testing::{FakeField, JvmImage},
+        ether::{collect_all_classes, ClassTable},
+    };
+
+    fn note(kind: u32, name: &[u8], desc: &[u8]) -> Vec<u8> {
+        let mut note = Vec::new();
+        note.extend_from_slice(&(name.len() as u32).to_le_bytes());
+        note.extend_from_slice(&(desc.len() as u32).to_le_bytes());
+        note.extend_from_slice(&kind.to_le_bytes());
+        note.extend_from_slice(name);
+        note.resize((note.len() + 3) & !3, 0);
+        note.extend_from_slice(desc);
+        note.resize((note.len() + 3) & !3, 0);
+        note
+    }
+
+    fn program_header(kind: u32, offset: u64, address: u64, size: u64) -> Vec<u8> {
+        let mut header = Vec::new();
+        header.extend_from_slice(&kind.to_le_bytes());
+        header.extend_from_slice(&4u32.to_le_bytes());
+        header.extend_from_slice(&offset.to_le_bytes());
+        header.extend_from_slice(&address.to_le_bytes());
+        header.extend_from_slice(&0u64.to_le_bytes());
+        header.extend_from_slice(&size.to_le_bytes());
+        header.extend_from_slice(&size.to_le_bytes());
+        header.extend_from_slice(&0x1000u64.to_le_bytes());
+        header
+    }
+
+    /// A core holding `notes` and a PT_LOAD segment of every (address, contents) in `segments`
+    fn core(notes: &[u8], segments: &[(u64, &[u8])]) -> Vec<u8> {
+        let header_count = 1 + segments.len();
+
+        let mut core = Vec::new();
+        core.extend_from_slice(ELF_MAGIC);
+        core.extend_from_slice(&[ELFCLASS64, ELFDATA2LSB, 1]);
+        core.resize(0x10, 0);
+        core.extend_from_slice(&ET_CORE.to_le_bytes());
+        core.resize(0x20, 0);
+        core.extend_from_slice(&(ELF_HEADER_SIZE as u64).to_le_bytes());
+        core.resize(0x36, 0);
+        core.extend_from_slice(&(PROGRAM_HEADER_SIZE as u16).to_le_bytes());
+        core.extend_from_slice(&(header_count as u16).to_le_bytes());
+        core.resize(ELF_HEADER_SIZE, 0);
+
+        let mut offset = (ELF_HEADER_SIZE + header_count * PROGRAM_HEADER_SIZE) as u64;
+
+        core.extend(program_header(PT_NOTE, offset, 0, notes.len() as u64));
+        offset += notes.len() as u64;
+
+        for (address, contents) in segments {
+            core.extend(program_header(
+                PT_LOAD,
+                offset,
+                *address,
+                contents.len() as u64,
+            ));
+            offset += contents.len() as u64;
+        }
+
+        core.extend_from_slice(notes);
+        for (_, contents) in segments {
+            core.extend_from_slice(contents);
+        }
+
+        core
+    }
+
+    /// An NT_FILE note of (start, end, page offset) mappings of `path`
+    fn file_note(page_size: u64, mappings: &[(u64, u64, u64)], path: &Path) -> Vec<u8> {
+        let mut file_note = Vec::new();
+        file_note.extend_from_slice(&(mappings.len() as u64).to_le_bytes());
+        file_note.extend_from_slice(&page_size.to_le_bytes());
+        for (start, end, page) in mappings {
+            file_note.extend_from_slice(&start.to_le_bytes());
+            file_note.extend_from_slice(&end.to_le_bytes());
+            file_note.extend_from_slice(&page.to_le_bytes());
+        }
+        for _ in mappings {
+            file_note.extend_from_slice(path.to_str().unwrap().as_bytes());
+            file_note.push(0);
+        }
+
+        note(NT_FILE, b"CORE\0", &file_note)
+    }
+
+    /// A core with two adjacent PT_LOAD segments and an NT_FILE note mapping `module` twice
+    fn write_core(path: &Path, module: &Path) {
+        let mappings = [(0x7000_0000, 0x7000_1000, 0), (0x7000_1000, 0x7000_3000, 1)];
+        let notes = file_note(0x1000, &mappings, module);
+
+        let data = (0u8..0x20).collect::<Vec<u8>>();
+        let core = core(
+            &notes,
+            &[(0x7000_0ff0, &data[..0x10]), (0x7000_1000, &data[0x10..])],
+        );
+
+        File::create(path).unwrap().write_all(&core).unwrap();
+    }
+
+    /// Open a core of `notes` and `segments` the way it would be opened from disk
+    fn open_core(
+        name: &str,
+        notes: &[u8],
+        segments: &[(u64, &[u8])],
+    ) -> Result<CoreDump, DumpError> {
+        let path = std::env::temp_dir().join(format!("ethe-rs-{}-{}", name, std::process::id()));
+        File::create(&path)
+            .unwrap()
+            .write_all(&core(notes, segments))
+            .unwrap();
+
+        let dump = CoreDump::open(&path);
+        std::fs::remove_file(&path).ok();
+
+        dump
+    }
+
+    #[test]
+    fn reads_segments_and_modules() {
+        let path = std::env::temp_dir().join(format!("ethe-rs-core-{}", std::process::id()));
+        let module = std::env::temp_dir().join(format!("libjvm.so.{}", std::process::id()));
+
+        // the second mapping starts one page into the file, so 0x7000_2000 is file offset 0x2000
+        let mut contents = vec![0u8; 0x3000];
+        contents[0x2000..0x2004].copy_from_slice(b"JVM!");
+        File::create(&module).unwrap().write_all(&contents).unwrap();
+
+        write_core(&path, &module);
+
+        let dump = CoreDump::open(&path);
+        std::fs::remove_file(&path).ok();
+        std::fs::remove_file(&module).ok();
+        let dump = dump.expect("Couldn't open core");
+
+        // spans both segments
+        let mut buffer = [0u8; 8];
+        dump.read_bytes(0x7000_0ffc, &mut buffer).unwrap();
+        assert_eq!(buffer, [12, 13, 14, 15, 16, 17, 18, 19]);
+
+        assert_eq!(
+            dump.read_bytes(0x1000, &mut buffer),
+            Err(ReadError::UnmappedAddress(0x1000))
+        );
+        assert!(matches!(
+            dump.read_bytes(0x7000_2ffc, &mut buffer),
+            Err(ReadError::PartialRead { read: 4, .. })
+        ));
+
+        // not in the core, but the mapped file still is on disk
+        let mut magic = [0u8; 4];
+        dump.read_bytes(0x7000_2000, &mut magic).unwrap();
+        assert_eq!(&magic, b"JVM!");
+
+        let name = module.file_name().unwrap().to_str().unwrap();
+        let libjvm = dump.find_module(name).expect("libjvm.so not in module list");
+        assert_eq!(libjvm.base, 0x7000_0000);
+        assert_eq!(libjvm.size, 0x3000);
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        let path = std::env::temp_dir().join(format!("ethe-rs-not-core-{}", std::process::id()));
+        File::create(&path).unwrap().write_all(&[0u8; 128]).unwrap();
+
+        let dump = CoreDump::open(&path);
+        std::fs::remove_file(&path).ok();
+
+        assert!(matches!(dump, Err(DumpError::InvalidFormat(_))));
+    }
+
+    #[test]
+    fn rejects_malformed_file_notes() {
+        let module = Path::new("/usr/lib/jvm/lib/server/libjvm.so");
+
+        // a mapping ending before it starts
+        let backwards = file_note(0x1000, &[(0x7000_1000, 0x7000_0000, 0)], module);
+        // a page offset that doesn't fit in 64 bits once it's in bytes
+        let far = file_note(0x1000, &[(0x7000_0000, 0x7000_1000, u64::MAX / 2)], module);
+
+        for notes in [backwards, far] {
+            assert!(matches!(
+                open_core("malformed-core", &notes, &[]),
+                Err(DumpError::InvalidFormat(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn reads_classes_from_core() {
+        let mut image = JvmImage::new(0x7f00_0000, 0x3000);
+
+        let object = image.class("java/lang/Object", 0, &[]);
+        let entity = image.class(
+            "pk",
+            object,
+            &[FakeField {
+                name: "posX",
+                sig: "D",
+                offset: 0x10,
+            }],
+        );
+        let player = image.class(
+            "wn",
+            entity,
+            &[FakeField {
+                name: "name",
+                sig: "Ljava/lang/String;",
+                offset: 0x18,
+            }],
+        );
+
+        let boot = image.class_loader_data(0, &[object]);
+        let app = image.class_loader_data(0x7f00_dead, &[entity, player]);
+        let head = image.class_loader_data_graph(&[boot, app]);
+
+        // the sdk never gets to see the image, only the core it was dumped into
+        let dump = open_core("jvm-core", &[], &[(image.base() as u64, image.bytes())])
+            .expect("Couldn't open core");
+
+        let classes = collect_all_classes(&ClassTable::ClassLoaderDataGraph(head), &dump);
+        assert_eq!(classes.len(), 3);
+
+        let clazz = classes.find_in(0x7f00_dead, "wn").expect("wn not in core");
+        assert_eq!(clazz.base as usize, player);
+
+        let fields = clazz
+            .iterate_fields(&dump)
+            .map(|field| (field.name, field.sig))
+            .collect::<Vec<(String, String)>>();
+        assert_eq!(
+            fields,
+            vec![
+                ("name".to_string(), "Ljava/lang/String;".to_string()),
+                ("posX".to_string(), "D".to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,352 @@
+use std::{
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    path::Path,
+};
+
+use crate::api::processes::{MemorySource, ModuleEntry, ReadError, SourceId};
+
+use super::{invalid, u32_at, u64_at, DumpError, Region, RegionMap};
+
+/// "MDMP"
+const MINIDUMP_SIGNATURE: u32 = 0x504d_444d;
+
+const MODULE_LIST_STREAM: u32 = 4;
+const MEMORY_LIST_STREAM: u32 = 5;
+const MEMORY64_LIST_STREAM: u32 = 9;
+
+const HEADER_SIZE: usize = 32;
+const DIRECTORY_SIZE: usize = 12;
+const MODULE_SIZE: usize = 108;
+const MEMORY_DESCRIPTOR_SIZE: usize = 16;
+
+/// A Windows minidump (e.g. a crash dump of javaw.exe) we can read the frozen process through.
+///
+/// Only memory that ended up in the dump is readable, a dump taken without full memory will
+/// be missing most of the heap.
+pub struct MiniDump {
+    regions: RegionMap,
+    modules: Vec<ModuleEntry>,
+    id: SourceId,
+}
+
+fn read_at(file: &mut File, offset: u64, size: usize) -> Result<Vec<u8>, DumpError> {
+    // sizes come from the file, don't go allocate more than it could possibly hold
+    let length = file.metadata()?.len();
+    if offset.checked_add(size as u64).is_none_or(|end| end > length) {
+        return Err(invalid("stream points past the end of the file"));
+    }
+
+    let mut buffer = vec![0u8; size];
+
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_exact(&mut buffer)
+        .map_err(|_| invalid("stream points past the end of the file"))?;
+
+    Ok(buffer)
+}
+
+impl MiniDump {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, DumpError> {
+        let path = path.as_ref();
+        let mut file = File::open(path)?;
+
+        let header = read_at(&mut file, 0, HEADER_SIZE)
+            .map_err(|_| invalid("file too small for a minidump header"))?;
+
+        if u32_at(&header, 0) != Some(MINIDUMP_SIGNATURE) {
+            return Err(invalid("not a minidump"));
+        }
+
+        let stream_count = u32_at(&header, 8).unwrap_or_default() as usize;
+        let directory_rva = u32_at(&header, 12).unwrap_or_default() as u64;
+
+        let directory_size = stream_count
+            .checked_mul(DIRECTORY_SIZE)
+            .ok_or_else(|| invalid("too many streams"))?;
+        let directory = read_at(&mut file, directory_rva, directory_size)?;
+
+        let mut regions = RegionMap::default();
+        let dump = regions.add_file(path)?;
+        let mut modules = Vec::new();
+
+        for entry in directory.chunks(DIRECTORY_SIZE) {
+            let kind = u32_at(entry, 0).unwrap_or_default();
+            let size = u32_at(entry, 4).unwrap_or_default() as usize;
+            let rva = u32_at(entry, 8).unwrap_or_default() as u64;
+
+            match kind {
+                MODULE_LIST_STREAM => {
+                    let stream = read_at(&mut file, rva, size)?;
+                    modules = parse_module_list(&mut file, &stream)?;
+                }
+                MEMORY_LIST_STREAM => {
+                    let stream = read_at(&mut file, rva, size)?;
+
+                    for region in parse_memory_list(&stream, dump)? {
+                        regions.add_region(region);
+                    }
+                }
+                MEMORY64_LIST_STREAM => {
+                    let stream = read_at(&mut file, rva, size)?;
+
+                    for region in parse_memory64_list(&stream, dump)? {
+                        regions.add_region(region);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Ok(Self {
+            regions,
+            modules,
+            id: SourceId::next(),
+        })
+    }
+}
+
+impl MemorySource for MiniDump {
+    fn read_bytes(&self, address: usize, buffer: &mut [u8]) -> Result<(), ReadError> {
+        self.regions.read_bytes(address, buffer)
+    }
+
+    fn id(&self) -> SourceId {
+        self.id
+    }
+
+    fn modules(&self) -> Vec<ModuleEntry> {
+        self.modules.clone()
+    }
+}
+
+/// MINIDUMP_MODULE_LIST, module names are MINIDUMP_STRINGs (length in bytes, then UTF-16)
+fn parse_module_list(file: &mut File, stream: &[u8]) -> Result<Vec<ModuleEntry>, DumpError> {
+    let malformed = || invalid("malformed module list");
+
+    let count = u32_at(stream, 0).ok_or_else(malformed)? as usize;
+
+    (0..count)
+        .map(|idx| {
+            let entry = 4 + idx * MODULE_SIZE;
+
+            let base = u64_at(stream, entry).ok_or_else(malformed)? as usize;
+            let size = u32_at(stream, entry + 8).ok_or_else(malformed)? as usize;
+            let name_rva = u32_at(stream, entry + 20).ok_or_else(malformed)? as u64;
+
+            let length = u32_at(&read_at(file, name_rva, 4)?, 0).unwrap_or_default() as usize;
+            let name = read_at(file, name_rva + 4, length)?
+                .chunks(2)
+                .map(|c| u16::from_le_bytes([c[0], *c.get(1).unwrap_or(&0)]))
+                .collect::<Vec<u16>>();
+            let name = String::from_utf16_lossy(&name);
+
+            Ok(ModuleEntry {
+                name: name.rsplit(['\\', '/']).next().unwrap_or_default().to_string(),
+                base,
+                size,
+            })
+        })
+        .collect()
+}
+
+/// MINIDUMP_MEMORY_LIST, every range carries its own location in the file
+fn parse_memory_list(stream: &[u8], file: usize) -> Result<Vec<Region>, DumpError> {
+    let malformed = || invalid("malformed memory list");
+
+    let count = u32_at(stream, 0).ok_or_else(malformed)? as usize;
+
+    (0..count)
+        .map(|idx| {
+            let entry = 4 + idx * MEMORY_DESCRIPTOR_SIZE;
+
+            Ok(Region {
+                address: u64_at(stream, entry).ok_or_else(malformed)? as usize,
+                size: u32_at(stream, entry + 8).ok_or_else(malformed)? as usize,
+                file,
+                offset: u32_at(stream, entry + 12).ok_or_else(malformed)? as u64,
+            })
+        })
+        .collect()
+}
+
+/// MINIDUMP_MEMORY64_LIST, all ranges are stored back to back starting at BaseRva
+fn parse_memory64_list(stream: &[u8], file: usize) -> Result<Vec<Region>, DumpError> {
+    let malformed = || invalid("malformed memory64 list");
+
+    let count = u64_at(stream, 0).ok_or_else(malformed)? as usize;
+    let mut offset = u64_at(stream, 8).ok_or_else(malformed)?;
+
+    (0..count)
+        .map(|idx| {
+            let entry = 16 + idx * MEMORY_DESCRIPTOR_SIZE;
+            let size = u64_at(stream, entry + 8).ok_or_else(malformed)?;
+
+            let region = Region {
+                address: u64_at(stream, entry).ok_or_else(malformed)? as usize,
+                size: size as usize,
+                file,
+                offset,
+            };
+            offset = offset.checked_add(size).ok_or_else(malformed)?;
+
+            Ok(region)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Write;
+
+    use crate::api::sig::{self, Signature};
+
+    const JVM_BASE: u64 = 0x7ff8_0000_0000;
+
+    /// A dump of a process with jvm.dll loaded, its first page in a MemoryListStream and the
+    /// code of interest in a Memory64ListStream
+    fn minidump() -> Vec<u8> {
+        let mut dump = vec![0u8; HEADER_SIZE];
+        dump[0..4].copy_from_slice(&MINIDUMP_SIGNATURE.to_le_bytes());
+        dump[4..8].copy_from_slice(&0xa793u32.to_le_bytes());
+        dump[8..12].copy_from_slice(&3u32.to_le_bytes());
+        dump[12..16].copy_from_slice(&(HEADER_SIZE as u32).to_le_bytes());
+
+        let directory = dump.len();
+        dump.resize(directory + 3 * DIRECTORY_SIZE, 0);
+
+        let add_stream = |dump: &mut Vec<u8>, idx: usize, kind: u32, data: Vec<u8>| {
+            let rva = dump.len() as u32;
+            dump.extend(data.iter());
+
+            let entry = directory + idx * DIRECTORY_SIZE;
+            dump[entry..entry + 4].copy_from_slice(&kind.to_le_bytes());
+            dump[entry + 4..entry + 8].copy_from_slice(&(data.len() as u32).to_le_bytes());
+            dump[entry + 8..entry + 12].copy_from_slice(&rva.to_le_bytes());
+        };
+
+        // module name first so the module list can point at it
+        let name_rva = dump.len() as u32;
+        let name = "C:\\Program Files\\Java\\jre8\\bin\\server\\jvm.dll"
+            .encode_utf16()
+            .flat_map(|c| c.to_le_bytes().to_vec())
+            .collect::<Vec<u8>>();
+        dump.extend(&(name.len() as u32).to_le_bytes());
+        dump.extend(name);
+
+        let mut modules = vec![0u8; 4 + MODULE_SIZE];
+        modules[0..4].copy_from_slice(&1u32.to_le_bytes());
+        modules[4..12].copy_from_slice(&JVM_BASE.to_le_bytes());
+        modules[12..16].copy_from_slice(&0x2000u32.to_le_bytes());
+        modules[24..28].copy_from_slice(&name_rva.to_le_bytes());
+        add_stream(&mut dump, 0, MODULE_LIST_STREAM, modules);
+
+        let header_page = dump.len() as u32;
+        let mut page = vec![0u8; 0x1000];
+        page[0..2].copy_from_slice(b"MZ");
+        dump.extend(page);
+
+        let mut memory = vec![0u8; 4 + MEMORY_DESCRIPTOR_SIZE];
+        memory[0..4].copy_from_slice(&1u32.to_le_bytes());
+        memory[4..12].copy_from_slice(&JVM_BASE.to_le_bytes());
+        memory[12..16].copy_from_slice(&0x1000u32.to_le_bytes());
+        memory[16..20].copy_from_slice(&header_page.to_le_bytes());
+        add_stream(&mut dump, 1, MEMORY_LIST_STREAM, memory);
+
+        // the code page lives at the very end of the file, after the Memory64ListStream
+        let base_rva = (dump.len() + 16 + MEMORY_DESCRIPTOR_SIZE) as u64;
+        let mut memory64 = vec![0u8; 16 + MEMORY_DESCRIPTOR_SIZE];
+        memory64[0..8].copy_from_slice(&1u64.to_le_bytes());
+        memory64[8..16].copy_from_slice(&base_rva.to_le_bytes());
+        memory64[16..24].copy_from_slice(&(JVM_BASE + 0x1000).to_le_bytes());
+        memory64[24..32].copy_from_slice(&0x1000u64.to_le_bytes());
+        add_stream(&mut dump, 2, MEMORY64_LIST_STREAM, memory64);
+
+        let mut code = vec![0x90u8; 0x1000];
+        code[0x200..0x20d].copy_from_slice(&[
+            0x48, 0x8b, 0x0d, 0x11, 0x22, 0x33, 0x44, 0x4c, 0x8b, 0xcd, 0x44, 0x8b, 0xc7,
+        ]);
+        dump.extend(code);
+
+        dump
+    }
+
+    fn open_dump(name: &str, dump: &[u8]) -> Result<MiniDump, DumpError> {
+        let path = std::env::temp_dir()
+            .join(format!("ethe-rs-{}-{}.dmp", name, std::process::id()));
+        File::create(&path).unwrap().write_all(dump).unwrap();
+
+        let dump = MiniDump::open(&path);
+        std::fs::remove_file(&path).ok();
+
+        dump
+    }
+
+    fn open_test_dump(name: &str) -> MiniDump {
+        open_dump(name, &minidump()).expect("Couldn't open minidump")
+    }
+
+    #[test]
+    fn reads_modules_and_memory() {
+        let dump = open_test_dump("modules");
+
+        let jvm = dump.find_module("jvm.dll").expect("jvm.dll not in module list");
+        assert_eq!(jvm.base, JVM_BASE as usize);
+        assert_eq!(jvm.size, 0x2000);
+
+        let mut magic = [0u8; 2];
+        dump.read_bytes(jvm.base, &mut magic).unwrap();
+        assert_eq!(&magic, b"MZ");
+
+        // straddles the MemoryListStream and the Memory64ListStream range
+        let mut buffer = [0u8; 4];
+        dump.read_bytes(jvm.base + 0xffe, &mut buffer).unwrap();
+        assert_eq!(buffer, [0, 0, 0x90, 0x90]);
+
+        assert_eq!(
+            dump.read_bytes(jvm.base + 0x2000, &mut buffer),
+            Err(ReadError::UnmappedAddress(jvm.base + 0x2000))
+        );
+    }
+
+    #[test]
+    fn replays_pattern_scan() {
+        let dump = open_test_dump("pattern");
+
+        let jvm = dump.find_module("jvm.dll").unwrap();
+        let pattern = Signature::new("48 8b 0d ?? ?? ?? ?? 4c 8b cd 44 8b c7");
+
+        assert_eq!(
+            sig::pattern_scan_module(&dump, &pattern, jvm),
+            Some(JVM_BASE as usize + 0x1200)
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_dumps() {
+        let directory = HEADER_SIZE;
+        let name = directory + 3 * DIRECTORY_SIZE;
+        let memory64 = u32_at(&minidump(), directory + 2 * DIRECTORY_SIZE + 8).unwrap() as usize;
+
+        let patched = |offset: usize, value: &[u8]| {
+            let mut dump = minidump();
+            dump[offset..offset + value.len()].copy_from_slice(value);
+            dump
+        };
+
+        // a directory, a module name and a range far bigger than the file
+        for (what, dump) in [
+            ("streams", patched(8, &u32::MAX.to_le_bytes())),
+            ("name", patched(name, &u32::MAX.to_le_bytes())),
+            ("memory64", patched(memory64 + 24, &u64::MAX.to_le_bytes())),
+        ] {
+            assert!(
+                matches!(open_dump(what, &dump), Err(DumpError::InvalidFormat(_))),
+                "{} wasn't rejected",
+                what
+            );
+        }
+    }
+}
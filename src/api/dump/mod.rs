@@ -0,0 +1,201 @@
+use std::{
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    path::Path,
+    sync::Mutex,
+};
+
+use super::processes::ReadError;
+
+// only the Linux binary opens cores, with --core
+#[cfg(any(target_os = "linux", test))]
+pub mod elf;
+// nothing in the binary opens minidumps (yet)
+#[allow(dead_code)]
+pub mod minidump;
+
+/// Why we couldn't open a dump
+#[derive(Debug)]
+pub enum DumpError {
+    Io(std::io::Error),
+
+    /// The file isn't a dump we understand
+    InvalidFormat(String),
+}
+
+impl std::fmt::Display for DumpError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DumpError::Io(error) => write!(f, "{}", error),
+            DumpError::InvalidFormat(reason) => write!(f, "invalid dump: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for DumpError {}
+
+impl From<std::io::Error> for DumpError {
+    fn from(error: std::io::Error) -> Self {
+        DumpError::Io(error)
+    }
+}
+
+fn invalid(reason: &str) -> DumpError {
+    DumpError::InvalidFormat(reason.to_string())
+}
+
+// dumps are little endian on every platform we care about
+
+#[cfg(any(target_os = "linux", test))]
+fn u16_at(data: &[u8], offset: usize) -> Option<u16> {
+    let mut bytes = [0u8; 2];
+    bytes.copy_from_slice(data.get(offset..offset + 2)?);
+
+    Some(u16::from_le_bytes(bytes))
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Option<u32> {
+    let mut bytes = [0u8; 4];
+    bytes.copy_from_slice(data.get(offset..offset + 4)?);
+
+    Some(u32::from_le_bytes(bytes))
+}
+
+fn u64_at(data: &[u8], offset: usize) -> Option<u64> {
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(data.get(offset..offset + 8)?);
+
+    Some(u64::from_le_bytes(bytes))
+}
+
+/// A range of the dumped address space and where its bytes live on disk
+#[derive(Debug, Clone)]
+pub struct Region {
+    pub address: usize,
+    pub size: usize,
+
+    /// Index of the file in the owning `RegionMap`
+    pub file: usize,
+
+    /// Offset of the first byte of the region in that file
+    pub offset: u64,
+}
+
+/// Maps virtual addresses onto byte ranges of one or more files, shared by all dump formats
+#[derive(Debug, Default)]
+pub struct RegionMap {
+    files: Vec<Mutex<File>>,
+    regions: Vec<Region>,
+}
+
+impl RegionMap {
+    /// Register a file regions can point into, returns its index
+    pub fn add_file(&mut self, path: &Path) -> std::io::Result<usize> {
+        self.files.push(Mutex::new(File::open(path)?));
+
+        Ok(self.files.len() - 1)
+    }
+
+    pub fn add_region(&mut self, region: Region) {
+        if region.size == 0 {
+            return;
+        }
+
+        let idx = self
+            .regions
+            .partition_point(|r| r.address < region.address);
+        self.regions.insert(idx, region);
+    }
+
+    /// Cover whatever part of `address..address + size` no region covers yet with `file`, where
+    /// `offset` is the file offset belonging to `address`
+    #[cfg(any(target_os = "linux", test))]
+    pub fn fill_gaps(&mut self, address: usize, size: usize, file: usize, offset: u64) {
+        let end = address + size;
+        let mut current = address;
+        let mut gaps = Vec::new();
+
+        for region in self.regions.iter() {
+            let region_end = region.address + region.size;
+
+            if region_end <= current {
+                continue;
+            }
+
+            if region.address >= end {
+                break;
+            }
+
+            if region.address > current {
+                gaps.push((current, region.address));
+            }
+
+            current = current.max(region_end);
+        }
+
+        if current < end {
+            gaps.push((current, end));
+        }
+
+        for (start, stop) in gaps {
+            self.add_region(Region {
+                address: start,
+                size: stop - start,
+                file,
+                offset: offset + (start - address) as u64,
+            });
+        }
+    }
+
+    fn find(&self, address: usize) -> Option<&Region> {
+        let idx = self.regions.partition_point(|r| r.address <= address);
+
+        self.regions[..idx]
+            .last()
+            .filter(|r| address - r.address < r.size)
+    }
+
+    fn read_file(&self, region: &Region, address: usize, buffer: &mut [u8]) -> std::io::Result<()> {
+        let mut file = self.files[region.file].lock().unwrap();
+
+        file.seek(SeekFrom::Start(region.offset + (address - region.address) as u64))?;
+        file.read_exact(buffer)
+    }
+
+    /// Read `buffer.len()` bytes at `address`, possibly spanning several adjacent regions
+    pub fn read_bytes(&self, address: usize, buffer: &mut [u8]) -> Result<(), ReadError> {
+        let mut read = 0usize;
+
+        while read < buffer.len() {
+            let current = address + read;
+
+            let region = match self.find(current) {
+                Some(region) => region,
+                None if read == 0 => return Err(ReadError::UnmappedAddress(address)),
+                None => break,
+            };
+
+            let count = (region.size - (current - region.address)).min(buffer.len() - read);
+
+            if self
+                .read_file(region, current, &mut buffer[read..read + count])
+                .is_err()
+            {
+                // a truncated dump, the region claims more than the file holds
+                break;
+            }
+
+            read += count;
+        }
+
+        if read < buffer.len() {
+            return Err(ReadError::PartialRead {
+                address,
+                requested: buffer.len(),
+                read,
+            });
+        }
+
+        Ok(())
+    }
+}
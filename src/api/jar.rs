@@ -0,0 +1,313 @@
+//! Just enough of the zip format to put files in a jar: entries are stored as they are, there's
+//! nothing in here worth compressing for the trouble it'd take. A jar of more than 65535 entries
+//! (a big modded client has those) gets the zip64 end of central directory on top of the usual
+//! one, the entries themselves stay 32 bit.
+
+use std::{
+    convert::TryFrom,
+    io::{self, Write},
+};
+
+const LOCAL_FILE_HEADER: u32 = 0x0403_4b50;
+const CENTRAL_DIRECTORY_HEADER: u32 = 0x0201_4b50;
+const END_OF_CENTRAL_DIRECTORY: u32 = 0x0605_4b50;
+const ZIP64_END_OF_CENTRAL_DIRECTORY: u32 = 0x0606_4b50;
+const ZIP64_END_OF_CENTRAL_DIRECTORY_LOCATOR: u32 = 0x0706_4b50;
+
+// 2.0, the first version that knows about directories
+const VERSION: u16 = 20;
+// 4.5, the first version with zip64
+const ZIP64_VERSION: u16 = 45;
+// names are UTF-8
+const FLAG_UTF8: u16 = 1 << 11;
+// 1980-01-01 00:00 in MS-DOS time, we don't know (or care) when the class was loaded
+const DOS_DATE: u16 = (1 << 5) | 1;
+
+/// CRC-32 as zip wants it (IEEE, reflected)
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+
+    for byte in bytes {
+        crc ^= *byte as u32;
+
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+
+    !crc
+}
+
+struct Entry {
+    name: String,
+    crc: u32,
+    size: u32,
+    offset: u32,
+}
+
+/// Writes a jar entry by entry, the central directory goes out on `finish`
+pub struct JarWriter<W: Write> {
+    writer: W,
+    written: u64,
+    entries: Vec<Entry>,
+}
+
+fn too_big(what: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("{} doesn't fit in a zip without zip64", what),
+    )
+}
+
+impl<W: Write> JarWriter<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            written: 0,
+            entries: Vec::new(),
+        }
+    }
+
+    fn put(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.writer.write_all(bytes)?;
+        self.written += bytes.len() as u64;
+
+        Ok(())
+    }
+
+    fn put_u2(&mut self, value: u16) -> io::Result<()> {
+        self.put(&value.to_le_bytes())
+    }
+
+    fn put_u4(&mut self, value: u32) -> io::Result<()> {
+        self.put(&value.to_le_bytes())
+    }
+
+    fn put_u8(&mut self, value: u64) -> io::Result<()> {
+        self.put(&value.to_le_bytes())
+    }
+
+    /// Everything the local and central headers have in common, from the version needed to
+    /// extract up to the extra field length
+    fn put_common_header(&mut self, name: &str, crc: u32, size: u32) -> io::Result<()> {
+        self.put_u2(VERSION)?;
+        self.put_u2(FLAG_UTF8)?;
+        // stored
+        self.put_u2(0)?;
+        // time, then date
+        self.put_u2(0)?;
+        self.put_u2(DOS_DATE)?;
+        self.put_u4(crc)?;
+        // compressed and uncompressed size are one and the same
+        self.put_u4(size)?;
+        self.put_u4(size)?;
+        self.put_u2(name.len() as u16)?;
+        self.put_u2(0)
+    }
+
+    /// Adds `bytes` as `name`, a path separated by '/'. Names have to be unique, we don't check.
+    pub fn add(&mut self, name: &str, bytes: &[u8]) -> io::Result<()> {
+        if name.len() > u16::MAX as usize {
+            return Err(too_big(name));
+        }
+
+        let size = u32::try_from(bytes.len()).map_err(|_| too_big(name))?;
+        let offset = u32::try_from(self.written).map_err(|_| too_big("the jar"))?;
+        let crc = crc32(bytes);
+
+        self.put_u4(LOCAL_FILE_HEADER)?;
+        self.put_common_header(name, crc, size)?;
+        self.put(name.as_bytes())?;
+        self.put(bytes)?;
+
+        self.entries.push(Entry {
+            name: name.to_string(),
+            crc,
+            size,
+            offset,
+        });
+
+        Ok(())
+    }
+
+    /// Writes the central directory, returning what we were writing to
+    pub fn finish(mut self) -> io::Result<W> {
+        let start = self.written;
+        let count = self.entries.len() as u64;
+
+        for entry in std::mem::take(&mut self.entries) {
+            self.put_u4(CENTRAL_DIRECTORY_HEADER)?;
+            // made by, same as needed to extract
+            self.put_u2(VERSION)?;
+            self.put_common_header(&entry.name, entry.crc, entry.size)?;
+            // comment length, disk number, internal and external attributes
+            self.put_u2(0)?;
+            self.put_u2(0)?;
+            self.put_u2(0)?;
+            self.put_u4(0)?;
+            self.put_u4(entry.offset)?;
+            self.put(entry.name.as_bytes())?;
+        }
+
+        let size = self.written - start;
+
+        // whatever doesn't fit the usual record is all ones there, readers look in the zip64 one
+        let short_count = u16::try_from(count).unwrap_or(u16::MAX);
+        let short_size = u32::try_from(size).unwrap_or(u32::MAX);
+        let short_start = u32::try_from(start).unwrap_or(u32::MAX);
+
+        if short_count == u16::MAX || short_size == u32::MAX || short_start == u32::MAX {
+            let zip64_end = self.written;
+
+            self.put_u4(ZIP64_END_OF_CENTRAL_DIRECTORY)?;
+            // the size of the rest of the record
+            self.put_u8(44)?;
+            // made by, needed to extract
+            self.put_u2(ZIP64_VERSION)?;
+            self.put_u2(ZIP64_VERSION)?;
+            // this disk and the one the central directory starts on
+            self.put_u4(0)?;
+            self.put_u4(0)?;
+            // entries on this disk and in total
+            self.put_u8(count)?;
+            self.put_u8(count)?;
+            self.put_u8(size)?;
+            self.put_u8(start)?;
+
+            self.put_u4(ZIP64_END_OF_CENTRAL_DIRECTORY_LOCATOR)?;
+            // the disk the zip64 record is on, where it is and how many disks there are
+            self.put_u4(0)?;
+            self.put_u8(zip64_end)?;
+            self.put_u4(1)?;
+        }
+
+        self.put_u4(END_OF_CENTRAL_DIRECTORY)?;
+        // this disk and the one the central directory starts on
+        self.put_u2(0)?;
+        self.put_u2(0)?;
+        // entries on this disk and in total
+        self.put_u2(short_count)?;
+        self.put_u2(short_count)?;
+        self.put_u4(short_size)?;
+        self.put_u4(short_start)?;
+        // comment length
+        self.put_u2(0)?;
+
+        self.writer.flush()?;
+
+        Ok(self.writer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn u2(bytes: &[u8], offset: usize) -> usize {
+        u16::from_le_bytes([bytes[offset], bytes[offset + 1]]) as usize
+    }
+
+    fn u4(bytes: &[u8], offset: usize) -> usize {
+        let mut value = [0u8; 4];
+        value.copy_from_slice(&bytes[offset..offset + 4]);
+
+        u32::from_le_bytes(value) as usize
+    }
+
+    #[test]
+    fn checksums_like_zip() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn writes_central_directory() {
+        let mut jar = JarWriter::new(Vec::new());
+        jar.add("META-INF/MANIFEST.MF", b"Manifest-Version: 1.0\r\n\r\n")
+            .unwrap();
+        jar.add(
+            "net/minecraft/client/Minecraft.class",
+            &[0xca, 0xfe, 0xba, 0xbe],
+        )
+        .unwrap();
+        let bytes = jar.finish().unwrap();
+
+        // the end of central directory record is the last 22 bytes without a comment
+        let end = bytes.len() - 22;
+        assert_eq!(u4(&bytes, end), END_OF_CENTRAL_DIRECTORY as usize);
+        assert_eq!(u2(&bytes, end + 10), 2);
+
+        let mut central = u4(&bytes, end + 16);
+        assert_eq!(central + u4(&bytes, end + 12), end);
+
+        let mut names = Vec::new();
+        for _ in 0..2 {
+            assert_eq!(u4(&bytes, central), CENTRAL_DIRECTORY_HEADER as usize);
+
+            let name_length = u2(&bytes, central + 28);
+            let name = &bytes[central + 46..central + 46 + name_length];
+
+            // the local header it points at has the same name, followed by the data
+            let local = u4(&bytes, central + 42);
+            assert_eq!(u4(&bytes, local), LOCAL_FILE_HEADER as usize);
+            assert_eq!(&bytes[local + 30..local + 30 + name_length], name);
+
+            let size = u4(&bytes, central + 24);
+            let data = &bytes[local + 30 + name_length..local + 30 + name_length + size];
+            assert_eq!(crc32(data) as usize, u4(&bytes, central + 16));
+
+            names.push(String::from_utf8(name.to_vec()).unwrap());
+            central += 46 + name_length;
+        }
+
+        assert_eq!(
+            names,
+            vec![
+                "META-INF/MANIFEST.MF",
+                "net/minecraft/client/Minecraft.class"
+            ]
+        );
+    }
+
+    #[test]
+    fn writes_zip64_past_65535_entries() {
+        let mut jar = JarWriter::new(Vec::new());
+        for index in 0..=u16::MAX as usize {
+            jar.add(&format!("{}.class", index), &[]).unwrap();
+        }
+        let bytes = jar.finish().unwrap();
+
+        let u8 = |offset: usize| {
+            let mut value = [0u8; 8];
+            value.copy_from_slice(&bytes[offset..offset + 8]);
+
+            u64::from_le_bytes(value) as usize
+        };
+
+        // the usual record gives up on the count, the locator right before it knows better
+        let end = bytes.len() - 22;
+        assert_eq!(u4(&bytes, end), END_OF_CENTRAL_DIRECTORY as usize);
+        assert_eq!(u2(&bytes, end + 10), 0xffff);
+
+        let locator = end - 20;
+        assert_eq!(
+            u4(&bytes, locator),
+            ZIP64_END_OF_CENTRAL_DIRECTORY_LOCATOR as usize
+        );
+
+        let zip64_end = u8(locator + 8);
+        assert_eq!(
+            u4(&bytes, zip64_end),
+            ZIP64_END_OF_CENTRAL_DIRECTORY as usize
+        );
+        assert_eq!(u8(zip64_end + 32), 0x10000);
+
+        let central = u8(zip64_end + 48);
+        assert_eq!(central + u8(zip64_end + 40), zip64_end);
+        assert_eq!(u4(&bytes, central), CENTRAL_DIRECTORY_HEADER as usize);
+    }
+}
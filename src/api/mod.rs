@@ -1,3 +1,7 @@
+pub mod dump;
+pub mod jar;
 pub mod processes;
 pub mod sdk;
+// scanning for the dictionary, only the Windows overlay still does that
+#[cfg(any(windows, test))]
 pub mod sig;
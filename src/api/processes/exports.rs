@@ -0,0 +1,358 @@
+//! Resolving exported symbols of a module mapped into a `MemorySource`, straight from the PE export
+//! directory of a DLL or the dynamic symbol table of a shared object
+
+use std::cmp::Ordering;
+
+use super::{try_read_exact, MemorySource, ModuleEntry};
+
+const DOS_MAGIC: u16 = 0x5a4d; // "MZ"
+const PE_MAGIC: u32 = 0x0000_4550; // "PE\0\0"
+const PE32_MAGIC: u16 = 0x010b;
+const PE32_PLUS_MAGIC: u16 = 0x020b;
+
+const ELF_MAGIC: u32 = 0x464c_457f; // "\x7fELF"
+const PT_LOAD: u32 = 1;
+const PT_DYNAMIC: u32 = 2;
+
+const DT_NULL: i64 = 0;
+const DT_HASH: i64 = 4;
+const DT_STRTAB: i64 = 5;
+const DT_SYMTAB: i64 = 6;
+const DT_GNU_HASH: i64 = 0x6fff_fef5;
+
+const ELF64_SYM_SIZE: usize = 24;
+
+/// Address of the export `name` of `module`, whether it's a PE image or an ELF shared object
+pub fn find_export(source: &dyn MemorySource, module: &ModuleEntry, name: &str) -> Option<usize> {
+    match try_read_exact::<u32>(source, module.base).ok()? {
+        ELF_MAGIC => find_elf_export(source, module.base, name),
+        magic if magic as u16 == DOS_MAGIC => find_pe_export(source, module.base, name),
+        _ => None,
+    }
+}
+
+/// Compare the NUL terminated string at `address` with `name`, like strcmp would
+fn compare_name(source: &dyn MemorySource, address: usize, name: &str) -> Option<Ordering> {
+    // one byte more than `name` tells us whether the remote string goes on
+    let mut buffer = vec![0u8; name.len() + 1];
+    source.read_bytes(address, &mut buffer).ok()?;
+
+    let remote = buffer
+        .iter()
+        .position(|&c| c == 0)
+        .map_or(&buffer[..], |end| &buffer[..end]);
+
+    Some(remote.cmp(name.as_bytes()))
+}
+
+fn find_pe_export(source: &dyn MemorySource, base: usize, name: &str) -> Option<usize> {
+    let read_u16 = |address: usize| try_read_exact::<u16>(source, address).ok();
+    let read_u32 = |address: usize| try_read_exact::<u32>(source, address).ok();
+
+    let nt_headers = base + read_u32(base + 0x3c)? as usize;
+    if read_u32(nt_headers)? != PE_MAGIC {
+        return None;
+    }
+
+    let optional_header = nt_headers + 0x18;
+    let export_directory = match read_u16(optional_header)? {
+        PE32_PLUS_MAGIC => optional_header + 0x70,
+        PE32_MAGIC => optional_header + 0x60,
+        _ => return None,
+    };
+
+    let directory_rva = read_u32(export_directory)? as usize;
+    let directory_size = read_u32(export_directory + 4)? as usize;
+    if directory_rva == 0 {
+        return None;
+    }
+
+    let directory = base + directory_rva;
+    let name_count = read_u32(directory + 0x18)? as usize;
+    let functions = base + read_u32(directory + 0x1c)? as usize;
+    let names = base + read_u32(directory + 0x20)? as usize;
+    let ordinals = base + read_u32(directory + 0x24)? as usize;
+
+    // the loader binary searches the name table too, so it's sorted
+    let (mut low, mut high) = (0usize, name_count);
+    while low < high {
+        let idx = low + (high - low) / 2;
+        let name_rva = read_u32(names + idx * 4)? as usize;
+
+        match compare_name(source, base + name_rva, name)? {
+            Ordering::Less => low = idx + 1,
+            Ordering::Greater => high = idx,
+            Ordering::Equal => {
+                let ordinal = read_u16(ordinals + idx * 2)? as usize;
+                let function_rva = read_u32(functions + ordinal * 4)? as usize;
+
+                // forwarded to another module, there's nothing here to point at
+                if function_rva >= directory_rva && function_rva < directory_rva + directory_size {
+                    return None;
+                }
+
+                return Some(base + function_rva);
+            }
+        }
+    }
+
+    None
+}
+
+/// Where the dynamic tables of a loaded shared object live
+struct DynamicTables {
+    bias: usize,
+    symbols: usize,
+    strings: usize,
+    hash: Option<usize>,
+    gnu_hash: Option<usize>,
+}
+
+impl DynamicTables {
+    fn read(source: &dyn MemorySource, base: usize) -> Option<Self> {
+        let program_headers = base + try_read_exact::<u64>(source, base + 0x20).ok()? as usize;
+        let header_size = try_read_exact::<u16>(source, base + 0x36).ok()? as usize;
+        let header_count = try_read_exact::<u16>(source, base + 0x38).ok()? as usize;
+
+        let mut first_load = None;
+        let mut dynamic = None;
+
+        for idx in 0..header_count {
+            let header = program_headers + idx * header_size;
+            let kind = try_read_exact::<u32>(source, header).ok()?;
+            let virtual_address = try_read_exact::<u64>(source, header + 0x10).ok()? as usize;
+
+            match kind {
+                PT_LOAD if first_load.is_none() => first_load = Some(virtual_address),
+                PT_DYNAMIC => dynamic = Some(virtual_address),
+                _ => {}
+            }
+        }
+
+        // the module starts at the page of the first segment
+        let bias = base.checked_sub(first_load? & !0xfff)?;
+        let mut entry = bias + dynamic?;
+
+        let mut tables = Self {
+            bias,
+            symbols: 0,
+            strings: 0,
+            hash: None,
+            gnu_hash: None,
+        };
+
+        loop {
+            let tag = try_read_exact::<i64>(source, entry).ok()?;
+            let value = try_read_exact::<u64>(source, entry + 8).ok()? as usize;
+
+            // the dynamic linker relocates these in place, a dump of an unloaded file doesn't
+            let pointer = if value < bias { bias + value } else { value };
+
+            match tag {
+                DT_NULL => break,
+                DT_HASH => tables.hash = Some(pointer),
+                DT_STRTAB => tables.strings = pointer,
+                DT_SYMTAB => tables.symbols = pointer,
+                DT_GNU_HASH => tables.gnu_hash = Some(pointer),
+                _ => {}
+            }
+
+            entry += 16;
+        }
+
+        if tables.symbols == 0 || tables.strings == 0 {
+            return None;
+        }
+
+        Some(tables)
+    }
+
+    /// Address of symbol `idx` if it's called `name` and defined in this object
+    fn matches(&self, source: &dyn MemorySource, idx: usize, name: &str) -> Option<usize> {
+        let symbol = self.symbols + idx * ELF64_SYM_SIZE;
+
+        let name_offset = try_read_exact::<u32>(source, symbol).ok()? as usize;
+        if compare_name(source, self.strings + name_offset, name)? != Ordering::Equal {
+            return None;
+        }
+
+        // SHN_UNDEF, an import rather than an export
+        if try_read_exact::<u16>(source, symbol + 6).ok()? == 0 {
+            return None;
+        }
+
+        Some(self.bias + try_read_exact::<u64>(source, symbol + 8).ok()? as usize)
+    }
+
+    fn find_gnu(&self, source: &dyn MemorySource, table: usize, name: &str) -> Option<usize> {
+        let read_u32 = |address: usize| try_read_exact::<u32>(source, address).ok();
+
+        let bucket_count = read_u32(table)? as usize;
+        let symbol_offset = read_u32(table + 4)? as usize;
+        let bloom_size = read_u32(table + 8)? as usize;
+
+        let buckets = table + 16 + bloom_size * 8;
+        let chains = buckets + bucket_count * 4;
+
+        let hash = name
+            .bytes()
+            .fold(5381u32, |h, c| h.wrapping_mul(33).wrapping_add(c as u32));
+
+        let mut idx = read_u32(buckets + (hash as usize % bucket_count.max(1)) * 4)? as usize;
+        if idx < symbol_offset {
+            return None;
+        }
+
+        loop {
+            let chain_hash = read_u32(chains + (idx - symbol_offset) * 4)?;
+
+            if chain_hash | 1 == hash | 1 {
+                if let Some(address) = self.matches(source, idx, name) {
+                    return Some(address);
+                }
+            }
+
+            // the lowest bit marks the end of the chain
+            if chain_hash & 1 != 0 {
+                return None;
+            }
+
+            idx += 1;
+        }
+    }
+
+    fn find_sysv(&self, source: &dyn MemorySource, table: usize, name: &str) -> Option<usize> {
+        let read_u32 = |address: usize| try_read_exact::<u32>(source, address).ok();
+
+        let bucket_count = read_u32(table)? as usize;
+        let chain_count = read_u32(table + 4)? as usize;
+
+        let hash = name.bytes().fold(0u32, |h, c| {
+            let h = (h << 4).wrapping_add(c as u32);
+            (h ^ ((h & 0xf000_0000) >> 24)) & 0x0fff_ffff
+        });
+
+        let mut idx = read_u32(table + 8 + (hash as usize % bucket_count.max(1)) * 4)? as usize;
+
+        // a corrupted chain could go round forever, it can't be longer than the table
+        for _ in 0..chain_count {
+            if idx == 0 {
+                break;
+            }
+
+            if let Some(address) = self.matches(source, idx, name) {
+                return Some(address);
+            }
+
+            idx = read_u32(table + 8 + bucket_count * 4 + idx * 4)? as usize;
+        }
+
+        None
+    }
+}
+
+fn find_elf_export(source: &dyn MemorySource, base: usize, name: &str) -> Option<usize> {
+    let tables = DynamicTables::read(source, base)?;
+
+    if let Some(table) = tables.gnu_hash {
+        return tables.find_gnu(source, table, name);
+    }
+
+    tables.find_sysv(source, tables.hash?, name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::api::sdk::testing::JvmImage;
+
+    const IMAGE_BASE: usize = 0x7ff8_0000_0000;
+
+    /// A jvm.dll with nothing but an export directory holding `exports` (name, rva)
+    fn write_pe(exports: &[(&str, u32)]) -> JvmImage {
+        let mut image = JvmImage::new(IMAGE_BASE, 0x2000);
+
+        image.write(IMAGE_BASE, &DOS_MAGIC);
+        image.write(IMAGE_BASE + 0x3c, &0x80u32);
+        image.write(IMAGE_BASE + 0x80, &PE_MAGIC);
+        image.write(IMAGE_BASE + 0x98, &PE32_PLUS_MAGIC);
+
+        let directory = 0x400u32;
+        image.write(IMAGE_BASE + 0x98 + 0x70, &directory);
+        image.write(IMAGE_BASE + 0x98 + 0x74, &0x400u32);
+
+        let functions = directory + 0x28;
+        let ordinals = functions + exports.len() as u32 * 4;
+        let names = ordinals + exports.len() as u32 * 2;
+        let mut strings = names + exports.len() as u32 * 4;
+
+        let at = |rva: u32| IMAGE_BASE + rva as usize;
+
+        image.write(at(directory + 0x14), &(exports.len() as u32));
+        image.write(at(directory + 0x18), &(exports.len() as u32));
+        image.write(at(directory + 0x1c), &functions);
+        image.write(at(directory + 0x20), &names);
+        image.write(at(directory + 0x24), &ordinals);
+
+        // ordinals in reverse so a mixed up name and function index shows
+        for (idx, (name, rva)) in exports.iter().enumerate() {
+            let ordinal = (exports.len() - 1 - idx) as u16;
+
+            image.write(at(functions + ordinal as u32 * 4), rva);
+            image.write(at(ordinals + idx as u32 * 2), &ordinal);
+            image.write(at(names + idx as u32 * 4), &strings);
+            image.write_bytes(at(strings), name.as_bytes());
+
+            strings += name.len() as u32 + 1;
+        }
+
+        image
+    }
+
+    #[test]
+    fn finds_pe_exports() {
+        let image = write_pe(&[
+            ("JVM_GetVersionInfo", 0x1010),
+            ("gHotSpotVMStructs", 0x1020),
+            ("gHotSpotVMTypes", 0x1030),
+            // forwarded, points into the export directory
+            ("gHotSpotVMTypesForwarded", 0x0500),
+        ]);
+
+        let jvm = ModuleEntry {
+            name: "jvm.dll".to_string(),
+            base: IMAGE_BASE,
+            size: 0x2000,
+        };
+
+        assert_eq!(
+            find_export(&image, &jvm, "gHotSpotVMStructs"),
+            Some(IMAGE_BASE + 0x1020)
+        );
+        assert_eq!(
+            find_export(&image, &jvm, "JVM_GetVersionInfo"),
+            Some(IMAGE_BASE + 0x1010)
+        );
+        assert_eq!(find_export(&image, &jvm, "gHotSpotVMType"), None);
+        assert_eq!(find_export(&image, &jvm, "gHotSpotVMTypesForwarded"), None);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn finds_elf_exports_of_own_libc() {
+        let handle = super::super::NativeHandle::new(std::process::id());
+
+        let libc_module = handle
+            .modules()
+            .into_iter()
+            .find(|m| m.name.starts_with("libc.so") || m.name.starts_with("libc-"))
+            .expect("not linked against libc");
+
+        assert_eq!(
+            find_export(&handle, &libc_module, "getpid"),
+            Some(libc::getpid as *const () as usize)
+        );
+        assert_eq!(find_export(&handle, &libc_module, "getpid_but_not_really"), None);
+    }
+}
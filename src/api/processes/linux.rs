@@ -0,0 +1,371 @@
+use std::{collections::HashMap, fs::File, os::unix::fs::FileExt, sync::Arc};
+
+use super::{MemorySource, ModuleEntry, ProcessEntry, ReadError, SourceId};
+
+/// Length the kernel truncates `/proc/<pid>/comm` to (TASK_COMM_LEN - 1)
+const COMM_LENGTH: usize = 15;
+
+/// Structure to handle native handles, on Linux a process is addressed by its pid
+#[derive(Default, Clone)]
+pub struct NativeHandle {
+    _pid: u32,
+
+    /// `/proc/<pid>/mem`, only used when `process_vm_readv` is unavailable to us
+    _mem: Option<Arc<File>>,
+
+    _id: SourceId,
+}
+
+/// Native implementation of memory allocation, this was made so that we can allocate big chunks of memory inside our own program  (outside of the program heap)
+/// this is backed by an anonymous mapping, the Linux equivalent of VirtualAlloc
+pub struct NativeAllocation {
+    /// Pointer to our base address
+    _memory: *mut u8,
+
+    /// Size of memory region
+    _size: usize,
+}
+
+impl NativeAllocation {
+    pub fn new(size: usize) -> Self {
+        let memory = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                size.max(1),
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        };
+
+        Self {
+            _memory: if memory == libc::MAP_FAILED {
+                std::ptr::null_mut()
+            } else {
+                memory as _
+            },
+            _size: size,
+        }
+    }
+
+    pub fn get(&self) -> *mut u8 {
+        self._memory
+    }
+
+    pub fn size(&self) -> usize {
+        self._size
+    }
+}
+
+impl NativeHandle {
+    pub fn new(pid: u32) -> Self {
+        Self {
+            _pid: pid,
+            _mem: File::open(format!("/proc/{}/mem", pid)).ok().map(Arc::new),
+            _id: SourceId::next(),
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn get(&self) -> u32 {
+        self._pid
+    }
+
+    fn read_process_vm(&self, address: usize, buffer: &mut [u8]) -> Result<(), ReadError> {
+        let local = libc::iovec {
+            iov_base: buffer.as_mut_ptr() as _,
+            iov_len: buffer.len(),
+        };
+        let remote = libc::iovec {
+            iov_base: address as _,
+            iov_len: buffer.len(),
+        };
+
+        let read = unsafe { libc::process_vm_readv(self._pid as _, &local, 1, &remote, 1, 0) };
+
+        if read < 0 {
+            return Err(read_error(address, std::io::Error::last_os_error()));
+        }
+
+        // process_vm_readv stops at the first page it can't read
+        if (read as usize) < buffer.len() {
+            return Err(ReadError::PartialRead {
+                address,
+                requested: buffer.len(),
+                read: read as usize,
+            });
+        }
+
+        Ok(())
+    }
+
+    fn read_proc_mem(&self, address: usize, buffer: &mut [u8]) -> Result<(), ReadError> {
+        let mem = match &self._mem {
+            Some(mem) => mem,
+            None => return Err(ReadError::PermissionDenied(address)),
+        };
+
+        let mut read = 0usize;
+
+        while read < buffer.len() {
+            match mem.read_at(&mut buffer[read..], (address + read) as u64) {
+                Ok(0) => break,
+                Ok(count) => read += count,
+                Err(error) if read == 0 => return Err(read_error(address, error)),
+                Err(_) => break,
+            }
+        }
+
+        if read < buffer.len() {
+            // a zero length read at the very start means there's no address space left to read from
+            if read == 0 && !std::path::Path::new(&proc_path(self._pid)).exists() {
+                return Err(ReadError::ProcessExited);
+            }
+
+            return Err(ReadError::PartialRead {
+                address,
+                requested: buffer.len(),
+                read,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// Translate an errno from process_vm_readv or /proc/<pid>/mem into a `ReadError`
+fn read_error(address: usize, error: std::io::Error) -> ReadError {
+    match error.raw_os_error() {
+        // seccomp filters tend to answer process_vm_readv with ENOSYS
+        Some(libc::EPERM) | Some(libc::EACCES) | Some(libc::ENOSYS) => {
+            ReadError::PermissionDenied(address)
+        }
+        Some(libc::ESRCH) => ReadError::ProcessExited,
+        _ => ReadError::UnmappedAddress(address),
+    }
+}
+
+impl Drop for NativeAllocation {
+    fn drop(&mut self) {
+        if !self._memory.is_null() {
+            unsafe {
+                libc::munmap(self._memory as _, self._size.max(1));
+            }
+        }
+    }
+}
+
+impl MemorySource for NativeHandle {
+    fn read_bytes(&self, address: usize, buffer: &mut [u8]) -> Result<(), ReadError> {
+        match self.read_process_vm(address, buffer) {
+            // seccomp profiles and old kernels can refuse process_vm_readv, /proc/<pid>/mem
+            // goes through a different path so give that a shot
+            Err(ReadError::PermissionDenied(_)) => self.read_proc_mem(address, buffer),
+            result => result,
+        }
+    }
+
+    fn id(&self) -> SourceId {
+        self._id
+    }
+
+    fn modules(&self) -> Vec<ModuleEntry> {
+        iterate_modules(self._pid)
+    }
+}
+
+/// `/proc/<pid>` for a pid, where pid 0 means our own process like it does for Toolhelp on Windows
+fn proc_path(process_id: u32) -> String {
+    if process_id == 0 {
+        String::from("/proc/self")
+    } else {
+        format!("/proc/{}", process_id)
+    }
+}
+
+fn file_name(path: &str) -> &str {
+    path.rsplit('/').next().unwrap_or(path)
+}
+
+pub fn iterate_modules(process_id: u32) -> Vec<ModuleEntry> {
+    let mut modules: Vec<ModuleEntry> = vec![];
+    let mut indices: HashMap<String, usize> = HashMap::new();
+
+    let maps = match std::fs::read_to_string(format!("{}/maps", proc_path(process_id))) {
+        Ok(maps) => maps,
+        Err(error) => {
+            println!("Error code: {}", error);
+            return modules;
+        }
+    };
+
+    // every line looks like: 7f1c2d400000-7f1c2d5c6000 r-xp 00000000 08:01 1234 /usr/lib/libjvm.so
+    for line in maps.lines() {
+        let mut columns = line.splitn(6, ' ');
+
+        let range = columns.next().unwrap_or_default();
+        let path = columns.nth(4).unwrap_or_default().trim_start();
+
+        // anonymous mappings and pseudo files like [heap] and [stack] aren't modules
+        if !path.starts_with('/') {
+            continue;
+        }
+
+        let (start, end) = match range.split_once('-') {
+            Some((start, end)) => (
+                usize::from_str_radix(start, 16).unwrap_or_default(),
+                usize::from_str_radix(end, 16).unwrap_or_default(),
+            ),
+            None => continue,
+        };
+
+        // a module is mapped as several segments, stretch a single entry over all of them
+        if let Some(&idx) = indices.get(path) {
+            let module = &mut modules[idx];
+            let module_end = (module.base + module.size).max(end);
+
+            module.base = module.base.min(start);
+            module.size = module_end - module.base;
+        } else {
+            indices.insert(path.to_string(), modules.len());
+            modules.push(ModuleEntry {
+                name: file_name(path).to_string(),
+                base: start,
+                size: end - start,
+            });
+        }
+    }
+
+    modules
+}
+
+pub fn iterate_processes() -> Vec<ProcessEntry> {
+    let mut processes = vec![];
+
+    let entries = match std::fs::read_dir("/proc") {
+        Ok(entries) => entries,
+        Err(_) => return processes,
+    };
+
+    for entry in entries.flatten() {
+        let pid = match entry.file_name().to_str().and_then(|p| p.parse::<u32>().ok()) {
+            Some(pid) => pid,
+            None => continue,
+        };
+
+        // the process may well be gone by now, just skip it
+        let comm = match std::fs::read_to_string(format!("/proc/{}/comm", pid)) {
+            Ok(comm) => comm.trim_end_matches('\n').to_string(),
+            Err(_) => continue,
+        };
+
+        // comm gets truncated, if that happened recover the full name from argv[0]
+        let name = if comm.len() >= COMM_LENGTH {
+            std::fs::read(format!("/proc/{}/cmdline", pid))
+                .ok()
+                .map(|cmdline| {
+                    let program = cmdline.split(|&c| c == 0).next().unwrap_or_default();
+                    let program = String::from_utf8_lossy(program).to_string();
+
+                    file_name(&program).to_string()
+                })
+                .filter(|program| program.starts_with(&comm))
+                .unwrap_or(comm)
+        } else {
+            comm
+        };
+
+        processes.push(ProcessEntry { name, pid });
+    }
+
+    processes
+}
+
+pub fn open_process(entry: &ProcessEntry) -> Option<NativeHandle> {
+    if std::path::Path::new(&proc_path(entry.pid)).exists() {
+        return Some(NativeHandle::new(entry.pid));
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spawn_child() -> std::process::Child {
+        let child = std::process::Command::new("sleep")
+            .arg("10")
+            .spawn()
+            .expect("Couldn't spawn child process");
+
+        // spawn can hand the child back before its exec has settled, wait until /proc catches up
+        for _ in 0..100 {
+            if !iterate_modules(child.id()).is_empty() {
+                break;
+            }
+
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+
+        child
+    }
+
+    #[test]
+    fn finds_spawned_process() {
+        let mut child = spawn_child();
+
+        let process = iterate_processes().into_iter().find(|p| p.pid == child.id());
+
+        child.kill().ok();
+        child.wait().ok();
+
+        assert_eq!(process.expect("Child process not listed").name, "sleep");
+    }
+
+    #[test]
+    fn lists_modules_of_own_process() {
+        let executable = std::env::current_exe().unwrap();
+        let name = executable.file_name().unwrap().to_str().unwrap();
+
+        let module = iterate_modules(0)
+            .into_iter()
+            .find(|m| m.name.eq(name))
+            .expect("Own executable not in module list");
+
+        assert!(module.base != 0 && module.size != 0);
+    }
+
+    #[test]
+    fn reads_child_memory() {
+        let mut child = spawn_child();
+
+        let handle = open_process(&ProcessEntry {
+            name: String::from("sleep"),
+            pid: child.id(),
+        })
+        .expect("Couldn't open child process");
+
+        // the main executable starts with its ELF header
+        let executable = std::fs::read_link(format!("/proc/{}/exe", child.id())).unwrap();
+        let module = iterate_modules(child.id())
+            .into_iter()
+            .find(|m| m.name.eq(file_name(executable.to_str().unwrap())))
+            .expect("Child executable not in module list");
+
+        let mut magic = [0u8; 4];
+        let result = handle.read_bytes(module.base, &mut magic);
+        let unmapped = handle.read_bytes(0, &mut [0u8; 4]);
+
+        child.kill().ok();
+        child.wait().ok();
+
+        let exited = handle.read_bytes(module.base, &mut [0u8; 4]);
+
+        result.expect("Couldn't read child memory");
+        assert_eq!(&magic, b"\x7fELF");
+        assert!(matches!(unmapped, Err(ReadError::UnmappedAddress(0))));
+        assert!(matches!(exited, Err(ReadError::ProcessExited)));
+    }
+}
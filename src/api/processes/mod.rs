@@ -1,32 +1,17 @@
-use std::vec;
-
-use winapi::um::{
-    errhandlingapi::GetLastError,
-    handleapi::CloseHandle,
-    memoryapi::{ReadProcessMemory, VirtualAlloc, VirtualFree},
-    processthreadsapi::OpenProcess,
-    tlhelp32::{
-        CreateToolhelp32Snapshot, Module32First, Module32Next, Process32First, Process32Next,
-        MODULEENTRY32, PROCESSENTRY32, TH32CS_SNAPMODULE, TH32CS_SNAPPROCESS,
-    },
-    winnt::{HANDLE, MEM_COMMIT, MEM_RELEASE, PAGE_READWRITE, PROCESS_ALL_ACCESS},
-};
+use std::sync::atomic::{AtomicUsize, Ordering};
 
-/// Structure to handle native handles
-#[derive(Default,Clone)]
-pub struct NativeHandle {
-    _handle: usize,
-}
+mod exports;
+#[cfg(target_os = "linux")]
+mod linux;
+#[cfg(windows)]
+mod windows;
 
-/// Native implementation of memory allocation, this was made so that we can allocate big chunks of memory inside our own program  (outside of the program heap)
-/// this was causing issues with the Rust compiler not being able to allocate enough memory
-pub struct NativeAllocation {
-    /// Pointer to our base address
-    _memory: *mut u8,
+pub use self::exports::find_export;
 
-    /// Size of memory region
-    _size: usize,
-}
+#[cfg(target_os = "linux")]
+pub use self::linux::*;
+#[cfg(windows)]
+pub use self::windows::*;
 
 #[derive(Debug, Default)]
 pub struct ProcessEntry {
@@ -34,220 +19,166 @@ pub struct ProcessEntry {
     pub pid: u32,
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 pub struct ModuleEntry {
     pub name: String,
     pub base: usize,
     pub size: usize,
 }
 
-impl NativeAllocation {
-    pub fn new(size: usize) -> Self {
-        Self {
-            _memory: unsafe { VirtualAlloc(std::ptr::null_mut(), size, MEM_COMMIT, PAGE_READWRITE) }
-                as _,
-            _size: size,
-        }
-    }
+/// Why a read from a `MemorySource` failed
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReadError {
+    /// Nothing is mapped at the address
+    UnmappedAddress(usize),
 
-    pub fn get(&self) -> *mut u8 {
-        self._memory
-    }
+    /// Only `read` out of the `requested` bytes at `address` could be read
+    PartialRead {
+        address: usize,
+        requested: usize,
+        read: usize,
+    },
 
-    pub fn size(&self) -> usize {
-        self._size
-    }
-}
+    /// The address is mapped but we aren't allowed to read it
+    PermissionDenied(usize),
 
-impl NativeHandle {
-    pub fn new(handle: usize) -> Self {
-        Self { _handle: handle }
-    }
+    /// The process we were reading from is gone
+    ProcessExited,
+}
 
-    pub fn get(&self) -> HANDLE {
-        self._handle as _
+impl std::fmt::Display for ReadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReadError::UnmappedAddress(address) => {
+                write!(f, "nothing mapped at {:p}", *address as *const u8)
+            }
+            ReadError::PartialRead {
+                address,
+                requested,
+                read,
+            } => write!(
+                f,
+                "only read {} of {} bytes at {:p}",
+                read, requested, *address as *const u8
+            ),
+            ReadError::PermissionDenied(address) => {
+                write!(f, "not allowed to read {:p}", *address as *const u8)
+            }
+            ReadError::ProcessExited => write!(f, "process exited"),
+        }
     }
 }
 
-impl Drop for NativeAllocation {
-    fn drop(&mut self) {
-        unsafe {
-            VirtualFree(self._memory as _, self._size, MEM_RELEASE);
-        }
+impl std::error::Error for ReadError {}
+
+/// Tells a `MemorySource` apart from every other one opened by this process. Whatever the sdk
+/// caches about a target is kept by source, so it never carries over to the next process or
+/// dump (which can have a class at the very same address).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SourceId(usize);
+
+impl SourceId {
+    /// An id no source had before
+    pub fn next() -> Self {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+        SourceId(NEXT.fetch_add(1, Ordering::Relaxed))
     }
 }
 
-impl Drop for NativeHandle {
-    fn drop(&mut self) {
-        unsafe {
-            CloseHandle(self.get());
-        }
+impl Default for SourceId {
+    fn default() -> Self {
+        Self::next()
     }
 }
 
-pub fn iterate_modules(process_id: u32) -> Vec<ModuleEntry> {
-    let mut modules = vec![];
-
-    unsafe {
-        let snapshot: HANDLE = CreateToolhelp32Snapshot(TH32CS_SNAPMODULE, process_id);
-
-        let module_entry: *mut MODULEENTRY32 =
-            [0 as u8; std::mem::size_of::<MODULEENTRY32>()].as_mut_ptr() as *mut _;
-        (*module_entry).dwSize = std::mem::size_of::<MODULEENTRY32>() as u32;
-
-        if Module32First(snapshot, module_entry) == 1 {
-            loop {
-                modules.push(ModuleEntry {
-                    name: String::from_utf8(
-                        module_entry
-                            .read()
-                            .szModule
-                            .iter()
-                            .map(|i| *i as u8)
-                            .take_while(|&i| i as char != char::from(0))
-                            .collect(),
-                    )
-                    .unwrap_or_default(),
-                    base: module_entry.read().modBaseAddr as usize,
-                    size: module_entry.read().modBaseSize as usize,
-                });
-
-                if Module32Next(snapshot, module_entry) == 0 {
-                    break;
-                }
-            }
-        } else {
-            println!("Error code: {}", GetLastError());
-        }
+/// Anything we can read (remote) memory from, a live process, a dump, or a plain buffer
+pub trait MemorySource {
+    /// Read `buffer.len()` bytes starting at `address` into `buffer`
+    fn read_bytes(&self, address: usize, buffer: &mut [u8]) -> Result<(), ReadError>;
 
-        CloseHandle(snapshot);
-    }
+    /// Which source this is, copies of a handle to the same process share it
+    fn id(&self) -> SourceId;
 
-    modules
-}
-
-pub fn iterate_processes() -> Vec<ProcessEntry> {
-    let mut processes = vec![];
-
-    unsafe {
-        let process_entry: *mut PROCESSENTRY32 =
-            [0 as u8; std::mem::size_of::<PROCESSENTRY32>()].as_mut_ptr() as *mut _;
-        (*process_entry).dwSize = std::mem::size_of::<PROCESSENTRY32>() as u32;
-
-        let snapshot = CreateToolhelp32Snapshot(TH32CS_SNAPPROCESS, 0);
-
-        if Process32First(snapshot, process_entry) == 1 {
-            loop {
-                processes.push(ProcessEntry {
-                    name: String::from_utf8(
-                        process_entry
-                            .read()
-                            .szExeFile
-                            .iter()
-                            .map(|i| *i as u8)
-                            .take_while(|&i| i as char != char::from(0))
-                            .collect(),
-                    )
-                    .unwrap_or_default(),
-                    pid: process_entry.read().th32ProcessID,
-                });
-
-                if Process32Next(snapshot, process_entry) == 0 {
-                    break;
-                }
-            }
-        }
+    /// Modules mapped into the address space, empty if the source has no notion of them
+    fn modules(&self) -> Vec<ModuleEntry> {
+        Vec::new()
+    }
 
-        CloseHandle(snapshot);
+    fn find_module(&self, name: &str) -> Option<ModuleEntry> {
+        self.modules().into_iter().find(|m| m.name.eq(name))
     }
+}
 
-    processes
+/// View any value as its raw bytes so a `MemorySource` can fill it in
+unsafe fn as_bytes_mut<T>(value: &mut T) -> &mut [u8] {
+    std::slice::from_raw_parts_mut(value as *mut T as *mut u8, std::mem::size_of::<T>())
 }
 
-pub fn read<T>(handle: &NativeHandle, address: usize, result: &mut T) {
-    unsafe {
-        ReadProcessMemory(
-            handle.get(),
-            address as _,
-            result as *mut _ as _,
-            std::mem::size_of::<T>(),
-            std::ptr::null_mut(),
-        );
-    }
+pub fn try_read<T>(
+    source: &dyn MemorySource,
+    address: usize,
+    result: &mut T,
+) -> Result<(), ReadError> {
+    source.read_bytes(address, unsafe { as_bytes_mut(result) })
 }
 
-pub fn read_exact<T>(handle: &NativeHandle, address: usize) -> T {
+pub fn try_read_exact<T>(source: &dyn MemorySource, address: usize) -> Result<T, ReadError> {
     let mut buffer: T = unsafe { core::mem::zeroed() };
 
-    unsafe {
-        ReadProcessMemory(
-            handle.get(),
-            address as _,
-            &mut buffer as *mut _ as _,
-             std::mem::size_of::<T>(),
-            std::ptr::null_mut(),
-        );
-    }
+    try_read(source, address, &mut buffer)?;
 
-    buffer
+    Ok(buffer)
 }
 
-pub fn read_class<T>(handle: &NativeHandle, address: usize) -> T {
-    //let buffer: *mut T = vec![0 as i8; std::mem::size_of::<T>()].as_mut_ptr() as *mut _;
-    //let buffer = NativeAllocation::new(std::mem::size_of::<T>());
-    let mut buffer: T = unsafe { core::mem::zeroed() };
+pub fn try_read_class<T>(source: &dyn MemorySource, address: usize) -> Result<T, ReadError> {
+    try_read_exact::<T>(source, address)
+}
 
-    unsafe {
-        ReadProcessMemory(
-            handle.get(),
-            address as _,
-            (&mut buffer as *mut T) as _,
-            std::mem::size_of::<T>(),
-            std::ptr::null_mut(),
-        );
-    }
+pub fn try_read_class_original<T>(
+    source: &dyn MemorySource,
+    address: usize,
+) -> Result<NativeAllocation, ReadError> {
+    let buffer = NativeAllocation::new(std::mem::size_of::<T>());
 
-    buffer
+    source.read_bytes(address, unsafe {
+        std::slice::from_raw_parts_mut(buffer.get(), buffer.size())
+    })?;
+
+    Ok(buffer)
 }
 
-pub fn read_class_original<T>(handle: &NativeHandle, address: usize) -> NativeAllocation {
-    //let buffer: *mut T = vec![0 as i8; std::mem::size_of::<T>()].as_mut_ptr() as *mut _;
-    let buffer = NativeAllocation::new(std::mem::size_of::<T>());
-    // let mut buffer: T = {
-    //     unsafe { core::mem::zeroed() }
-    // };
-
-    unsafe {
-        ReadProcessMemory(
-            handle.get(),
-            address as _,
-            buffer.get() as _,
-            buffer.size(),
-            std::ptr::null_mut(),
-        );
-    }
+/// Like `try_read`, but a failed read leaves `result` as it was
+#[allow(dead_code)]
+pub fn read<T>(source: &dyn MemorySource, address: usize, result: &mut T) {
+    let _ = try_read(source, address, result);
+}
+
+/// Like `try_read_exact`, but a failed read gives back a zeroed `T`
+pub fn read_exact<T>(source: &dyn MemorySource, address: usize) -> T {
+    try_read_exact::<T>(source, address).unwrap_or_else(|_| unsafe { core::mem::zeroed() })
+}
 
-    buffer
+/// Like `try_read_class`, but a failed read gives back a zeroed `T`
+#[allow(dead_code)]
+pub fn read_class<T>(source: &dyn MemorySource, address: usize) -> T {
+    try_read_class::<T>(source, address).unwrap_or_else(|_| unsafe { core::mem::zeroed() })
+}
+
+/// Like `try_read_class_original`, but a failed read gives back a zeroed allocation
+#[allow(dead_code)]
+pub fn read_class_original<T>(source: &dyn MemorySource, address: usize) -> NativeAllocation {
+    try_read_class_original::<T>(source, address)
+        .unwrap_or_else(|_| NativeAllocation::new(std::mem::size_of::<T>()))
 }
 
 pub fn find_process(name: &str) -> Option<ProcessEntry> {
     iterate_processes().into_iter().find(|p| p.name.eq(name))
 }
 
+#[allow(dead_code)]
 pub fn find_module(name: &str, process: Option<ProcessEntry>) -> Option<ModuleEntry> {
     iterate_modules(process.unwrap_or(ProcessEntry::default()).pid)
         .into_iter()
         .find(|m| m.name.eq(name))
 }
-
-pub fn open_process(entry: &ProcessEntry) -> Option<NativeHandle> {
-    unsafe {
-        let handle = OpenProcess(PROCESS_ALL_ACCESS, 0, entry.pid);
-
-        if handle as usize != 0x0 {
-            return Some(NativeHandle::new(handle as usize));
-        }
-    }
-
-    None
-}
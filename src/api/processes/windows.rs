@@ -0,0 +1,219 @@
+use winapi::shared::{
+    minwindef::DWORD,
+    winerror::{ERROR_ACCESS_DENIED, ERROR_INVALID_HANDLE, ERROR_NOACCESS, ERROR_PARTIAL_COPY},
+};
+use winapi::um::{
+    errhandlingapi::GetLastError,
+    handleapi::CloseHandle,
+    memoryapi::{ReadProcessMemory, VirtualAlloc, VirtualFree},
+    minwinbase::STILL_ACTIVE,
+    processthreadsapi::{GetExitCodeProcess, GetProcessId, OpenProcess},
+    tlhelp32::{
+        CreateToolhelp32Snapshot, Module32First, Module32Next, Process32First, Process32Next,
+        MODULEENTRY32, PROCESSENTRY32, TH32CS_SNAPMODULE, TH32CS_SNAPPROCESS,
+    },
+    winnt::{HANDLE, MEM_COMMIT, MEM_RELEASE, PAGE_READWRITE, PROCESS_ALL_ACCESS},
+};
+
+use super::{MemorySource, ModuleEntry, ProcessEntry, ReadError, SourceId};
+
+/// Structure to handle native handles
+#[derive(Default,Clone)]
+pub struct NativeHandle {
+    _handle: usize,
+    _id: SourceId,
+}
+
+/// Native implementation of memory allocation, this was made so that we can allocate big chunks of memory inside our own program  (outside of the program heap)
+/// this was causing issues with the Rust compiler not being able to allocate enough memory
+pub struct NativeAllocation {
+    /// Pointer to our base address
+    _memory: *mut u8,
+
+    /// Size of memory region
+    _size: usize,
+}
+
+impl NativeAllocation {
+    pub fn new(size: usize) -> Self {
+        Self {
+            _memory: unsafe { VirtualAlloc(std::ptr::null_mut(), size, MEM_COMMIT, PAGE_READWRITE) }
+                as _,
+            _size: size,
+        }
+    }
+
+    pub fn get(&self) -> *mut u8 {
+        self._memory
+    }
+
+    pub fn size(&self) -> usize {
+        self._size
+    }
+}
+
+impl NativeHandle {
+    pub fn new(handle: usize) -> Self {
+        Self {
+            _handle: handle,
+            _id: SourceId::next(),
+        }
+    }
+
+    pub fn get(&self) -> HANDLE {
+        self._handle as _
+    }
+}
+
+impl Drop for NativeAllocation {
+    fn drop(&mut self) {
+        unsafe {
+            VirtualFree(self._memory as _, self._size, MEM_RELEASE);
+        }
+    }
+}
+
+impl Drop for NativeHandle {
+    fn drop(&mut self) {
+        unsafe {
+            CloseHandle(self.get());
+        }
+    }
+}
+
+pub fn iterate_modules(process_id: u32) -> Vec<ModuleEntry> {
+    let mut modules = vec![];
+
+    unsafe {
+        let snapshot: HANDLE = CreateToolhelp32Snapshot(TH32CS_SNAPMODULE, process_id);
+
+        let module_entry: *mut MODULEENTRY32 =
+            [0 as u8; std::mem::size_of::<MODULEENTRY32>()].as_mut_ptr() as *mut _;
+        (*module_entry).dwSize = std::mem::size_of::<MODULEENTRY32>() as u32;
+
+        if Module32First(snapshot, module_entry) == 1 {
+            loop {
+                modules.push(ModuleEntry {
+                    name: String::from_utf8(
+                        module_entry
+                            .read()
+                            .szModule
+                            .iter()
+                            .map(|i| *i as u8)
+                            .take_while(|&i| i as char != char::from(0))
+                            .collect(),
+                    )
+                    .unwrap_or_default(),
+                    base: module_entry.read().modBaseAddr as usize,
+                    size: module_entry.read().modBaseSize as usize,
+                });
+
+                if Module32Next(snapshot, module_entry) == 0 {
+                    break;
+                }
+            }
+        } else {
+            println!("Error code: {}", GetLastError());
+        }
+
+        CloseHandle(snapshot);
+    }
+
+    modules
+}
+
+pub fn iterate_processes() -> Vec<ProcessEntry> {
+    let mut processes = vec![];
+
+    unsafe {
+        let process_entry: *mut PROCESSENTRY32 =
+            [0 as u8; std::mem::size_of::<PROCESSENTRY32>()].as_mut_ptr() as *mut _;
+        (*process_entry).dwSize = std::mem::size_of::<PROCESSENTRY32>() as u32;
+
+        let snapshot = CreateToolhelp32Snapshot(TH32CS_SNAPPROCESS, 0);
+
+        if Process32First(snapshot, process_entry) == 1 {
+            loop {
+                processes.push(ProcessEntry {
+                    name: String::from_utf8(
+                        process_entry
+                            .read()
+                            .szExeFile
+                            .iter()
+                            .map(|i| *i as u8)
+                            .take_while(|&i| i as char != char::from(0))
+                            .collect(),
+                    )
+                    .unwrap_or_default(),
+                    pid: process_entry.read().th32ProcessID,
+                });
+
+                if Process32Next(snapshot, process_entry) == 0 {
+                    break;
+                }
+            }
+        }
+
+        CloseHandle(snapshot);
+    }
+
+    processes
+}
+
+impl MemorySource for NativeHandle {
+    fn read_bytes(&self, address: usize, buffer: &mut [u8]) -> Result<(), ReadError> {
+        let mut read: usize = 0;
+
+        unsafe {
+            if ReadProcessMemory(
+                self.get(),
+                address as _,
+                buffer.as_mut_ptr() as _,
+                buffer.len(),
+                &mut read,
+            ) != 0
+            {
+                return Ok(());
+            }
+
+            let error = GetLastError();
+            let mut exit_code: DWORD = 0;
+
+            // a dead process still has a valid handle, only the exit code tells us it's gone
+            if GetExitCodeProcess(self.get(), &mut exit_code) != 0 && exit_code != STILL_ACTIVE {
+                return Err(ReadError::ProcessExited);
+            }
+
+            Err(match error {
+                ERROR_PARTIAL_COPY if read != 0 => ReadError::PartialRead {
+                    address,
+                    requested: buffer.len(),
+                    read,
+                },
+                ERROR_ACCESS_DENIED | ERROR_NOACCESS => ReadError::PermissionDenied(address),
+                ERROR_INVALID_HANDLE => ReadError::ProcessExited,
+                _ => ReadError::UnmappedAddress(address),
+            })
+        }
+    }
+
+    fn id(&self) -> SourceId {
+        self._id
+    }
+
+    fn modules(&self) -> Vec<ModuleEntry> {
+        iterate_modules(unsafe { GetProcessId(self.get()) })
+    }
+}
+
+pub fn open_process(entry: &ProcessEntry) -> Option<NativeHandle> {
+    unsafe {
+        let handle = OpenProcess(PROCESS_ALL_ACCESS, 0, entry.pid);
+
+        if handle as usize != 0x0 {
+            return Some(NativeHandle::new(handle as usize));
+        }
+    }
+
+    None
+}
@@ -1,15 +1,9 @@
-use std::sync::Mutex;
-
-use crate::api::{
-    processes::{self, NativeHandle},
-    sdk::{java, FromNative},
-};
+use crate::api::processes::{MemorySource, ReadError};
 
 use super::{
+    buffer::JavaBuffer,
     entity::{Vec2, Vec3, Vec4},
-    java::JavaBuffer,
-    minecraft::find_class,
-    JClass,
+    remote::{RemoteError, RemoteField},
 };
 
 pub fn multiply(vec: Vec4, mat: &Vec<f32>) -> Vec4 {
@@ -33,6 +27,7 @@ pub fn multiply(vec: Vec4, mat: &Vec<f32>) -> Vec4 {
     }
 }
 
+#[cfg_attr(not(windows), allow(dead_code))]
 pub fn world_to_screen(
     point: Vec3,
     out: &mut Vec2,
@@ -69,108 +64,48 @@ pub fn world_to_screen(
     true
 }
 
-pub struct RenderInfo {
-    activerenderinfo: JClass,
-    rendermanager: JClass,
-}
-
-lazy_static::lazy_static! {
-    static ref VIEWPORT_OFFSET: Mutex<usize> = Mutex::new(0usize);
-    static ref MODELVIEW_OFFSET: Mutex<usize> = Mutex::new(0usize);
-    static ref PROJECTION_OFFSET: Mutex<usize> = Mutex::new(0usize);
-    static ref RENDERPOS_X_OFFSET: Mutex<usize> = Mutex::new(0usize);
-}
-
-impl RenderInfo {
-    pub fn new() -> Self {
-        Self {
-            activerenderinfo: find_class("baj").clone(),
-            rendermanager: find_class("bnn").clone(),
-        }
-    }
+/// ActiveRenderInfo's viewport, modelview and projection, which LWJGL fills every frame
+static VIEWPORT: RemoteField = RemoteField::new("baj", "i", "Ljava/nio/IntBuffer;");
+static MODELVIEW: RemoteField = RemoteField::new("baj", "j", "Ljava/nio/FloatBuffer;");
+static PROJECTION: RemoteField = RemoteField::new("baj", "k", "Ljava/nio/FloatBuffer;");
 
-    pub fn get_viewport(&self, handle: &NativeHandle) -> JavaBuffer<i32> {
-        if *VIEWPORT_OFFSET.lock().unwrap() == 0usize {
-            *VIEWPORT_OFFSET.lock().unwrap() = self
-                .activerenderinfo
-                .find_field_entry(handle, "i", "Ljava/nio/IntBuffer;")
-                .expect("Couldn't find viewport field entry")
-                ._field_info
-                .offset() as usize;
-        }
+/// RenderManager's renderPosX, renderPosY and renderPosZ are declared one after the other
+static RENDER_POSITION: RemoteField = RemoteField::new("bnn", "b", "D");
 
-        let mut viewport_pointer: u32 = 0;
-        processes::read(
-            &handle,
-            self.activerenderinfo.static_fields as usize + *VIEWPORT_OFFSET.lock().unwrap(),
-            &mut viewport_pointer,
-        );
+/// The static render state of the game, read from ActiveRenderInfo and RenderManager
+#[cfg_attr(not(windows), allow(dead_code))]
+pub struct RenderInfo;
 
-        java::JavaBuffer::from_native(&handle, viewport_pointer as *mut java::JavaBuffer<i32>)
+#[cfg_attr(not(windows), allow(dead_code))]
+impl RenderInfo {
+    pub fn get_viewport(
+        &self,
+        source: &dyn MemorySource,
+    ) -> Result<JavaBuffer<i32>, RemoteError> {
+        VIEWPORT
+            .read_static::<Option<JavaBuffer<i32>>>(source)?
+            .ok_or(RemoteError::Read(ReadError::UnmappedAddress(0)))
     }
 
-    pub fn get_modelview(&self, handle: &NativeHandle) -> JavaBuffer<f32> {
-        if *MODELVIEW_OFFSET.lock().unwrap() == 0usize {
-            *MODELVIEW_OFFSET.lock().unwrap() = self
-                .activerenderinfo
-                .find_field_entry(handle, "j", "Ljava/nio/FloatBuffer;")
-                .expect("Couldn't find modelview field entry")
-                ._field_info
-                .offset() as usize;
-        }
-
-        let mut modelview_pointer: u32 = 0;
-        processes::read(
-            &handle,
-            self.activerenderinfo.static_fields as usize + *MODELVIEW_OFFSET.lock().unwrap(),
-            &mut modelview_pointer,
-        );
-
-        java::JavaBuffer::from_native(&handle, modelview_pointer as *mut java::JavaBuffer<f32>)
+    pub fn get_modelview(
+        &self,
+        source: &dyn MemorySource,
+    ) -> Result<JavaBuffer<f32>, RemoteError> {
+        MODELVIEW
+            .read_static::<Option<JavaBuffer<f32>>>(source)?
+            .ok_or(RemoteError::Read(ReadError::UnmappedAddress(0)))
     }
 
-    pub fn get_projection(&self, handle: &NativeHandle) -> JavaBuffer<f32> {
-        if *PROJECTION_OFFSET.lock().unwrap() == 0usize {
-            *PROJECTION_OFFSET.lock().unwrap() = self
-                .activerenderinfo
-                .find_field_entry(handle, "k", "Ljava/nio/FloatBuffer;")
-                .expect("Couldn't find modelview field entry")
-                ._field_info
-                .offset() as usize;
-        }
-
-        let mut projection_pointer: u32 = 0;
-        processes::read(
-            handle,
-            self.activerenderinfo.static_fields as usize + *PROJECTION_OFFSET.lock().unwrap(),
-            &mut projection_pointer,
-        );
-
-        java::JavaBuffer::from_native(&handle, projection_pointer as *mut java::JavaBuffer<f32>)
+    pub fn get_projection(
+        &self,
+        source: &dyn MemorySource,
+    ) -> Result<JavaBuffer<f32>, RemoteError> {
+        PROJECTION
+            .read_static::<Option<JavaBuffer<f32>>>(source)?
+            .ok_or(RemoteError::Read(ReadError::UnmappedAddress(0)))
     }
 
-    pub fn get_render_position(&self, handle: &NativeHandle) -> Vec3 {
-        if *RENDERPOS_X_OFFSET.lock().unwrap() == 0usize {
-            *RENDERPOS_X_OFFSET.lock().unwrap() = self
-                .rendermanager
-                .find_field_entry(handle, "b", "D")
-                .expect("Couldn't find renderPosX  field entry")
-                ._field_info
-                .offset() as usize;
-        }
-
-        let mut positioning: Vec3 = unsafe {
-            Vec3 {
-                ..core::mem::zeroed()
-            }
-        };
-
-        processes::read(
-            handle,
-            self.rendermanager.static_fields as usize + *RENDERPOS_X_OFFSET.lock().unwrap(),
-            &mut positioning,
-        );
-
-        positioning
+    pub fn get_render_position(&self, source: &dyn MemorySource) -> Result<Vec3, RemoteError> {
+        RENDER_POSITION.read_static(source)
     }
 }
@@ -0,0 +1,313 @@
+//! java.nio buffers in the target. A direct buffer (what LWJGL hands out) keeps its elements in
+//! native memory at `address`, a heap buffer in the array `hb` from `offset` on. Either way
+//! `position` and `limit` say which elements are in play.
+//!
+//! Views of a heap ByteBuffer as another type (`ByteBufferAsFloatBufferL` and friends) aren't
+//! supported, LWJGL never makes those.
+
+use std::ops::Range;
+
+use crate::api::processes::MemorySource;
+
+use super::{
+    java::{ArrayElement, JavaArray},
+    oops::{self, COMPRESSED_OOPS},
+    remote::{RemoteError, RemoteObject, RemoteValue},
+};
+
+/// The buffer classes by the signature of what they hold
+const BUFFER_CLASSES: &[(&str, &str)] = &[
+    ("java/nio/ByteBuffer", "B"),
+    ("java/nio/CharBuffer", "C"),
+    ("java/nio/ShortBuffer", "S"),
+    ("java/nio/IntBuffer", "I"),
+    ("java/nio/LongBuffer", "J"),
+    ("java/nio/FloatBuffer", "F"),
+    ("java/nio/DoubleBuffer", "D"),
+];
+
+/// Where the elements of a buffer are
+#[derive(Debug, Clone)]
+pub enum BufferStorage<T> {
+    /// Native memory, `address` being element 0
+    Direct { address: usize },
+
+    /// An array on the heap, element 0 being `offset` elements into it
+    Heap { array: JavaArray<T>, offset: usize },
+}
+
+/// A buffer as it was when it was read. `position` and `limit` move as the game uses it, read
+/// the buffer again to see where they went.
+#[derive(Debug, Clone)]
+#[allow(dead_code)]
+pub struct JavaBuffer<T> {
+    pub address: usize,
+    pub position: usize,
+    pub limit: usize,
+    pub capacity: usize,
+    pub storage: BufferStorage<T>,
+
+    /// Whether the elements are in the other byte order than the target's, e.g. a
+    /// `DirectFloatBufferS`
+    pub swapped: bool,
+}
+
+impl<T: ArrayElement> JavaBuffer<T> {
+    /// The buffer at `address`, which has to hold `T`s (a FloatBuffer for f32 and so on)
+    pub fn read(source: &dyn MemorySource, address: usize) -> Result<Self, RemoteError> {
+        let buffer = RemoteObject::new(source, address)?;
+        let class = buffer.class_name(source)?;
+
+        let mut holds = None;
+        for (buffer_class, sig) in BUFFER_CLASSES {
+            if buffer.is_instance_of(source, buffer_class)? {
+                holds = Some(sig);
+                break;
+            }
+        }
+
+        if !holds.is_some_and(|sig| T::matches(sig)) {
+            let expected = BUFFER_CLASSES
+                .iter()
+                .find(|(_, sig)| T::matches(sig))
+                .map_or("java/nio/Buffer", |(buffer_class, _)| buffer_class);
+
+            return Err(RemoteError::WrongClass {
+                expected,
+                found: class,
+            });
+        }
+
+        let int = |name: &str| -> Result<usize, RemoteError> {
+            Ok(buffer.get_int(source, name)?.max(0) as usize)
+        };
+
+        let storage = match buffer.get::<Option<JavaArray<T>>>(source, "hb")? {
+            Some(array) => BufferStorage::Heap {
+                array,
+                offset: int("offset")?,
+            },
+            None if class.starts_with("java/nio/ByteBufferAs") => {
+                return Err(RemoteError::WrongClass {
+                    expected: "direct or heap buffer",
+                    found: class,
+                })
+            }
+            // a 64 bit address, whatever the target does with its references
+            None => BufferStorage::Direct {
+                address: buffer.get_long(source, "address")? as usize,
+            },
+        };
+
+        // a ByteBuffer's order only matters once it's read as something wider
+        let swapped = class.starts_with("java/nio/Direct") && class.ends_with('S');
+
+        Ok(Self {
+            address,
+            position: int("position")?,
+            limit: int("limit")?,
+            capacity: int("capacity")?,
+            storage,
+            swapped,
+        })
+    }
+
+    /// How many elements are left between `position` and `limit`
+    #[allow(dead_code)]
+    pub fn remaining(&self) -> usize {
+        self.limit.saturating_sub(self.position)
+    }
+
+    /// The elements in `range`, which has to end by `limit` like Java's absolute gets
+    pub fn read_range(
+        &self,
+        source: &dyn MemorySource,
+        range: Range<usize>,
+    ) -> Result<Vec<T>, RemoteError> {
+        if range.start > range.end || range.end > self.limit {
+            return Err(RemoteError::IndexOutOfBounds {
+                index: range.end.max(range.start + 1) - 1,
+                length: self.limit,
+            });
+        }
+
+        let address = match &self.storage {
+            BufferStorage::Heap { array, offset } => {
+                return array.read_range(source, offset + range.start..offset + range.end)
+            }
+            BufferStorage::Direct { address } => *address,
+        };
+
+        let compressed_oops = *COMPRESSED_OOPS.lock().unwrap();
+        let element_size = T::size(&compressed_oops);
+
+        let mut bytes = vec![0u8; (range.end - range.start) * element_size];
+        if !bytes.is_empty() {
+            source.read_bytes(address + range.start * element_size, &mut bytes)?;
+        }
+
+        Ok(bytes
+            .chunks_exact_mut(element_size)
+            .map(|element| {
+                if self.swapped {
+                    element.reverse();
+                }
+
+                T::decode(element, &compressed_oops)
+            })
+            .collect())
+    }
+
+    /// The element at `index`, ignoring `position` like Java's absolute get
+    #[allow(dead_code)]
+    pub fn get(&self, source: &dyn MemorySource, index: usize) -> Result<T, RemoteError> {
+        Ok(self.read_range(source, index..index + 1)?.remove(0))
+    }
+
+    /// The elements from `position` to `limit`, what a relative get would go through
+    #[allow(dead_code)]
+    pub fn remaining_elements(&self, source: &dyn MemorySource) -> Result<Vec<T>, RemoteError> {
+        self.read_range(source, self.position.min(self.limit)..self.limit)
+    }
+
+    /// The elements up to `limit`, where the matrices LWJGL fills are
+    #[allow(dead_code)]
+    pub fn to_vec(&self, source: &dyn MemorySource) -> Result<Vec<T>, RemoteError> {
+        self.read_range(source, 0..self.limit)
+    }
+}
+
+/// The buffer a field points at, `None` for null
+impl<T: ArrayElement> RemoteValue for Option<JavaBuffer<T>> {
+    const EXPECTED: &'static str = "buffer";
+
+    fn matches(sig: &str) -> bool {
+        BUFFER_CLASSES.iter().any(|(buffer_class, holds)| {
+            sig.strip_prefix('L').and_then(|sig| sig.strip_suffix(';')) == Some(*buffer_class)
+                && T::matches(holds)
+        })
+    }
+
+    fn read(source: &dyn MemorySource, address: usize) -> Result<Self, RemoteError> {
+        match oops::read_oop(source, address)? {
+            0 => Ok(None),
+            buffer => JavaBuffer::read(source, buffer).map(Some),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::api::sdk::testing::{field, JvmImage};
+
+    /// A buffer of `klass` at `position` and `limit`, holding `capacity` elements at `address` or
+    /// in `hb` from `offset` on
+    #[allow(clippy::too_many_arguments)]
+    fn buffer(
+        image: &mut JvmImage,
+        klass: usize,
+        position: i32,
+        limit: i32,
+        capacity: i32,
+        address: usize,
+        hb: usize,
+        offset: i32,
+    ) -> usize {
+        let buffer = image.instance(klass, 0x30);
+
+        image.write(buffer + 0x10, &position);
+        image.write(buffer + 0x14, &limit);
+        image.write(buffer + 0x18, &capacity);
+        image.write(buffer + 0x20, &(address as u64));
+        image.write(buffer + 0x28, &(hb as u32));
+        image.write(buffer + 0x2c, &offset);
+
+        buffer
+    }
+
+    #[test]
+    fn reads_buffers() {
+        let mut image = JvmImage::new(0x7f00_0000, 0x4000);
+
+        let object = image.class("java/lang/Object", 0, &[]);
+        let buffer_class = image.class(
+            "java/nio/Buffer",
+            object,
+            &[
+                field("mark", "I", 0x0c),
+                field("position", "I", 0x10),
+                field("limit", "I", 0x14),
+                field("capacity", "I", 0x18),
+                field("address", "J", 0x20),
+            ],
+        );
+        let float_buffer = image.class(
+            "java/nio/FloatBuffer",
+            buffer_class,
+            &[field("hb", "[F", 0x28), field("offset", "I", 0x2c)],
+        );
+        let int_buffer = image.class(
+            "java/nio/IntBuffer",
+            buffer_class,
+            &[field("hb", "[I", 0x28), field("offset", "I", 0x2c)],
+        );
+        let native = image.class("java/nio/DirectFloatBufferU", float_buffer, &[]);
+        let swapped = image.class("java/nio/DirectFloatBufferS", float_buffer, &[]);
+        let heap = image.class("java/nio/HeapIntBuffer", int_buffer, &[]);
+        let ints = image.class("[I", 0, &[]);
+
+        // the matrix LWJGL keeps, malloc'd far from the heap
+        let matrix = image.alloc(16 * 4);
+        for index in 0..16 {
+            image.write(matrix + index * 4, &(index as f32));
+        }
+
+        let modelview = buffer(&mut image, native, 4, 16, 16, matrix, 0, 0);
+        let modelview = JavaBuffer::<f32>::read(&image, modelview).unwrap();
+        assert_eq!(modelview.remaining(), 12);
+        assert_eq!(
+            modelview.to_vec(&image),
+            Ok((0..16).map(|index| index as f32).collect())
+        );
+        assert_eq!(modelview.remaining_elements(&image).unwrap()[0], 4.0);
+        assert_eq!(
+            modelview.get(&image, 16),
+            Err(RemoteError::IndexOutOfBounds {
+                index: 16,
+                length: 16
+            })
+        );
+
+        let big_endian = image.alloc(2 * 4);
+        image.write(big_endian, &1.5f32.to_be_bytes());
+        image.write(big_endian + 4, &(-2f32).to_be_bytes());
+        let projection = buffer(&mut image, swapped, 0, 2, 2, big_endian, 0, 0);
+        assert_eq!(
+            JavaBuffer::<f32>::read(&image, projection)
+                .unwrap()
+                .to_vec(&image),
+            Ok(vec![1.5, -2.0])
+        );
+
+        // a slice of an int[], starting two elements in
+        let array = image.instance(ints, 16 + 6 * 4);
+        image.write(array + 12, &6i32);
+        for (index, value) in [9i32, 9, 0, 0, 1920, 1080].iter().enumerate() {
+            image.write(array + 16 + index * 4, value);
+        }
+        let viewport = buffer(&mut image, heap, 0, 4, 4, 0, array, 2);
+        let viewport = JavaBuffer::<i32>::read(&image, viewport).unwrap();
+        assert_eq!(viewport.to_vec(&image), Ok(vec![0, 0, 1920, 1080]));
+        assert_eq!(viewport.get(&image, 3), Ok(1080));
+
+        assert_eq!(
+            JavaBuffer::<i32>::read(&image, projection).err(),
+            Some(RemoteError::WrongClass {
+                expected: "java/nio/IntBuffer",
+                found: "java/nio/DirectFloatBufferS".to_string(),
+            })
+        );
+    }
+}
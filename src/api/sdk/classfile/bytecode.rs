@@ -0,0 +1,598 @@
+//! Undoing what HotSpot does to bytecode once a class is linked: the Rewriter swaps constant
+//! pool indices for constant pool cache indices (in native byte order) and the interpreter
+//! replaces instructions with its own fast variants as it runs them.
+
+use std::convert::TryFrom;
+
+use crate::api::{
+    processes::{self, MemorySource},
+    sdk::{
+        vmstructs::{self, VMStructs},
+        FromNative, JArray, JConstantPool,
+    },
+};
+
+use super::{malformed, ClassFileError};
+
+const ALOAD_0: u8 = 0x2a;
+const ILOAD: u8 = 0x15;
+const LDC: u8 = 0x12;
+const LDC_W: u8 = 0x13;
+const LOOKUPSWITCH: u8 = 0xab;
+const TABLESWITCH: u8 = 0xaa;
+const RETURN: u8 = 0xb1;
+const GETSTATIC: u8 = 0xb2;
+const PUTFIELD: u8 = 0xb5;
+const GETFIELD: u8 = 0xb4;
+const INVOKEVIRTUAL: u8 = 0xb6;
+const INVOKEINTERFACE: u8 = 0xb9;
+const INVOKEDYNAMIC: u8 = 0xba;
+const IINC: u8 = 0x84;
+const WIDE: u8 = 0xc4;
+
+// HotSpot's own bytecodes, numbered like JDK 8u121 up to 17
+
+const BREAKPOINT: u8 = 202;
+const FAST_ALDC: u8 = 230;
+const FAST_ALDC_W: u8 = 231;
+
+/// The instruction HotSpot's bytecode `opcode` stands for, Java bytecodes map onto themselves
+fn java_code(opcode: u8) -> u8 {
+    match opcode {
+        // _fast_Xgetfield and _nofast_getfield
+        203..=210 | 234 => GETFIELD,
+        // _fast_Xputfield and _nofast_putfield
+        211..=219 | 235 => PUTFIELD,
+        // _fast_aload_0, _fast_Xaccess_0 and _nofast_aload_0
+        220..=223 | 236 => ALOAD_0,
+        // _fast_iload, _fast_iload2, _fast_icaload and _nofast_iload
+        224..=226 | 237 => ILOAD,
+        // _fast_invokevfinal and _invokehandle
+        227 | 233 => INVOKEVIRTUAL,
+        // _fast_linearswitch and _fast_binaryswitch
+        228 | 229 => LOOKUPSWITCH,
+        FAST_ALDC => LDC,
+        FAST_ALDC_W => LDC_W,
+        // _return_register_finalizer
+        232 => RETURN,
+        opcode => opcode,
+    }
+}
+
+fn i32_at(code: &[u8], offset: usize) -> Option<i32> {
+    let mut bytes = [0u8; 4];
+    bytes.copy_from_slice(code.get(offset..offset + 4)?);
+
+    Some(i32::from_be_bytes(bytes))
+}
+
+/// Length of the Java instruction at `bci`, `None` if it isn't one or runs past the end
+fn instruction_length(code: &[u8], bci: usize) -> Option<usize> {
+    // switches pad their operands to the next multiple of 4
+    let operands = bci + 1 + (4 - (bci + 1) % 4) % 4;
+
+    let length = match *code.get(bci)? {
+        0x10 | LDC | 0x15..=0x19 | 0x36..=0x3a | 0xa9 | 0xbc => 2,
+        0x11
+        | LDC_W
+        | 0x14
+        | IINC
+        | 0x99..=0xa8
+        | GETSTATIC..=0xb8
+        | 0xbb
+        | 0xbd
+        | 0xc0
+        | 0xc1
+        | 0xc6
+        | 0xc7 => 3,
+        0xc5 => 4,
+        INVOKEINTERFACE | INVOKEDYNAMIC | 0xc8 | 0xc9 => 5,
+        TABLESWITCH => {
+            let low = i32_at(code, operands + 4)?;
+            let high = i32_at(code, operands + 8)?;
+
+            operands - bci + 12 + 4 * (high.checked_sub(low)? as usize + 1)
+        }
+        LOOKUPSWITCH => {
+            let pairs = i32_at(code, operands + 4)?;
+
+            operands - bci + 8 + 8 * pairs.max(0) as usize
+        }
+        WIDE if *code.get(bci + 1)? == IINC => 6,
+        WIDE => 4,
+        0x00..=0xc9 => 1,
+        _ => return None,
+    };
+
+    if bci + length > code.len() {
+        return None;
+    }
+
+    Some(length)
+}
+
+/// One table of resolved entries, each of which remembers the constant pool index it was made
+/// for
+#[derive(Debug, Clone)]
+struct CpIndexTable {
+    /// What the entries are resolved for, for the errors
+    what: &'static str,
+
+    /// The address of entry 0
+    start: usize,
+    length: i32,
+    entry_size: usize,
+
+    /// Where the u2 constant pool index is in an entry
+    cp_index: usize,
+}
+
+impl CpIndexTable {
+    fn cp_index(&self, source: &dyn MemorySource, which: i32) -> Result<u16, ClassFileError> {
+        if which < 0 || which >= self.length {
+            return Err(malformed(&format!("no {} {}", self.what, which)));
+        }
+
+        let entry = self.start + which as usize * self.entry_size;
+
+        Ok(processes::try_read_exact::<u16>(
+            source,
+            entry + self.cp_index,
+        )?)
+    }
+}
+
+/// What we need of a `ConstantPoolCache` to map the operands the Rewriter put in the bytecode
+/// back onto the constant pool. Up to JDK 20 those all index `ConstantPoolCacheEntry`s, JDK 21
+/// moved invokedynamic call sites to `ResolvedIndyEntry`s and JDK 22 field and method references
+/// to `ResolvedFieldEntry`s and `ResolvedMethodEntry`s, dropping `ConstantPoolCacheEntry`.
+pub struct ConstantPoolCache {
+    fields: CpIndexTable,
+    methods: CpIndexTable,
+    call_sites: CpIndexTable,
+    reference_map: Option<JArray<u16>>,
+}
+
+impl ConstantPoolCache {
+    /// The cache of `constant_pool`, `None` if the class was never linked and its bytecode is
+    /// still as it was loaded
+    pub fn read(
+        source: &dyn MemorySource,
+        constant_pool: &JConstantPool,
+    ) -> Result<Option<Self>, ClassFileError> {
+        let structs = vmstructs::VM_STRUCTS.lock().unwrap();
+
+        Self::read_with(source, constant_pool, &structs)
+    }
+
+    /// `read` with the layouts of `structs`
+    fn read_with(
+        source: &dyn MemorySource,
+        constant_pool: &JConstantPool,
+        structs: &VMStructs,
+    ) -> Result<Option<Self>, ClassFileError> {
+        if constant_pool.cache.is_null() {
+            return Ok(None);
+        }
+
+        let unknown = || malformed("the JVM doesn't describe its constant pool cache");
+        let header = structs.size_of("ConstantPoolCache").ok_or_else(unknown)?;
+
+        let view = structs.read_struct(source, "ConstantPoolCache", constant_pool.cache as _)?;
+
+        // JDK 8 keeps the reference map in the pool
+        let reference_map = match structs.offset_of("ConstantPoolCache::_reference_map") {
+            Some(_) => view.field::<usize>("ConstantPoolCache::_reference_map"),
+            None => structs
+                .read_struct(source, "ConstantPool", constant_pool.base as _)?
+                .field::<usize>("ConstantPool::_reference_map"),
+        };
+
+        // the ConstantPoolCacheEntry table follows the header, if this JVM still has one
+        let entries = || -> Result<CpIndexTable, ClassFileError> {
+            Ok(CpIndexTable {
+                what: "constant pool cache entry",
+                start: constant_pool.cache as usize + header,
+                length: view.field("ConstantPoolCache::_length"),
+                entry_size: structs
+                    .size_of("ConstantPoolCacheEntry")
+                    .ok_or_else(unknown)?,
+                cp_index: structs
+                    .offset_of("ConstantPoolCacheEntry::_indices")
+                    .ok_or_else(unknown)?,
+            })
+        };
+
+        // an Array of the entries that replaced them, NULL when the class needs none
+        let resolved = |array_field: &str,
+                        entry_type: &str,
+                        what: &'static str|
+         -> Result<Option<CpIndexTable>, ClassFileError> {
+            let array_field = format!("ConstantPoolCache::{}", array_field);
+            if structs.offset_of(&array_field).is_none() {
+                return Ok(None);
+            }
+
+            let array = view.field::<usize>(&array_field);
+            let length = match array {
+                0 => 0,
+                array => JArray::<usize>::from_native(source, array as _)?.lenght,
+            };
+
+            Ok(Some(CpIndexTable {
+                what,
+                // they all hold pointers, so they start where an Array<Method*>'s elements do
+                start: array + std::mem::size_of::<usize>(),
+                length,
+                entry_size: structs.size_of(entry_type).ok_or_else(unknown)?,
+                cp_index: structs
+                    .offset_of(&format!("{}::_cpool_index", entry_type))
+                    .ok_or_else(unknown)?,
+            }))
+        };
+
+        let fields = match resolved(
+            "_resolved_field_entries",
+            "ResolvedFieldEntry",
+            "resolved field entry",
+        )? {
+            Some(fields) => fields,
+            None => entries()?,
+        };
+        let methods = match resolved(
+            "_resolved_method_entries",
+            "ResolvedMethodEntry",
+            "resolved method entry",
+        )? {
+            Some(methods) => methods,
+            None => entries()?,
+        };
+        let call_sites = match resolved(
+            "_resolved_indy_entries",
+            "ResolvedIndyEntry",
+            "resolved invokedynamic entry",
+        )? {
+            Some(call_sites) => call_sites,
+            None => entries()?,
+        };
+
+        Ok(Some(Self {
+            fields,
+            methods,
+            call_sites,
+            reference_map: JArray::from_native(source, reference_map as _).ok(),
+        }))
+    }
+
+    /// The constant pool index of the field or method reference `which` a get, put or invoke
+    /// instruction had
+    fn member_cp_index(
+        &self,
+        source: &dyn MemorySource,
+        opcode: u8,
+        which: i32,
+    ) -> Result<u16, ClassFileError> {
+        match opcode {
+            GETSTATIC..=PUTFIELD => self.fields.cp_index(source, which),
+            _ => self.methods.cp_index(source, which),
+        }
+    }
+
+    /// The constant pool index of the invokedynamic call site `which`
+    fn call_site_cp_index(
+        &self,
+        source: &dyn MemorySource,
+        which: i32,
+    ) -> Result<u16, ClassFileError> {
+        self.call_sites.cp_index(source, which)
+    }
+
+    /// The constant pool index resolved reference `which` was made for
+    fn referenced_cp_index(
+        &self,
+        source: &dyn MemorySource,
+        which: i32,
+    ) -> Result<u16, ClassFileError> {
+        self.reference_map
+            .as_ref()
+            .and_then(|reference_map| reference_map.at(which, source))
+            .ok_or_else(|| malformed(&format!("no resolved reference {}", which)))
+    }
+}
+
+fn native_u2(code: &[u8], offset: usize) -> u16 {
+    u16::from_ne_bytes([code[offset], code[offset + 1]])
+}
+
+/// The bytecode the way it was in the class file. Without a `cache` the class wasn't linked
+/// yet, only fast bytecodes would need undoing then (and there won't be any).
+pub fn restore(
+    source: &dyn MemorySource,
+    code: &[u8],
+    cache: Option<&ConstantPoolCache>,
+) -> Result<Vec<u8>, ClassFileError> {
+    let mut restored = code.to_vec();
+    let mut bci = 0;
+
+    while bci < code.len() {
+        let opcode = code[bci];
+
+        // the original instruction went to the breakpoint table, we'd rather fail than guess
+        if opcode == BREAKPOINT {
+            return Err(malformed(&format!("breakpoint set at bci {}", bci)));
+        }
+
+        restored[bci] = java_code(opcode);
+
+        let length = instruction_length(&restored, bci)
+            .ok_or_else(|| malformed(&format!("bad instruction {:#x} at bci {}", opcode, bci)))?;
+
+        if let Some(cache) = cache {
+            let java_u2 = match restored[bci] {
+                instruction @ GETSTATIC..=INVOKEINTERFACE => Some(cache.member_cp_index(
+                    source,
+                    instruction,
+                    native_u2(code, bci + 1) as i32,
+                )?),
+                // one entry per call site, the index used to be stored inverted
+                INVOKEDYNAMIC => {
+                    let mut index = [0u8; 4];
+                    index.copy_from_slice(&code[bci + 1..bci + 5]);
+
+                    let index = i32::from_ne_bytes(index);
+                    let index = if index < 0 { !index } else { index };
+
+                    restored[bci + 3] = 0;
+                    restored[bci + 4] = 0;
+
+                    Some(cache.call_site_cp_index(source, index)?)
+                }
+                LDC if opcode == FAST_ALDC => {
+                    let cp_index = cache.referenced_cp_index(source, code[bci + 1] as i32)?;
+
+                    restored[bci + 1] = u8::try_from(cp_index)
+                        .map_err(|_| malformed(&format!("ldc of entry {}", cp_index)))?;
+
+                    None
+                }
+                LDC_W if opcode == FAST_ALDC_W => {
+                    Some(cache.referenced_cp_index(source, native_u2(code, bci + 1) as i32)?)
+                }
+                _ => None,
+            };
+
+            if let Some(cp_index) = java_u2 {
+                restored[bci + 1..bci + 3].copy_from_slice(&cp_index.to_be_bytes());
+            }
+        }
+
+        bci += length;
+    }
+
+    Ok(restored)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::api::sdk::{
+        constantpool::JVM_CONSTANT_INVALID, testing::JvmImage, vmstructs::VMField,
+    };
+
+    /// The JDK 8 layouts plus the given fields, and the types as (name, size)
+    fn structs(fields: &[(&str, usize)], types: &[(&str, usize)]) -> VMStructs {
+        let mut structs = VMStructs::jdk8();
+
+        for (name, offset) in fields {
+            structs.insert_field(
+                name,
+                VMField {
+                    offset: *offset,
+                    is_static: false,
+                    address: 0,
+                    type_string: None,
+                },
+            );
+        }
+
+        for (name, size) in types {
+            structs.add_type(name, *size, None);
+        }
+
+        structs
+    }
+
+    /// An `Array` of `entry_size` byte entries, each holding the constant pool index it was
+    /// resolved for at `cp_index`
+    fn resolved_entries(
+        image: &mut JvmImage,
+        entry_size: usize,
+        cp_index: usize,
+        cp_indices: &[u16],
+    ) -> usize {
+        let array = image.alloc(8 + cp_indices.len() * entry_size);
+
+        image.write(array, &(cp_indices.len() as i32));
+        for (idx, index) in cp_indices.iter().enumerate() {
+            image.write(array + 8 + idx * entry_size + cp_index, index);
+        }
+
+        array
+    }
+
+    #[test]
+    fn undoes_fast_bytecodes() {
+        let image = JvmImage::new(0x7f00_0000, 0x10);
+
+        let code = [
+            236, // _nofast_aload_0
+            225, 0x01, // _fast_iload2
+            ILOAD, 0x02, //
+            WIDE, IINC, 0x01, 0x00, 0x00, 0x05, //
+            232,  // _return_register_finalizer
+        ];
+
+        assert_eq!(
+            restore(&image, &code, None).unwrap(),
+            vec![ALOAD_0, ILOAD, 0x01, ILOAD, 0x02, WIDE, IINC, 0x01, 0x00, 0x00, 0x05, RETURN]
+        );
+    }
+
+    #[test]
+    fn walks_switches() {
+        let image = JvmImage::new(0x7f00_0000, 0x10);
+
+        // tableswitch at bci 1 pads to bci 4, 0..=1 so two offsets
+        let mut code = vec![ALOAD_0, TABLESWITCH, 0, 0];
+        for value in [16i32, 0, 1, 16, 16] {
+            code.extend(value.to_be_bytes());
+        }
+
+        // _fast_linearswitch at bci 24, padded to 28, a single pair
+        code.push(228);
+        code.extend([0, 0, 0]);
+        for value in [8i32, 1, 1, 8] {
+            code.extend(value.to_be_bytes());
+        }
+        code.push(RETURN);
+
+        let restored = restore(&image, &code, None).unwrap();
+        assert_eq!(restored[24], LOOKUPSWITCH);
+        assert_eq!(restored.len(), code.len());
+
+        // a switch running past the end
+        code.truncate(30);
+        assert!(restore(&image, &code, None).is_err());
+    }
+
+    #[test]
+    fn maps_resolved_entries() {
+        let mut image = JvmImage::new(0x7f00_0000, 0x1000);
+
+        let call_sites = resolved_entries(&mut image, 0x10, 0x0a, &[40, 41]);
+        let fields = resolved_entries(&mut image, 0x18, 0x0e, &[20, 21]);
+        let methods = resolved_entries(&mut image, 0x18, 0x10, &[30, 31]);
+
+        // JDK 22: no ConstantPoolCacheEntry left, every kind of reference has its own entries
+        let jdk22 = structs(
+            &[
+                ("ConstantPoolCache::_length", 0x00),
+                ("ConstantPoolCache::_reference_map", 0x08),
+                ("ConstantPoolCache::_resolved_indy_entries", 0x10),
+                ("ConstantPoolCache::_resolved_field_entries", 0x18),
+                ("ConstantPoolCache::_resolved_method_entries", 0x20),
+                ("ResolvedIndyEntry::_cpool_index", 0x0a),
+                ("ResolvedFieldEntry::_cpool_index", 0x0e),
+                ("ResolvedMethodEntry::_cpool_index", 0x10),
+            ],
+            &[
+                ("ConstantPoolCache", 0x28),
+                ("ResolvedIndyEntry", 0x10),
+                ("ResolvedFieldEntry", 0x18),
+                ("ResolvedMethodEntry", 0x18),
+            ],
+        );
+
+        let cache = image.alloc(0x28);
+        image.write(cache + 0x10, &call_sites);
+        image.write(cache + 0x18, &fields);
+        image.write(cache + 0x20, &methods);
+
+        let constant_pool = image.tagged_constant_pool(&[(JVM_CONSTANT_INVALID, 0)]);
+        image.write_field(constant_pool, "ConstantPool::_cache", &cache);
+        let constant_pool = JConstantPool::from_native(&image, constant_pool as _).unwrap();
+
+        let cache = ConstantPoolCache::read_with(&image, &constant_pool, &jdk22)
+            .unwrap()
+            .unwrap();
+
+        let mut code = vec![GETFIELD];
+        code.extend(1u16.to_ne_bytes());
+        code.push(INVOKEVIRTUAL);
+        code.extend(0u16.to_ne_bytes());
+        // call site 0 the way JDK 22 writes it, call site 1 inverted like before
+        code.push(INVOKEDYNAMIC);
+        code.extend(0i32.to_ne_bytes());
+        code.push(INVOKEDYNAMIC);
+        code.extend((!1i32).to_ne_bytes());
+        // _invokehandle
+        code.push(233);
+        code.extend(1u16.to_ne_bytes());
+        code.push(RETURN);
+
+        assert_eq!(
+            restore(&image, &code, Some(&cache)).unwrap(),
+            vec![
+                GETFIELD,
+                0,
+                21,
+                INVOKEVIRTUAL,
+                0,
+                30,
+                INVOKEDYNAMIC,
+                0,
+                40,
+                0,
+                0,
+                INVOKEDYNAMIC,
+                0,
+                41,
+                0,
+                0,
+                INVOKEVIRTUAL,
+                0,
+                31,
+                RETURN
+            ]
+        );
+
+        // a field reference past the entries isn't papered over with some other index
+        let mut code = vec![GETFIELD];
+        code.extend(2u16.to_ne_bytes());
+        assert!(matches!(
+            restore(&image, &code, Some(&cache)),
+            Err(ClassFileError::Malformed(_))
+        ));
+
+        // JDK 21: only call sites moved out of the ConstantPoolCacheEntries following the header
+        let jdk21 = structs(
+            &[
+                ("ConstantPoolCache::_length", 0x00),
+                ("ConstantPoolCache::_reference_map", 0x08),
+                ("ConstantPoolCache::_resolved_indy_entries", 0x10),
+                ("ConstantPoolCacheEntry::_indices", 0x00),
+                ("ResolvedIndyEntry::_cpool_index", 0x0a),
+            ],
+            &[
+                ("ConstantPoolCache", 0x18),
+                ("ConstantPoolCacheEntry", 0x20),
+                ("ResolvedIndyEntry", 0x10),
+            ],
+        );
+
+        let cache = image.alloc(0x18 + 2 * 0x20);
+        image.write(cache, &2i32);
+        image.write(cache + 0x10, &call_sites);
+        image.write(cache + 0x18, &0xb4b4_0000_u32);
+        image.write(cache + 0x18 + 0x20, &(0xb6b6_0000_u32 | 50));
+
+        let constant_pool = image.tagged_constant_pool(&[(JVM_CONSTANT_INVALID, 0)]);
+        image.write_field(constant_pool, "ConstantPool::_cache", &cache);
+        let constant_pool = JConstantPool::from_native(&image, constant_pool as _).unwrap();
+
+        let cache = ConstantPoolCache::read_with(&image, &constant_pool, &jdk21)
+            .unwrap()
+            .unwrap();
+
+        let mut code = vec![INVOKEVIRTUAL];
+        code.extend(1u16.to_ne_bytes());
+        code.push(INVOKEDYNAMIC);
+        code.extend((!1i32).to_ne_bytes());
+
+        assert_eq!(
+            restore(&image, &code, Some(&cache)).unwrap(),
+            vec![INVOKEVIRTUAL, 0, 50, INVOKEDYNAMIC, 0, 41, 0, 0]
+        );
+    }
+}
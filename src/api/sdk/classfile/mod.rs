@@ -0,0 +1,827 @@
+//! Turns a loaded class back into a .class file, built from what HotSpot kept of the original:
+//! the constant pool, the fields and the methods with their (restored) bytecode.
+//!
+//! Debug attributes (line numbers, local variables), annotations and inner class entries
+//! aren't written, a decompiler does fine without them.
+
+use std::{collections::HashMap, convert::TryFrom};
+
+use crate::api::processes::{MemorySource, ReadError};
+
+use super::{
+    constantpool::ConstantPoolEntry, fieldinfo, FieldError, FromNative, JArray, JClass,
+    JConstMethod, JConstantPool, JMethod, JSymbol,
+};
+
+pub mod bytecode;
+
+const MAGIC: u32 = 0xCAFE_BABE;
+
+// the flags HotSpot keeps next to its own, masks as in jvm.h
+
+pub const JVM_RECOGNIZED_CLASS_MODIFIERS: i32 = 0x7631;
+pub const JVM_RECOGNIZED_FIELD_MODIFIERS: u16 = 0x50DF;
+pub const JVM_RECOGNIZED_METHOD_MODIFIERS: i32 = 0x1DFF;
+
+const JVM_ACC_STATIC: u16 = 0x0008;
+const JVM_ACC_NATIVE: i32 = 0x0100;
+const JVM_ACC_ABSTRACT: i32 = 0x0400;
+
+/// Why a class couldn't be written
+#[derive(Debug)]
+pub enum ClassFileError {
+    Read(ReadError),
+
+    /// What we read doesn't add up to a class file
+    Malformed(String),
+}
+
+impl std::fmt::Display for ClassFileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ClassFileError::Read(error) => write!(f, "{}", error),
+            ClassFileError::Malformed(reason) => write!(f, "malformed class: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for ClassFileError {}
+
+impl From<ReadError> for ClassFileError {
+    fn from(error: ReadError) -> Self {
+        ClassFileError::Read(error)
+    }
+}
+
+impl From<FieldError> for ClassFileError {
+    fn from(error: FieldError) -> Self {
+        match error {
+            FieldError::Read(error) => ClassFileError::Read(error),
+            error => ClassFileError::Malformed(error.to_string()),
+        }
+    }
+}
+
+fn malformed(reason: &str) -> ClassFileError {
+    ClassFileError::Malformed(reason.to_string())
+}
+
+// class files are big endian
+
+fn put_u2(out: &mut Vec<u8>, value: u16) {
+    out.extend(value.to_be_bytes());
+}
+
+fn put_u4(out: &mut Vec<u8>, value: u32) {
+    out.extend(value.to_be_bytes());
+}
+
+/// Java's "modified UTF-8": NUL takes two bytes and everything outside the BMP is written as a
+/// surrogate pair of three bytes each
+fn modified_utf8(text: &str) -> Vec<u8> {
+    let mut bytes = Vec::new();
+
+    for unit in text.encode_utf16() {
+        match unit {
+            0x0001..=0x007F => bytes.push(unit as u8),
+            0x0000 | 0x0080..=0x07FF => {
+                bytes.push(0xC0 | (unit >> 6) as u8);
+                bytes.push(0x80 | (unit & 0x3F) as u8);
+            }
+            _ => {
+                bytes.push(0xE0 | (unit >> 12) as u8);
+                bytes.push(0x80 | ((unit >> 6) & 0x3F) as u8);
+                bytes.push(0x80 | (unit & 0x3F) as u8);
+            }
+        }
+    }
+
+    bytes
+}
+
+/// The constant pool of the class file. Every entry keeps its index so the bytecode still
+/// points at the right ones, whatever the JVM no longer has (the name of a resolved JDK 8
+/// class, attribute names) is added at the end.
+struct PoolWriter {
+    bytes: Vec<u8>,
+    count: u16,
+    utf8: HashMap<Vec<u8>, u16>,
+    classes: HashMap<String, u16>,
+}
+
+impl PoolWriter {
+    fn new(
+        source: &dyn MemorySource,
+        constant_pool: &JConstantPool,
+    ) -> Result<Self, ClassFileError> {
+        let length = constant_pool.length;
+
+        let entries = (1..length)
+            .map(|which| {
+                constant_pool
+                    .entry(source, which)
+                    .ok_or_else(|| malformed(&format!("unreadable constant pool entry {}", which)))
+            })
+            .collect::<Result<Vec<ConstantPoolEntry>, ClassFileError>>()?;
+
+        // Utf8 and String entries are written as the symbol's bytes, not our lossy String
+        let raw_symbol = |which: i32| -> Result<Vec<u8>, ClassFileError> {
+            let symbol = constant_pool
+                .symbol(source, which as _)
+                .ok_or_else(|| malformed(&format!("no symbol in constant pool entry {}", which)))?;
+
+            Ok(symbol.as_bytes(source)?)
+        };
+
+        let mut pool = Self {
+            bytes: Vec::new(),
+            count: u16::try_from(length).map_err(|_| malformed("constant pool too large"))?,
+            utf8: HashMap::new(),
+            classes: HashMap::new(),
+        };
+
+        // know every Utf8 entry up front, the ones referring to them often come first
+        let mut symbols = HashMap::new();
+        for (which, entry) in (1..length).zip(entries.iter()) {
+            if let ConstantPoolEntry::Utf8(_) | ConstantPoolEntry::String(_) = entry {
+                let bytes = raw_symbol(which)?;
+
+                if let ConstantPoolEntry::Utf8(_) = entry {
+                    pool.utf8.entry(bytes.clone()).or_insert(which as u16);
+                }
+
+                symbols.insert(which, bytes);
+            }
+        }
+
+        let mut existing = Vec::new();
+        let mut wide = false;
+
+        for (which, entry) in (1..length).zip(entries.iter()) {
+            // a Long or Double takes up two entries, the second one isn't written
+            if std::mem::take(&mut wide) {
+                continue;
+            }
+
+            match entry {
+                ConstantPoolEntry::Utf8(_) => {
+                    let bytes = &symbols[&which];
+
+                    existing.push(1);
+                    put_u2(&mut existing, bytes.len() as u16);
+                    existing.extend(bytes);
+                }
+                ConstantPoolEntry::Integer(value) => {
+                    existing.push(3);
+                    put_u4(&mut existing, *value as u32);
+                }
+                ConstantPoolEntry::Float(value) => {
+                    existing.push(4);
+                    put_u4(&mut existing, value.to_bits());
+                }
+                ConstantPoolEntry::Long(value) => {
+                    existing.push(5);
+                    existing.extend(value.to_be_bytes());
+                    wide = true;
+                }
+                ConstantPoolEntry::Double(value) => {
+                    existing.push(6);
+                    existing.extend(value.to_bits().to_be_bytes());
+                    wide = true;
+                }
+                ConstantPoolEntry::Class { name, .. }
+                | ConstantPoolEntry::UnresolvedClass { name, .. } => {
+                    let name_index = pool.utf8(&modified_utf8(name))?;
+                    pool.classes.entry(name.clone()).or_insert(which as u16);
+
+                    existing.push(7);
+                    put_u2(&mut existing, name_index);
+                }
+                ConstantPoolEntry::String(_) => {
+                    let string_index = pool.utf8(&symbols[&which])?;
+
+                    existing.push(8);
+                    put_u2(&mut existing, string_index);
+                }
+                ConstantPoolEntry::Fieldref {
+                    class_index,
+                    name_and_type_index,
+                }
+                | ConstantPoolEntry::Methodref {
+                    class_index,
+                    name_and_type_index,
+                }
+                | ConstantPoolEntry::InterfaceMethodref {
+                    class_index,
+                    name_and_type_index,
+                } => {
+                    existing.push(match entry {
+                        ConstantPoolEntry::Fieldref { .. } => 9,
+                        ConstantPoolEntry::Methodref { .. } => 10,
+                        _ => 11,
+                    });
+                    put_u2(&mut existing, *class_index);
+                    put_u2(&mut existing, *name_and_type_index);
+                }
+                ConstantPoolEntry::NameAndType {
+                    name_index,
+                    signature_index,
+                } => {
+                    existing.push(12);
+                    put_u2(&mut existing, *name_index);
+                    put_u2(&mut existing, *signature_index);
+                }
+                ConstantPoolEntry::MethodHandle {
+                    ref_kind,
+                    ref_index,
+                } => {
+                    existing.push(15);
+                    existing.push(*ref_kind);
+                    put_u2(&mut existing, *ref_index);
+                }
+                ConstantPoolEntry::MethodType { signature_index } => {
+                    existing.push(16);
+                    put_u2(&mut existing, *signature_index);
+                }
+                ConstantPoolEntry::Dynamic {
+                    bootstrap_index,
+                    name_and_type_index,
+                }
+                | ConstantPoolEntry::InvokeDynamic {
+                    bootstrap_index,
+                    name_and_type_index,
+                } => {
+                    existing.push(match entry {
+                        ConstantPoolEntry::Dynamic { .. } => 17,
+                        _ => 18,
+                    });
+                    put_u2(&mut existing, *bootstrap_index);
+                    put_u2(&mut existing, *name_and_type_index);
+                }
+                ConstantPoolEntry::Module { name_index } => {
+                    existing.push(19);
+                    put_u2(&mut existing, *name_index);
+                }
+                ConstantPoolEntry::Package { name_index } => {
+                    existing.push(20);
+                    put_u2(&mut existing, *name_index);
+                }
+                ConstantPoolEntry::Invalid | ConstantPoolEntry::Unknown(_) => {
+                    return Err(malformed(&format!(
+                        "constant pool entry {} is {:?}",
+                        which, entry
+                    )));
+                }
+            }
+        }
+
+        // entries added while writing the existing ones go after them
+        existing.append(&mut pool.bytes);
+        pool.bytes = existing;
+
+        Ok(pool)
+    }
+
+    /// The index of a new entry, if the pool has room for one
+    fn add(&mut self) -> Result<u16, ClassFileError> {
+        let which = self.count;
+        self.count = self
+            .count
+            .checked_add(1)
+            .ok_or_else(|| malformed("no room left in the constant pool"))?;
+
+        Ok(which)
+    }
+
+    fn utf8(&mut self, bytes: &[u8]) -> Result<u16, ClassFileError> {
+        if let Some(which) = self.utf8.get(bytes) {
+            return Ok(*which);
+        }
+
+        let which = self.add()?;
+        self.bytes.push(1);
+        put_u2(&mut self.bytes, bytes.len() as u16);
+        self.bytes.extend(bytes);
+
+        self.utf8.insert(bytes.to_vec(), which);
+        Ok(which)
+    }
+
+    fn class(&mut self, name: &str) -> Result<u16, ClassFileError> {
+        if let Some(which) = self.classes.get(name) {
+            return Ok(*which);
+        }
+
+        let name_index = self.utf8(&modified_utf8(name))?;
+
+        let which = self.add()?;
+        self.bytes.push(7);
+        put_u2(&mut self.bytes, name_index);
+
+        self.classes.insert(name.to_string(), which);
+        Ok(which)
+    }
+
+    /// An attribute named `name` holding `data`
+    fn attribute(
+        &mut self,
+        out: &mut Vec<u8>,
+        name: &str,
+        data: &[u8],
+    ) -> Result<(), ClassFileError> {
+        put_u2(out, self.utf8(name.as_bytes())?);
+        put_u4(out, data.len() as u32);
+        out.extend(data);
+
+        Ok(())
+    }
+}
+
+fn class_name(source: &dyn MemorySource, klass: *mut JClass) -> Result<String, ClassFileError> {
+    let clazz = JClass::from_native(source, klass)?;
+
+    Ok(JSymbol::from_native(source, clazz.symbol)?.to_string(source))
+}
+
+/// The class file of `clazz`, as close to what was loaded as HotSpot lets us get
+pub fn write_class(source: &dyn MemorySource, clazz: &JClass) -> Result<Vec<u8>, ClassFileError> {
+    let constant_pool = JConstantPool::from_native(source, clazz.constant_pool)?;
+    let cache = bytecode::ConstantPoolCache::read(source, &constant_pool)?;
+
+    let mut pool = PoolWriter::new(source, &constant_pool)?;
+    let mut body = Vec::new();
+
+    put_u2(
+        &mut body,
+        (clazz.access_flags & JVM_RECOGNIZED_CLASS_MODIFIERS) as u16,
+    );
+
+    let name = JSymbol::from_native(source, clazz.symbol)?.to_string(source);
+    put_u2(&mut body, pool.class(&name)?);
+
+    if clazz.super_klass.is_null() {
+        put_u2(&mut body, 0);
+    } else {
+        let super_name = class_name(source, clazz.super_klass)?;
+        put_u2(&mut body, pool.class(&super_name)?);
+    }
+
+    let interfaces = if clazz._local_interfaces.is_null() {
+        Vec::new()
+    } else {
+        let interfaces = JArray::<*mut JClass>::from_native(source, clazz._local_interfaces as _)?;
+
+        (0..interfaces.lenght)
+            .map(|i| interfaces.at(i, source).unwrap_or(std::ptr::null_mut()))
+            .collect::<Vec<*mut JClass>>()
+    };
+
+    put_u2(&mut body, interfaces.len() as u16);
+    for interface in interfaces {
+        let interface_name = class_name(source, interface)?;
+        put_u2(&mut body, pool.class(&interface_name)?);
+    }
+
+    write_fields(source, clazz, &mut pool, &mut body)?;
+    write_methods(source, clazz, cache.as_ref(), &mut pool, &mut body)?;
+
+    let mut attributes: Vec<u8> = Vec::new();
+    let mut attribute_count = 0u16;
+
+    if constant_pool.source_file_name_index != 0 {
+        pool.attribute(
+            &mut attributes,
+            "SourceFile",
+            &constant_pool.source_file_name_index.to_be_bytes(),
+        )?;
+        attribute_count += 1;
+    }
+
+    if constant_pool.generic_signature_index != 0 {
+        pool.attribute(
+            &mut attributes,
+            "Signature",
+            &constant_pool.generic_signature_index.to_be_bytes(),
+        )?;
+        attribute_count += 1;
+    }
+
+    let bootstrap_methods = constant_pool.bootstrap_methods(source);
+    if !bootstrap_methods.is_empty() {
+        let mut data = Vec::new();
+        put_u2(&mut data, bootstrap_methods.len() as u16);
+
+        for bootstrap_method in bootstrap_methods {
+            put_u2(&mut data, bootstrap_method.method_handle_index);
+            put_u2(&mut data, bootstrap_method.arguments.len() as u16);
+
+            for argument in bootstrap_method.arguments {
+                put_u2(&mut data, argument);
+            }
+        }
+
+        pool.attribute(&mut attributes, "BootstrapMethods", &data)?;
+        attribute_count += 1;
+    }
+
+    put_u2(&mut body, attribute_count);
+    body.extend(attributes);
+
+    let mut class_file = Vec::new();
+    put_u4(&mut class_file, MAGIC);
+    put_u2(&mut class_file, constant_pool.minor);
+    put_u2(&mut class_file, constant_pool.major);
+    put_u2(&mut class_file, pool.count);
+    class_file.extend(&pool.bytes);
+    class_file.extend(body);
+
+    Ok(class_file)
+}
+
+fn write_fields(
+    source: &dyn MemorySource,
+    clazz: &JClass,
+    pool: &mut PoolWriter,
+    out: &mut Vec<u8>,
+) -> Result<(), ClassFileError> {
+    let fields = fieldinfo::declared_fields(source, clazz)?;
+
+    put_u2(out, fields.len() as u16);
+
+    for field in fields {
+        put_u2(out, field.access_flags & JVM_RECOGNIZED_FIELD_MODIFIERS);
+        put_u2(out, field.name_index);
+        put_u2(out, field.signature_index);
+
+        let mut attributes: Vec<u8> = Vec::new();
+        let mut attribute_count = 0u16;
+
+        if field.access_flags & JVM_ACC_STATIC != 0 && field.initval_index != 0 {
+            pool.attribute(
+                &mut attributes,
+                "ConstantValue",
+                &field.initval_index.to_be_bytes(),
+            )?;
+            attribute_count += 1;
+        }
+
+        if field.generic_signature_index != 0 {
+            pool.attribute(
+                &mut attributes,
+                "Signature",
+                &field.generic_signature_index.to_be_bytes(),
+            )?;
+            attribute_count += 1;
+        }
+
+        put_u2(out, attribute_count);
+        out.extend(attributes);
+    }
+
+    Ok(())
+}
+
+fn write_methods(
+    source: &dyn MemorySource,
+    clazz: &JClass,
+    cache: Option<&bytecode::ConstantPoolCache>,
+    pool: &mut PoolWriter,
+    out: &mut Vec<u8>,
+) -> Result<(), ClassFileError> {
+    let mut methods: Vec<(JMethod, JConstMethod)> = Vec::new();
+
+    if !clazz.methods.is_null() {
+        let methods_array = JArray::<*mut JMethod>::from_native(source, clazz.methods as _)?;
+
+        for i in 0..methods_array.lenght {
+            let method = methods_array
+                .at(i, source)
+                .ok_or_else(|| malformed("method past the methods array"))?;
+
+            let method = JMethod::from_native(source, method)?;
+            let const_method = JConstMethod::from_native(source, method.const_method)?;
+
+            if !const_method.is_overpass() {
+                methods.push((method, const_method));
+            }
+        }
+    }
+
+    put_u2(out, methods.len() as u16);
+
+    for (method, const_method) in methods {
+        put_u2(
+            out,
+            (method.access_flags & JVM_RECOGNIZED_METHOD_MODIFIERS) as u16,
+        );
+        put_u2(out, const_method.name_index);
+        put_u2(out, const_method.signature_index);
+
+        let mut attributes: Vec<u8> = Vec::new();
+        let mut attribute_count = 0u16;
+
+        if method.access_flags & (JVM_ACC_NATIVE | JVM_ACC_ABSTRACT) == 0 {
+            let code = write_code(source, &const_method, cache, pool)?;
+
+            pool.attribute(&mut attributes, "Code", &code)?;
+            attribute_count += 1;
+        }
+
+        let checked_exceptions = const_method.checked_exceptions(source)?;
+        if !checked_exceptions.is_empty() {
+            let mut data = Vec::new();
+            put_u2(&mut data, checked_exceptions.len() as u16);
+
+            for exception in checked_exceptions {
+                put_u2(&mut data, exception);
+            }
+
+            pool.attribute(&mut attributes, "Exceptions", &data)?;
+            attribute_count += 1;
+        }
+
+        if let Some(signature) = const_method.generic_signature_index(source)? {
+            pool.attribute(&mut attributes, "Signature", &signature.to_be_bytes())?;
+            attribute_count += 1;
+        }
+
+        put_u2(out, attribute_count);
+        out.extend(attributes);
+    }
+
+    Ok(())
+}
+
+/// The body of the Code attribute of a method
+fn write_code(
+    source: &dyn MemorySource,
+    const_method: &JConstMethod,
+    cache: Option<&bytecode::ConstantPoolCache>,
+    pool: &mut PoolWriter,
+) -> Result<Vec<u8>, ClassFileError> {
+    let code = bytecode::restore(source, &const_method.code(source)?, cache)?;
+
+    let mut data = Vec::new();
+    put_u2(&mut data, const_method.max_stack);
+    put_u2(&mut data, const_method.max_locals);
+    put_u4(&mut data, code.len() as u32);
+    data.extend(code);
+
+    let exception_table = const_method.exception_table(source)?;
+    put_u2(&mut data, exception_table.len() as u16);
+
+    for entry in exception_table {
+        put_u2(&mut data, entry.start_pc);
+        put_u2(&mut data, entry.end_pc);
+        put_u2(&mut data, entry.handler_pc);
+        put_u2(&mut data, entry.catch_type_index);
+    }
+
+    let stackmap = const_method.stackmap_data(source)?;
+    if stackmap.is_empty() {
+        put_u2(&mut data, 0);
+    } else {
+        put_u2(&mut data, 1);
+        pool.attribute(&mut data, "StackMapTable", &stackmap)?;
+    }
+
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::api::sdk::{
+        constantpool::*,
+        testing::{field_info, FakeMethod, JvmImage},
+    };
+
+    /// Just enough of a class file reader to look at what we wrote
+    struct Reader<'a> {
+        data: &'a [u8],
+        at: usize,
+    }
+
+    type Attributes = Vec<(String, Vec<u8>)>;
+
+    struct Member {
+        access_flags: u16,
+        name: String,
+        attributes: Attributes,
+    }
+
+    impl<'a> Reader<'a> {
+        fn bytes(&mut self, count: usize) -> &'a [u8] {
+            self.at += count;
+            &self.data[self.at - count..self.at]
+        }
+
+        fn u2(&mut self) -> u16 {
+            let bytes = self.bytes(2);
+            u16::from_be_bytes([bytes[0], bytes[1]])
+        }
+
+        fn u4(&mut self) -> u32 {
+            let bytes = self.bytes(4);
+            u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+        }
+
+        fn attributes(&mut self, utf8: &HashMap<u16, String>) -> Attributes {
+            (0..self.u2())
+                .map(|_| {
+                    let name = utf8[&self.u2()].clone();
+                    let length = self.u4() as usize;
+
+                    (name, self.bytes(length).to_vec())
+                })
+                .collect()
+        }
+
+        fn members(&mut self, utf8: &HashMap<u16, String>) -> Vec<Member> {
+            (0..self.u2())
+                .map(|_| {
+                    let access_flags = self.u2();
+                    let name = utf8[&self.u2()].clone();
+                    self.u2();
+
+                    Member {
+                        access_flags,
+                        name,
+                        attributes: self.attributes(utf8),
+                    }
+                })
+                .collect()
+        }
+    }
+
+    #[test]
+    fn writes_loaded_class() {
+        let mut image = JvmImage::new(0x7f00_0000, 0x4000);
+
+        let object = image.class("java/lang/Object", 0, &[]);
+
+        let slots = |image: &mut JvmImage| {
+            vec![
+                (JVM_CONSTANT_INVALID, 0),
+                // a CPSlot, the Symbol* tagged as unresolved
+                (JVM_CONSTANT_UNRESOLVED_CLASS, image.symbol(b"ave") | 1),
+                (JVM_CONSTANT_UTF8, image.symbol(b"ave")),
+                (JVM_CONSTANT_CLASS, object),
+                (JVM_CONSTANT_UTF8, image.symbol(b"theMinecraft")),
+                (JVM_CONSTANT_UTF8, image.symbol(b"Lave;")),
+                (JVM_CONSTANT_NAME_AND_TYPE, 5 << 16 | 4),
+                (JVM_CONSTANT_FIELDREF, 6 << 16 | 1),
+                (JVM_CONSTANT_UTF8, image.symbol(b"getMinecraft")),
+                (JVM_CONSTANT_UTF8, image.symbol(b"()Lave;")),
+                (JVM_CONSTANT_LONG, 7),
+                (JVM_CONSTANT_INVALID, 0),
+                (JVM_CONSTANT_STRING, image.symbol(b"hi")),
+                (JVM_CONSTANT_UTF8, image.symbol(b"hi")),
+                (JVM_CONSTANT_UTF8, image.symbol(b"Ave.java")),
+            ]
+        };
+        let slots = slots(&mut image);
+        let constant_pool = image.tagged_constant_pool(&slots);
+
+        // entry 1 of the cache is the getstatic, resolved reference 0 the string
+        let cache = image.constant_pool_cache(&[0, 7], &[12]);
+        image.write_field(constant_pool, "ConstantPool::_cache", &cache);
+        image.write_field(constant_pool, "ConstantPool::_major_version", &52u16);
+        image.write_field(
+            constant_pool,
+            "ConstantPool::_source_file_name_index",
+            &14u16,
+        );
+
+        let method = image.method(
+            8,
+            9,
+            &FakeMethod {
+                name: "getMinecraft",
+                sig: "()Lave;",
+                access_flags: 0x0009,
+                max_stack: 1,
+                max_locals: 0,
+                // _fast_aldc, pop, getstatic with a native cache index, areturn
+                code: &[230, 0x00, 0x57, 0xb2, 0x01, 0x00, 0xb0],
+                exception_table: &[[0, 3, 6, 3]],
+            },
+        );
+
+        // static plus HotSpot's JVM_ACC_FIELD_INTERNAL
+        let mut field = field_info(4, 5, 0x70);
+        field[0] = 0x0008 | 0x0400;
+
+        let clazz = JClass {
+            symbol: image.symbol(b"ave") as _,
+            super_klass: object as _,
+            // public, super plus JVM_ACC_HAS_FINALIZER
+            access_flags: 0x0021 | 0x4000_0000,
+            constant_pool: constant_pool as _,
+            methods: image.array(&[method]) as _,
+            fields: image.fields(&[field]) as _,
+            ..Default::default()
+        };
+        let clazz = image.class_from(&clazz);
+        let clazz = JClass::from_native(&image, clazz as _).unwrap();
+
+        let class_file = write_class(&image, &clazz).unwrap();
+        let mut reader = Reader {
+            data: &class_file,
+            at: 0,
+        };
+
+        assert_eq!(reader.u4(), MAGIC);
+        assert_eq!(reader.u2(), 0);
+        assert_eq!(reader.u2(), 52);
+
+        // the name of java/lang/Object and the attribute names are added to the pool
+        let count = reader.u2();
+        assert!(count > 15);
+
+        let mut utf8 = HashMap::new();
+        let mut which = 1;
+        while which < count {
+            let tag = reader.bytes(1)[0];
+
+            match tag {
+                1 => {
+                    let length = reader.u2() as usize;
+                    let text = String::from_utf8(reader.bytes(length).to_vec()).unwrap();
+                    utf8.insert(which, text);
+                }
+                5 | 6 => {
+                    assert_eq!(reader.bytes(8), &7u64.to_be_bytes());
+                    which += 1;
+                }
+                7 | 8 => {
+                    reader.u2();
+                }
+                9..=12 => {
+                    reader.u4();
+                }
+                tag => panic!("unexpected tag {}", tag),
+            }
+
+            which += 1;
+        }
+
+        // access flags, this and super class, no interfaces
+        assert_eq!(reader.u2(), 0x0021);
+        assert_eq!(reader.u2(), 1);
+        assert_eq!(reader.u2(), 3);
+        assert_eq!(reader.u2(), 0);
+
+        let fields = reader.members(&utf8);
+        assert_eq!(fields.len(), 1);
+        assert_eq!(fields[0].name, "theMinecraft");
+        assert_eq!(fields[0].access_flags, 0x0008);
+
+        let methods = reader.members(&utf8);
+        assert_eq!(methods.len(), 1);
+        assert_eq!(methods[0].name, "getMinecraft");
+        assert_eq!(methods[0].attributes[0].0, "Code");
+
+        let code = &methods[0].attributes[0].1;
+        // max_stack, max_locals, code_length
+        assert_eq!(&code[..8], &[0, 1, 0, 0, 0, 0, 0, 7]);
+        assert_eq!(&code[8..15], &[0x12, 12, 0x57, 0xb2, 0, 7, 0xb0]);
+        // one exception table entry, no attributes
+        assert_eq!(&code[15..], &[0, 1, 0, 0, 0, 3, 0, 6, 0, 3, 0, 0]);
+
+        let attributes = reader.attributes(&utf8);
+        assert_eq!(attributes, vec![("SourceFile".to_string(), vec![0, 14])]);
+        assert_eq!(reader.at, class_file.len());
+    }
+
+    #[test]
+    fn encodes_modified_utf8() {
+        assert_eq!(modified_utf8("ave"), b"ave".to_vec());
+        assert_eq!(modified_utf8("\0"), vec![0xC0, 0x80]);
+        assert_eq!(
+            modified_utf8("\u{1F600}"),
+            vec![0xED, 0xA0, 0xBD, 0xED, 0xB8, 0x80]
+        );
+    }
+
+    #[test]
+    fn runs_out_of_constant_pool() {
+        // a class whose pool is as big as they get, naming the first attribute overflows it
+        let mut pool = PoolWriter {
+            bytes: Vec::new(),
+            count: u16::MAX,
+            utf8: HashMap::new(),
+            classes: HashMap::new(),
+        };
+
+        assert!(matches!(
+            pool.attribute(&mut Vec::new(), "SourceFile", &[0, 1]),
+            Err(ClassFileError::Malformed(_))
+        ));
+        assert!(matches!(
+            pool.class("java/lang/Object"),
+            Err(ClassFileError::Malformed(_))
+        ));
+    }
+}
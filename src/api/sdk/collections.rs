@@ -0,0 +1,494 @@
+//! java.util collections in the target, read through their fields the way the JDK declares
+//! them. The readers hand out the references the collection holds, 0 standing for null.
+//!
+//! Collections change while we read them, so chains of nodes are followed until they end or
+//! come back around, and an ArrayList never yields more than its array holds.
+
+use std::collections::HashSet;
+
+use crate::api::processes::MemorySource;
+
+use super::{
+    java::JavaArray,
+    remote::{RemoteError, RemoteObject},
+};
+
+// ConcurrentHashMap marks the bins that aren't plain nodes with a negative hash
+
+/// A ForwardingNode, the bin moved to `nextTable` while the table grows
+const MOVED: i32 = -1;
+
+/// A TreeBin, the nodes hang off its `first` field
+const TREEBIN: i32 = -2;
+
+/// A ReservationNode, holding the bin while `computeIfAbsent` works out a value
+const RESERVED: i32 = -3;
+
+/// The references an Object[] (or any other reference array) holds
+fn references(source: &dyn MemorySource, array: usize) -> Result<Vec<usize>, RemoteError> {
+    JavaArray::<usize>::read(source, array)?.to_vec(source)
+}
+
+/// The objects from `first` on, each pointing at the following one through its field `next`
+fn chain(
+    source: &dyn MemorySource,
+    first: Option<RemoteObject>,
+    next: &str,
+) -> Result<Vec<RemoteObject>, RemoteError> {
+    let mut seen = HashSet::new();
+    let mut nodes = Vec::new();
+
+    let mut node = first;
+    while let Some(current) = node {
+        if !seen.insert(current.address) {
+            break;
+        }
+
+        node = current.get_object(source, next)?;
+        nodes.push(current);
+    }
+
+    Ok(nodes)
+}
+
+/// The elements of an ArrayList, or a Vector which keeps its size as `elementCount`
+pub fn array_list(
+    source: &dyn MemorySource,
+    list: &RemoteObject,
+) -> Result<Vec<usize>, RemoteError> {
+    let size = if list.is_instance_of(source, "java/util/Vector")? {
+        list.get_int(source, "elementCount")?
+    } else {
+        list.get_int(source, "size")?
+    };
+
+    // elementData has room to grow, only the first `size` elements are in the list
+    match list.get_reference(source, "elementData")? {
+        0 => Ok(Vec::new()),
+        element_data => {
+            let element_data = JavaArray::<usize>::read(source, element_data)?;
+            let size = (size.max(0) as usize).min(element_data.len());
+
+            element_data.read_range(source, 0..size)
+        }
+    }
+}
+
+/// The elements of a LinkedList, from first to last
+pub fn linked_list(
+    source: &dyn MemorySource,
+    list: &RemoteObject,
+) -> Result<Vec<usize>, RemoteError> {
+    chain(source, list.get_object(source, "first")?, "next")?
+        .iter()
+        .map(|node| node.get_reference(source, "item"))
+        .collect()
+}
+
+/// The keys and values of a HashMap (or LinkedHashMap), bin by bin. A bin that grew into a tree
+/// keeps its TreeNodes chained through `next` as well, so every bin is walked the same way.
+pub fn hash_map(
+    source: &dyn MemorySource,
+    map: &RemoteObject,
+) -> Result<Vec<(usize, usize)>, RemoteError> {
+    let table = match map.get_reference(source, "table")? {
+        0 => return Ok(Vec::new()),
+        table => references(source, table)?,
+    };
+
+    let mut entries = Vec::new();
+
+    for bin in table.into_iter().filter(|&bin| bin != 0) {
+        for node in chain(source, Some(RemoteObject::new(source, bin)?), "next")? {
+            entries.push((
+                node.get_reference(source, "key")?,
+                node.get_reference(source, "value")?,
+            ));
+        }
+    }
+
+    Ok(entries)
+}
+
+/// The elements of a HashSet (or LinkedHashSet), the keys of the map behind it
+pub fn hash_set(source: &dyn MemorySource, set: &RemoteObject) -> Result<Vec<usize>, RemoteError> {
+    match set.get_object(source, "map")? {
+        Some(map) => Ok(hash_map(source, &map)?
+            .into_iter()
+            .map(|(key, _)| key)
+            .collect()),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// The nodes of the ConcurrentHashMap bin at `index` of a table `length` bins long
+fn concurrent_bin(
+    source: &dyn MemorySource,
+    bin: usize,
+    index: usize,
+    length: usize,
+) -> Result<Vec<RemoteObject>, RemoteError> {
+    if bin == 0 {
+        return Ok(Vec::new());
+    }
+
+    let bin = RemoteObject::new(source, bin)?;
+
+    match bin.get_int(source, "hash")? {
+        // the bin was split between the same index and the one a table length further
+        MOVED => {
+            let next_table = references(source, bin.get_reference(source, "nextTable")?)?;
+
+            let mut nodes = Vec::new();
+            for &index in &[index, index + length] {
+                if let Some(&moved) = next_table.get(index) {
+                    nodes.extend(concurrent_bin(source, moved, index, next_table.len())?);
+                }
+            }
+
+            Ok(nodes)
+        }
+        TREEBIN => chain(source, bin.get_object(source, "first")?, "next"),
+        RESERVED => Ok(Vec::new()),
+        _ => chain(source, Some(bin), "next"),
+    }
+}
+
+/// The keys and values of a ConcurrentHashMap, whatever shape its bins are in
+pub fn concurrent_hash_map(
+    source: &dyn MemorySource,
+    map: &RemoteObject,
+) -> Result<Vec<(usize, usize)>, RemoteError> {
+    let table = match map.get_reference(source, "table")? {
+        0 => return Ok(Vec::new()),
+        table => references(source, table)?,
+    };
+
+    let mut entries = Vec::new();
+
+    for (index, &bin) in table.iter().enumerate() {
+        for node in concurrent_bin(source, bin, index, table.len())? {
+            entries.push((
+                node.get_reference(source, "key")?,
+                node.get_reference(source, "val")?,
+            ));
+        }
+    }
+
+    Ok(entries)
+}
+
+/// The elements of the List at `list`, an ArrayList, Vector or LinkedList
+pub fn list_elements(source: &dyn MemorySource, list: usize) -> Result<Vec<usize>, RemoteError> {
+    let list = RemoteObject::new(source, list)?;
+
+    if list.is_instance_of(source, "java/util/ArrayList")?
+        || list.is_instance_of(source, "java/util/Vector")?
+    {
+        array_list(source, &list)
+    } else if list.is_instance_of(source, "java/util/LinkedList")? {
+        linked_list(source, &list)
+    } else {
+        Err(RemoteError::WrongClass {
+            expected: "java/util/List",
+            found: list.class_name(source)?,
+        })
+    }
+}
+
+/// The keys and values of the Map at `map`, a HashMap, LinkedHashMap or ConcurrentHashMap
+#[allow(dead_code)]
+pub fn map_entries(
+    source: &dyn MemorySource,
+    map: usize,
+) -> Result<Vec<(usize, usize)>, RemoteError> {
+    let map = RemoteObject::new(source, map)?;
+
+    if map.is_instance_of(source, "java/util/HashMap")? {
+        hash_map(source, &map)
+    } else if map.is_instance_of(source, "java/util/concurrent/ConcurrentHashMap")? {
+        concurrent_hash_map(source, &map)
+    } else {
+        Err(RemoteError::WrongClass {
+            expected: "java/util/Map",
+            found: map.class_name(source)?,
+        })
+    }
+}
+
+/// The elements of the Set at `set`, a HashSet or LinkedHashSet
+#[allow(dead_code)]
+pub fn set_elements(source: &dyn MemorySource, set: usize) -> Result<Vec<usize>, RemoteError> {
+    let set = RemoteObject::new(source, set)?;
+
+    if set.is_instance_of(source, "java/util/HashSet")? {
+        hash_set(source, &set)
+    } else {
+        Err(RemoteError::WrongClass {
+            expected: "java/util/Set",
+            found: set.class_name(source)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::api::sdk::testing::{field, JvmImage};
+
+    /// An array of `klass` holding the narrow references to `elements`
+    fn object_array(image: &mut JvmImage, klass: usize, elements: &[usize]) -> usize {
+        let array = image.instance(klass, 16 + elements.len() * 4);
+
+        image.write(array + 12, &(elements.len() as i32));
+        for (index, &element) in elements.iter().enumerate() {
+            image.write(array + 16 + index * 4, &(element as u32));
+        }
+
+        array
+    }
+
+    /// A node of `klass` with its key, value and next at 0x0c, 0x10 and 0x14 and its hash at 0x18
+    fn node(
+        image: &mut JvmImage,
+        klass: usize,
+        hash: i32,
+        key: usize,
+        value: usize,
+        next: usize,
+    ) -> usize {
+        let node = image.instance(klass, 0x20);
+
+        image.write(node + 0x0c, &(key as u32));
+        image.write(node + 0x10, &(value as u32));
+        image.write(node + 0x14, &(next as u32));
+        image.write(node + 0x18, &hash);
+
+        node
+    }
+
+    #[test]
+    fn reads_lists() {
+        let mut image = JvmImage::new(0x7f00_0000, 0x8000);
+
+        let object = image.class("java/lang/Object", 0, &[]);
+        let objects = image.class("[Ljava/lang/Object;", 0, &[]);
+        let array_list = image.class(
+            "java/util/ArrayList",
+            object,
+            &[
+                field("size", "I", 0x10),
+                field("elementData", "[Ljava/lang/Object;", 0x14),
+            ],
+        );
+        // how the game hides its lists
+        let player_list = image.class("bjf$1", array_list, &[]);
+        let linked_list = image.class(
+            "java/util/LinkedList",
+            object,
+            &[
+                field("size", "I", 0x0c),
+                field("first", "Ljava/util/LinkedList$Node;", 0x10),
+                field("last", "Ljava/util/LinkedList$Node;", 0x14),
+            ],
+        );
+        let linked_node = image.class(
+            "java/util/LinkedList$Node",
+            object,
+            &[
+                field("item", "Ljava/lang/Object;", 0x0c),
+                field("next", "Ljava/util/LinkedList$Node;", 0x10),
+                field("prev", "Ljava/util/LinkedList$Node;", 0x14),
+            ],
+        );
+
+        let players = [image.instance(object, 0x10), image.instance(object, 0x10)];
+
+        // room for four, two of them taken
+        let element_data = object_array(&mut image, objects, &[players[0], players[1], 0, 0]);
+        let list = image.instance(player_list, 0x18);
+        image.write(list + 0x10, &2i32);
+        image.write(list + 0x14, &(element_data as u32));
+
+        assert_eq!(list_elements(&image, list), Ok(players.to_vec()));
+
+        let second = image.instance(linked_node, 0x18);
+        image.write(second + 0x0c, &(players[1] as u32));
+        let first = image.instance(linked_node, 0x18);
+        image.write(first + 0x0c, &(players[0] as u32));
+        image.write(first + 0x10, &(second as u32));
+
+        let list = image.instance(linked_list, 0x18);
+        image.write(list + 0x0c, &2i32);
+        image.write(list + 0x10, &(first as u32));
+
+        assert_eq!(list_elements(&image, list), Ok(players.to_vec()));
+
+        // a list caught halfway through a change doesn't go around forever
+        image.write(second + 0x10, &(first as u32));
+        assert_eq!(list_elements(&image, list), Ok(players.to_vec()));
+
+        assert_eq!(
+            list_elements(&image, players[0]),
+            Err(RemoteError::WrongClass {
+                expected: "java/util/List",
+                found: "java/lang/Object".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn reads_hash_maps_and_sets() {
+        let mut image = JvmImage::new(0x7f00_0000, 0x8000);
+
+        let object = image.class("java/lang/Object", 0, &[]);
+        let nodes = image.class("[Ljava/util/HashMap$Node;", 0, &[]);
+        let hash_map = image.class(
+            "java/util/HashMap",
+            object,
+            &[field("table", "[Ljava/util/HashMap$Node;", 0x0c)],
+        );
+        let linked_hash_map = image.class("java/util/LinkedHashMap", hash_map, &[]);
+        let hash_set = image.class(
+            "java/util/HashSet",
+            object,
+            &[field("map", "Ljava/util/HashMap;", 0x0c)],
+        );
+        let node_class = image.class(
+            "java/util/HashMap$Node",
+            object,
+            &[
+                field("key", "Ljava/lang/Object;", 0x0c),
+                field("value", "Ljava/lang/Object;", 0x10),
+                field("next", "Ljava/util/HashMap$Node;", 0x14),
+                field("hash", "I", 0x18),
+            ],
+        );
+        // a treeified bin, still chained through next
+        let entry = image.class("java/util/LinkedHashMap$Entry", node_class, &[]);
+        let tree_node = image.class("java/util/HashMap$TreeNode", entry, &[]);
+
+        let keys = [0, 1, 2, 3].map(|_| image.instance(object, 0x10));
+        let values = [0, 1, 2, 3].map(|_| image.instance(object, 0x10));
+
+        let third = node(&mut image, tree_node, 2, keys[3], values[3], 0);
+        let tree = node(&mut image, tree_node, 2, keys[2], values[2], third);
+        let chained = node(&mut image, node_class, 0, keys[1], 0, 0);
+        let bin = node(&mut image, node_class, 0, keys[0], values[0], chained);
+        let table = object_array(&mut image, nodes, &[bin, 0, tree, 0]);
+
+        let map = image.instance(linked_hash_map, 0x10);
+        image.write(map + 0x0c, &(table as u32));
+
+        assert_eq!(
+            map_entries(&image, map),
+            Ok(vec![
+                (keys[0], values[0]),
+                (keys[1], 0),
+                (keys[2], values[2]),
+                (keys[3], values[3]),
+            ])
+        );
+
+        let set = image.instance(hash_set, 0x10);
+        image.write(set + 0x0c, &(map as u32));
+        assert_eq!(set_elements(&image, set), Ok(keys.to_vec()));
+
+        // nothing was put in yet
+        let empty = image.instance(hash_map, 0x10);
+        assert_eq!(map_entries(&image, empty), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn reads_concurrent_hash_maps() {
+        let mut image = JvmImage::new(0x7f00_0000, 0x8000);
+
+        let object = image.class("java/lang/Object", 0, &[]);
+        let nodes = image.class("[Ljava/util/concurrent/ConcurrentHashMap$Node;", 0, &[]);
+        let map_class = image.class(
+            "java/util/concurrent/ConcurrentHashMap",
+            object,
+            &[field(
+                "table",
+                "[Ljava/util/concurrent/ConcurrentHashMap$Node;",
+                0x0c,
+            )],
+        );
+        let node_class = image.class(
+            "java/util/concurrent/ConcurrentHashMap$Node",
+            object,
+            &[
+                field("key", "Ljava/lang/Object;", 0x0c),
+                field("val", "Ljava/lang/Object;", 0x10),
+                field(
+                    "next",
+                    "Ljava/util/concurrent/ConcurrentHashMap$Node;",
+                    0x14,
+                ),
+                field("hash", "I", 0x18),
+            ],
+        );
+        let tree_bin = image.class(
+            "java/util/concurrent/ConcurrentHashMap$TreeBin",
+            node_class,
+            &[field(
+                "first",
+                "Ljava/util/concurrent/ConcurrentHashMap$TreeNode;",
+                0x1c,
+            )],
+        );
+        let tree_node = image.class(
+            "java/util/concurrent/ConcurrentHashMap$TreeNode",
+            node_class,
+            &[],
+        );
+        let forwarding = image.class(
+            "java/util/concurrent/ConcurrentHashMap$ForwardingNode",
+            node_class,
+            &[field(
+                "nextTable",
+                "[Ljava/util/concurrent/ConcurrentHashMap$Node;",
+                0x1c,
+            )],
+        );
+        let reservation = image.class(
+            "java/util/concurrent/ConcurrentHashMap$ReservationNode",
+            node_class,
+            &[],
+        );
+
+        let keys = [0, 1, 2, 3].map(|_| image.instance(object, 0x10));
+        let values = [0, 1, 2, 3].map(|_| image.instance(object, 0x10));
+
+        let plain = node(&mut image, node_class, 4, keys[0], values[0], 0);
+
+        let second = node(&mut image, tree_node, 5, keys[2], values[2], 0);
+        let first = node(&mut image, tree_node, 5, keys[1], values[1], second);
+        let tree = node(&mut image, tree_bin, TREEBIN, 0, 0, 0);
+        image.write(tree + 0x1c, &(first as u32));
+
+        // bin 1 already moved, into bins 1 and 5 of the table twice the size
+        let moved = node(&mut image, node_class, 7, keys[3], values[3], 0);
+        let next_table = object_array(&mut image, nodes, &[0, 0, 0, 0, 0, moved, 0, 0]);
+        let forward = image.instance(forwarding, 0x20);
+        image.write(forward + 0x18, &MOVED);
+        image.write(forward + 0x1c, &(next_table as u32));
+
+        let reserved = node(&mut image, reservation, RESERVED, 0, 0, 0);
+
+        let table = object_array(&mut image, nodes, &[plain, forward, tree, reserved]);
+        let map = image.instance(map_class, 0x10);
+        image.write(map + 0x0c, &(table as u32));
+
+        assert_eq!(
+            map_entries(&image, map),
+            Ok(vec![
+                (keys[0], values[0]),
+                (keys[3], values[3]),
+                (keys[1], values[1]),
+                (keys[2], values[2]),
+            ])
+        );
+    }
+}
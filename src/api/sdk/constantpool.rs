@@ -0,0 +1,468 @@
+//! Typed entries of a `ConstantPool`. Which kind of entry a slot holds is only known from the
+//! pool's tags array, the slot itself is a pointer or some packed shorts depending on the tag.
+
+use crate::api::processes::{self, MemorySource};
+
+use super::{FromNative, JArray, JClass, JConstantPool, JSymbol};
+
+// tags as in the class file
+pub const JVM_CONSTANT_UTF8: u8 = 1;
+pub const JVM_CONSTANT_INTEGER: u8 = 3;
+pub const JVM_CONSTANT_FLOAT: u8 = 4;
+pub const JVM_CONSTANT_LONG: u8 = 5;
+pub const JVM_CONSTANT_DOUBLE: u8 = 6;
+pub const JVM_CONSTANT_CLASS: u8 = 7;
+pub const JVM_CONSTANT_STRING: u8 = 8;
+pub const JVM_CONSTANT_FIELDREF: u8 = 9;
+pub const JVM_CONSTANT_METHODREF: u8 = 10;
+pub const JVM_CONSTANT_INTERFACE_METHODREF: u8 = 11;
+pub const JVM_CONSTANT_NAME_AND_TYPE: u8 = 12;
+pub const JVM_CONSTANT_METHOD_HANDLE: u8 = 15;
+pub const JVM_CONSTANT_METHOD_TYPE: u8 = 16;
+pub const JVM_CONSTANT_DYNAMIC: u8 = 17;
+pub const JVM_CONSTANT_INVOKE_DYNAMIC: u8 = 18;
+pub const JVM_CONSTANT_MODULE: u8 = 19;
+pub const JVM_CONSTANT_PACKAGE: u8 = 20;
+
+// tags HotSpot only uses internally
+pub const JVM_CONSTANT_INVALID: u8 = 0;
+pub const JVM_CONSTANT_UNRESOLVED_CLASS: u8 = 100;
+pub const JVM_CONSTANT_UNRESOLVED_CLASS_IN_ERROR: u8 = 103;
+pub const JVM_CONSTANT_METHOD_HANDLE_IN_ERROR: u8 = 104;
+pub const JVM_CONSTANT_METHOD_TYPE_IN_ERROR: u8 = 105;
+pub const JVM_CONSTANT_DYNAMIC_IN_ERROR: u8 = 106;
+
+/// One constant pool entry, indices point at other entries of the same pool
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConstantPoolEntry {
+    /// Index 0 and the slot after a Long or Double
+    Invalid,
+    Utf8(String),
+    Integer(i32),
+    Float(f32),
+    Long(i64),
+    Double(f64),
+    /// A class that has been loaded, `klass` is its `Klass*`
+    Class { name: String, klass: *mut JClass },
+    /// A class that hasn't been loaded (yet), or failed to load if `in_error`
+    UnresolvedClass { name: String, in_error: bool },
+    String(String),
+    Fieldref {
+        class_index: u16,
+        name_and_type_index: u16,
+    },
+    Methodref {
+        class_index: u16,
+        name_and_type_index: u16,
+    },
+    InterfaceMethodref {
+        class_index: u16,
+        name_and_type_index: u16,
+    },
+    NameAndType {
+        name_index: u16,
+        signature_index: u16,
+    },
+    MethodHandle { ref_kind: u8, ref_index: u16 },
+    MethodType { signature_index: u16 },
+    /// `bootstrap_index` is an index into `JConstantPool::bootstrap_methods`
+    Dynamic {
+        bootstrap_index: u16,
+        name_and_type_index: u16,
+    },
+    InvokeDynamic {
+        bootstrap_index: u16,
+        name_and_type_index: u16,
+    },
+    Module { name_index: u16 },
+    Package { name_index: u16 },
+    /// A tag we don't know what to do with
+    Unknown(u8),
+}
+
+/// One entry of the BootstrapMethods attribute, kept in the pool's operands
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BootstrapMethod {
+    pub method_handle_index: u16,
+    pub arguments: Vec<u16>,
+}
+
+impl JConstantPool {
+    /// The tag of entry `which`, `None` if it's out of bounds or the tags can't be read
+    pub fn tag(&self, source: &dyn MemorySource, which: i32) -> Option<u8> {
+        if which < 0 || which >= self.length {
+            return None;
+        }
+
+        JArray::from_native(source, self.tags as *mut JArray<u8>)
+            .ok()?
+            .at(which, source)
+    }
+
+    /// Every entry of the pool, index 0 included so entries can be indexed like in the class
+    /// file. Entries we can't read are `Invalid`.
+    #[allow(dead_code)]
+    pub fn entries(&self, source: &dyn MemorySource) -> Vec<ConstantPoolEntry> {
+        let tags = match JArray::from_native(source, self.tags as *mut JArray<u8>) {
+            Ok(tags) => tags,
+            Err(_) => return Vec::new(),
+        };
+
+        (0..self.length)
+            .map(|which| {
+                tags.at(which, source)
+                    .and_then(|tag| self.decode(source, which, tag))
+                    .unwrap_or(ConstantPoolEntry::Invalid)
+            })
+            .collect()
+    }
+
+    pub fn entry(&self, source: &dyn MemorySource, which: i32) -> Option<ConstantPoolEntry> {
+        let tag = self.tag(source, which)?;
+
+        self.decode(source, which, tag)
+    }
+
+    /// The bootstrap methods of the `Dynamic` and `InvokeDynamic` entries, empty if the class has
+    /// none
+    pub fn bootstrap_methods(&self, source: &dyn MemorySource) -> Vec<BootstrapMethod> {
+        let operands = match JArray::from_native(source, self.operands as *mut JArray<u16>) {
+            Ok(operands) => operands,
+            Err(_) => return Vec::new(),
+        };
+
+        // the operands start with an int offset per bootstrap method, the first one pointing
+        // right behind them
+        let offset_at = |idx: i32| -> Option<i32> {
+            let low = operands.at(idx * 2, source)?;
+            let high = operands.at(idx * 2 + 1, source)?;
+
+            Some((((high as u32) << 16) | low as u32) as i32)
+        };
+
+        let count = match offset_at(0) {
+            Some(first) => first / 2,
+            None => return Vec::new(),
+        };
+
+        (0..count)
+            .map_while(|idx| {
+                let offset = offset_at(idx)?;
+
+                let method_handle_index = operands.at(offset, source)?;
+                let argument_count = operands.at(offset + 1, source)? as i32;
+
+                let arguments = (0..argument_count)
+                    .map(|arg| operands.at(offset + 2 + arg, source))
+                    .collect::<Option<Vec<u16>>>()?;
+
+                Some(BootstrapMethod {
+                    method_handle_index,
+                    arguments,
+                })
+            })
+            .collect()
+    }
+
+    fn slot_address(&self, which: i32) -> usize {
+        self.base as usize + self.size() + which as usize * std::mem::size_of::<usize>()
+    }
+
+    fn slot<T>(&self, source: &dyn MemorySource, which: i32) -> Option<T> {
+        processes::try_read_exact::<T>(source, self.slot_address(which)).ok()
+    }
+
+    /// The slot as the two shorts HotSpot packs into the low int, low one first
+    fn shorts(&self, source: &dyn MemorySource, which: i32) -> Option<(u16, u16)> {
+        let value = self.slot::<u32>(source, which)?;
+
+        Some((value as u16, (value >> 16) as u16))
+    }
+
+    fn utf8(&self, source: &dyn MemorySource, which: i32) -> Option<String> {
+        self.symbol(source, which as _)
+            .map(|symbol| symbol.to_string(source))
+    }
+
+    fn decode(&self, source: &dyn MemorySource, which: i32, tag: u8) -> Option<ConstantPoolEntry> {
+        let entry = match tag {
+            JVM_CONSTANT_INVALID => ConstantPoolEntry::Invalid,
+            JVM_CONSTANT_UTF8 => ConstantPoolEntry::Utf8(self.utf8(source, which)?),
+            JVM_CONSTANT_INTEGER => ConstantPoolEntry::Integer(self.slot(source, which)?),
+            JVM_CONSTANT_FLOAT => ConstantPoolEntry::Float(self.slot(source, which)?),
+            JVM_CONSTANT_LONG => ConstantPoolEntry::Long(self.slot(source, which)?),
+            JVM_CONSTANT_DOUBLE => ConstantPoolEntry::Double(self.slot(source, which)?),
+            JVM_CONSTANT_CLASS
+            | JVM_CONSTANT_UNRESOLVED_CLASS
+            | JVM_CONSTANT_UNRESOLVED_CLASS_IN_ERROR => self.class(source, which, tag)?,
+            JVM_CONSTANT_STRING => ConstantPoolEntry::String(self.utf8(source, which)?),
+            JVM_CONSTANT_FIELDREF | JVM_CONSTANT_METHODREF | JVM_CONSTANT_INTERFACE_METHODREF => {
+                let (class_index, name_and_type_index) = self.shorts(source, which)?;
+
+                match tag {
+                    JVM_CONSTANT_FIELDREF => ConstantPoolEntry::Fieldref {
+                        class_index,
+                        name_and_type_index,
+                    },
+                    JVM_CONSTANT_METHODREF => ConstantPoolEntry::Methodref {
+                        class_index,
+                        name_and_type_index,
+                    },
+                    _ => ConstantPoolEntry::InterfaceMethodref {
+                        class_index,
+                        name_and_type_index,
+                    },
+                }
+            }
+            JVM_CONSTANT_NAME_AND_TYPE => {
+                let (name_index, signature_index) = self.shorts(source, which)?;
+
+                ConstantPoolEntry::NameAndType {
+                    name_index,
+                    signature_index,
+                }
+            }
+            JVM_CONSTANT_METHOD_HANDLE | JVM_CONSTANT_METHOD_HANDLE_IN_ERROR => {
+                let (ref_kind, ref_index) = self.shorts(source, which)?;
+
+                ConstantPoolEntry::MethodHandle {
+                    ref_kind: ref_kind as u8,
+                    ref_index,
+                }
+            }
+            JVM_CONSTANT_METHOD_TYPE | JVM_CONSTANT_METHOD_TYPE_IN_ERROR => {
+                ConstantPoolEntry::MethodType {
+                    signature_index: self.shorts(source, which)?.0,
+                }
+            }
+            JVM_CONSTANT_DYNAMIC | JVM_CONSTANT_DYNAMIC_IN_ERROR | JVM_CONSTANT_INVOKE_DYNAMIC => {
+                let (bootstrap_index, name_and_type_index) = self.shorts(source, which)?;
+
+                if tag == JVM_CONSTANT_INVOKE_DYNAMIC {
+                    ConstantPoolEntry::InvokeDynamic {
+                        bootstrap_index,
+                        name_and_type_index,
+                    }
+                } else {
+                    ConstantPoolEntry::Dynamic {
+                        bootstrap_index,
+                        name_and_type_index,
+                    }
+                }
+            }
+            JVM_CONSTANT_MODULE => ConstantPoolEntry::Module {
+                name_index: self.shorts(source, which)?.0,
+            },
+            JVM_CONSTANT_PACKAGE => ConstantPoolEntry::Package {
+                name_index: self.shorts(source, which)?.0,
+            },
+            tag => ConstantPoolEntry::Unknown(tag),
+        };
+
+        Some(entry)
+    }
+
+    fn class(&self, source: &dyn MemorySource, which: i32, tag: u8) -> Option<ConstantPoolEntry> {
+        let resolved = tag == JVM_CONSTANT_CLASS;
+
+        if !self.klass_slots {
+            // JDK 8 keeps the Klass* of a resolved class in the slot, the name Symbol* otherwise,
+            // tagged with bit 0 to tell the two apart
+            if !resolved {
+                let symbol = self.slot::<usize>(source, which)? & !1;
+                let name = JSymbol::from_native(source, symbol as _).ok()?;
+
+                return Some(ConstantPoolEntry::UnresolvedClass {
+                    name: name.to_string(source),
+                    in_error: tag == JVM_CONSTANT_UNRESOLVED_CLASS_IN_ERROR,
+                });
+            }
+
+            let klass = self.slot::<usize>(source, which)?;
+            let clazz = JClass::from_native(source, klass as _).ok()?;
+            let name = JSymbol::from_native(source, clazz.symbol).ok()?;
+
+            return Some(ConstantPoolEntry::Class {
+                name: name.to_string(source),
+                klass: klass as _,
+            });
+        }
+
+        let (resolved_klass_index, name_index) = self.shorts(source, which)?;
+        let name = self.utf8(source, name_index as _)?;
+
+        if !resolved {
+            return Some(ConstantPoolEntry::UnresolvedClass {
+                name,
+                in_error: tag == JVM_CONSTANT_UNRESOLVED_CLASS_IN_ERROR,
+            });
+        }
+
+        let klass = JArray::from_native(source, self.resolved_klasses as *mut JArray<*mut JClass>)
+            .ok()?
+            .at(resolved_klass_index as _, source)?;
+
+        Some(ConstantPoolEntry::Class { name, klass })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::api::sdk::testing::JvmImage;
+
+    /// Two shorts packed into a slot the way HotSpot does, `low` first
+    fn shorts(low: u16, high: u16) -> usize {
+        ((high as usize) << 16) | low as usize
+    }
+
+    #[test]
+    fn decodes_tagged_entries() {
+        let mut image = JvmImage::new(0x7f00_0000, 0x2000);
+
+        let minecraft = image.class("ave", 0, &[]);
+        let name = image.symbol(b"getMinecraft");
+        let signature = image.symbol(b"()Lave;");
+        let title = image.symbol(b"Minecraft 1.8.9");
+        let session = image.symbol(b"avm");
+
+        let constant_pool = image.tagged_constant_pool(&[
+            (JVM_CONSTANT_INVALID, 0),
+            (JVM_CONSTANT_CLASS, minecraft),
+            (JVM_CONSTANT_UTF8, name),
+            (JVM_CONSTANT_UTF8, signature),
+            (JVM_CONSTANT_NAME_AND_TYPE, shorts(2, 3)),
+            (JVM_CONSTANT_METHODREF, shorts(1, 4)),
+            (JVM_CONSTANT_INTEGER, 1337),
+            (JVM_CONSTANT_LONG, -2i64 as usize),
+            (JVM_CONSTANT_INVALID, 0),
+            (JVM_CONSTANT_FLOAT, 1.5f32.to_bits() as usize),
+            (JVM_CONSTANT_STRING, title),
+            (JVM_CONSTANT_UNRESOLVED_CLASS, session | 1),
+            (JVM_CONSTANT_METHOD_HANDLE, shorts(6, 5)),
+            (JVM_CONSTANT_INVOKE_DYNAMIC, shorts(0, 4)),
+        ]);
+        let constant_pool = JConstantPool::from_native(&image, constant_pool as _).unwrap();
+
+        let entries = constant_pool.entries(&image);
+        assert_eq!(entries.len(), 14);
+        assert_eq!(entries[0], ConstantPoolEntry::Invalid);
+        assert_eq!(
+            entries[1],
+            ConstantPoolEntry::Class {
+                name: "ave".to_string(),
+                klass: minecraft as _,
+            }
+        );
+        assert_eq!(entries[2], ConstantPoolEntry::Utf8("getMinecraft".to_string()));
+        assert_eq!(
+            entries[4],
+            ConstantPoolEntry::NameAndType {
+                name_index: 2,
+                signature_index: 3,
+            }
+        );
+        assert_eq!(
+            entries[5],
+            ConstantPoolEntry::Methodref {
+                class_index: 1,
+                name_and_type_index: 4,
+            }
+        );
+        assert_eq!(entries[6], ConstantPoolEntry::Integer(1337));
+        assert_eq!(entries[7], ConstantPoolEntry::Long(-2));
+        assert_eq!(entries[8], ConstantPoolEntry::Invalid);
+        assert_eq!(entries[9], ConstantPoolEntry::Float(1.5));
+        assert_eq!(
+            entries[10],
+            ConstantPoolEntry::String("Minecraft 1.8.9".to_string())
+        );
+        assert_eq!(
+            entries[11],
+            ConstantPoolEntry::UnresolvedClass {
+                name: "avm".to_string(),
+                in_error: false,
+            }
+        );
+        assert_eq!(
+            entries[12],
+            ConstantPoolEntry::MethodHandle {
+                ref_kind: 6,
+                ref_index: 5,
+            }
+        );
+        assert_eq!(
+            constant_pool.entry(&image, 13),
+            Some(ConstantPoolEntry::InvokeDynamic {
+                bootstrap_index: 0,
+                name_and_type_index: 4,
+            })
+        );
+        assert_eq!(constant_pool.entry(&image, 14), None);
+    }
+
+    #[test]
+    fn decodes_klass_slots() {
+        let mut image = JvmImage::new(0x7f00_0000, 0x2000);
+
+        let minecraft = image.class("ave", 0, &[]);
+        let minecraft_name = image.symbol(b"ave");
+        let session_name = image.symbol(b"avm");
+        let resolved_klasses = image.array(&[0usize, minecraft]);
+
+        let constant_pool = image.tagged_constant_pool(&[
+            (JVM_CONSTANT_INVALID, 0),
+            (JVM_CONSTANT_CLASS, shorts(1, 3)),
+            (JVM_CONSTANT_UNRESOLVED_CLASS_IN_ERROR, shorts(0, 4)),
+            (JVM_CONSTANT_UTF8, minecraft_name),
+            (JVM_CONSTANT_UTF8, session_name),
+        ]);
+
+        // the JDK 8 layouts don't have _resolved_klasses, pretend this is JDK 10 or up
+        let mut constant_pool = JConstantPool::from_native(&image, constant_pool as _).unwrap();
+        constant_pool.resolved_klasses = resolved_klasses as _;
+        constant_pool.klass_slots = true;
+
+        assert_eq!(
+            constant_pool.entry(&image, 1),
+            Some(ConstantPoolEntry::Class {
+                name: "ave".to_string(),
+                klass: minecraft as _,
+            })
+        );
+        assert_eq!(
+            constant_pool.entry(&image, 2),
+            Some(ConstantPoolEntry::UnresolvedClass {
+                name: "avm".to_string(),
+                in_error: true,
+            })
+        );
+    }
+
+    #[test]
+    fn reads_bootstrap_methods() {
+        let mut image = JvmImage::new(0x7f00_0000, 0x1000);
+
+        // two offsets (as two shorts each), then the methods they point at
+        let operands = image.array::<u16>(&[4, 0, 8, 0, 12, 2, 13, 14, 15, 0]);
+        let constant_pool = image.tagged_constant_pool(&[(JVM_CONSTANT_INVALID, 0)]);
+        image.write_field(constant_pool, "ConstantPool::_operands", &operands);
+
+        let constant_pool = JConstantPool::from_native(&image, constant_pool as _).unwrap();
+
+        assert_eq!(
+            constant_pool.bootstrap_methods(&image),
+            vec![
+                BootstrapMethod {
+                    method_handle_index: 12,
+                    arguments: vec![13, 14],
+                },
+                BootstrapMethod {
+                    method_handle_index: 15,
+                    arguments: vec![],
+                },
+            ]
+        );
+    }
+}
@@ -1,15 +1,28 @@
-use std::sync::Mutex;
+use crate::api::processes::{self, MemorySource, ReadError};
 
-use crate::api::processes::{self, NativeHandle};
+use super::{
+    header,
+    remote::{RemoteClass, RemoteError, RemoteValue},
+    JClass,
+};
 
-use super::{minecraft::find_class};
-
-#[derive(Debug)]
+#[derive(Debug, RemoteClass)]
+#[class("bll")]
 pub struct Entity {
+    #[address]
     pub _address: usize,
+
+    /// posX, posY and posZ
+    #[field("s", "D")]
+    pub position: Vec3,
+
+    /// lastTickPosX, lastTickPosY and lastTickPosZ
+    #[field("S", "D")]
+    #[cfg_attr(not(windows), allow(dead_code))]
+    pub last_tick_position: Vec3,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub struct Vec3 {
     pub x: f64,
     pub y: f64,
@@ -31,81 +44,34 @@ pub struct Vec2 {
 }
 
 impl Vec3 {
-    #[allow(unused)]
+    #[allow(dead_code)]
     pub fn new(x: f64, y: f64, z: f64) -> Self {
         Self { x, y, z }
     }
 }
 
-lazy_static::lazy_static! {
-    static ref POSITION_OFFSET: Mutex<usize> = Mutex::new(0usize);
-    static ref LAST_TICK_POSITION_OFFSET: Mutex<usize> = Mutex::new(0usize);
+/// Three doubles declared one after the other, which the JVM keeps together
+impl RemoteValue for Vec3 {
+    const EXPECTED: &'static str = "D";
+
+    fn read(source: &dyn MemorySource, address: usize) -> Result<Self, RemoteError> {
+        Ok(processes::try_read_exact(source, address)?)
+    }
 }
 
 impl Entity {
-    pub fn new(address: u32) -> Self {
-        Self {
-            _address: address as _,
-        }
+    /// The class this entity really is and its name, whatever list it came out of
+    #[allow(dead_code)]
+    pub fn get_class(&self, source: &dyn MemorySource) -> Result<(JClass, String), ReadError> {
+        header::class_of(source, self._address)
     }
 
-    pub fn get_head_position(&self, handle: &NativeHandle) -> Vec3 {
-        let pos = self.get_position(handle);
-
+    #[cfg_attr(not(windows), allow(dead_code))]
+    pub fn get_head_position(&self) -> Vec3 {
         Vec3 {
-            x: pos.x,
-            y: pos.y + 1.8f64,
-            z: pos.z,
+            x: self.position.x,
+            y: self.position.y + 1.8f64,
+            z: self.position.z,
         }
     }
-
-    pub fn get_last_tick_position(&self, handle: &NativeHandle) -> Vec3 {
-        if *LAST_TICK_POSITION_OFFSET.lock().unwrap() == 0usize {
-            let clazz = find_class("bll");
-            *LAST_TICK_POSITION_OFFSET.lock().unwrap() = clazz
-                .find_field_entry(&handle, "S", "D")
-                .expect("Couldn't find lastTickPosX field")
-                ._field_info
-                .offset() as usize;
-        }
-
-        let mut positioning: Vec3 = unsafe {
-            Vec3 {
-                ..core::mem::zeroed()
-            }
-        };
-
-        processes::read(
-            &handle,
-            (self._address + *LAST_TICK_POSITION_OFFSET.lock().unwrap()) as usize,
-            &mut positioning,
-        );
-
-        positioning
-    }
-
-    pub fn get_position(&self, handle: &NativeHandle) -> Vec3 {
-        if *POSITION_OFFSET.lock().unwrap() == 0usize {
-            let clazz = find_class("bll");
-            *POSITION_OFFSET.lock().unwrap() = clazz
-                .find_field_entry(&handle, "s", "D")
-                .expect("Couldn't find posX field")
-                ._field_info
-                .offset() as usize;
-        }
-
-        let mut positioning: Vec3 = unsafe {
-            Vec3 {
-                ..core::mem::zeroed()
-            }
-        };
-
-        processes::read(
-            &handle,
-            (self._address + *POSITION_OFFSET.lock().unwrap()) as usize,
-            &mut positioning,
-        );
-
-        positioning
-    }
 }
@@ -0,0 +1,345 @@
+//! The fields an `InstanceKlass` declares. HotSpot has kept them in two ways:
+//!
+//! * JDK 8 to 20: `_fields`, an `Array<u2>` of six shorts per field, followed by the generic
+//!   signature indices of the fields that have one
+//! * JDK 21 and up: `_fieldinfo_stream`, the same things compressed into UNSIGNED5 numbers, with
+//!   what changes at runtime moved out to `_fields_status`
+
+use crate::api::processes::MemorySource;
+
+use super::{vmstructs, FieldError, FromNative, JArray, JClass, JFieldInfo, JFieldOffset};
+
+/// The low bits of a packed offset say what it holds, this tag meaning an actual offset
+const FIELDINFO_TAG_OFFSET: u32 = 1;
+
+// UNSIGNED5 spends the byte values from X + L on saying another byte follows, every following
+// byte adds LG_H more bits. Bytes below X never occur so a 0 can end the stream.
+const X: u32 = 1;
+const L: u32 = 191;
+const LG_H: u32 = 6;
+const MAX_LENGTH: u32 = 5;
+
+/// A field from the class file, whichever layout the JVM keeps it in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FieldInfo {
+    /// The class file flags, the packed layout has HotSpot's own ones above them
+    pub access_flags: u16,
+    pub name_index: u16,
+    pub signature_index: u16,
+
+    /// Constant pool index of the ConstantValue of a static field, 0 if it has none
+    pub initval_index: u16,
+
+    /// Constant pool index of the Signature attribute, 0 if it has none
+    pub generic_signature_index: u16,
+
+    /// Where the field lives in the instance, or in the mirror for static fields
+    pub offset: u32,
+}
+
+impl FieldInfo {
+    /// The field as JDK 8 packs it, so `FieldEntry` doesn't care which layout it came from
+    pub fn packed(&self) -> JFieldInfo {
+        let offset = (self.offset << 2) | FIELDINFO_TAG_OFFSET;
+
+        JFieldInfo {
+            _shorts: [
+                self.access_flags,
+                self.name_index,
+                self.signature_index,
+                self.initval_index,
+                offset as u16,
+                (offset >> 16) as u16,
+            ],
+        }
+    }
+}
+
+/// Reads UNSIGNED5 numbers, HotSpot's way of squeezing a u4 into 1 to 5 bytes
+pub struct Unsigned5Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Unsigned5Reader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, position: 0 }
+    }
+}
+
+impl<'a> Iterator for Unsigned5Reader<'a> {
+    type Item = u32;
+
+    /// The next number, `None` at the end of the stream or if it stops halfway through one
+    fn next(&mut self) -> Option<u32> {
+        let mut sum = 0u32;
+
+        for i in 0..MAX_LENGTH {
+            let byte = *self.bytes.get(self.position)? as u32;
+            if byte < X {
+                return None;
+            }
+
+            self.position += 1;
+            sum = sum.wrapping_add((byte - X) << (LG_H * i));
+
+            if byte < X + L || i == MAX_LENGTH - 1 {
+                return Some(sum);
+            }
+        }
+
+        None
+    }
+}
+
+fn malformed(reason: &str) -> FieldError {
+    FieldError::Malformed(reason.to_string())
+}
+
+/// The fields `clazz` declares in its class file (not its supers), in declaration order
+pub fn declared_fields(
+    source: &dyn MemorySource,
+    clazz: &JClass,
+) -> Result<Vec<FieldInfo>, FieldError> {
+    if clazz.fieldinfo_stream.is_null() {
+        packed_fields(source, clazz)
+    } else {
+        streamed_fields(source, clazz)
+    }
+}
+
+fn packed_fields(source: &dyn MemorySource, clazz: &JClass) -> Result<Vec<FieldInfo>, FieldError> {
+    if clazz.fields.is_null() {
+        return Ok(Vec::new());
+    }
+
+    let fields_array = JArray::from_native(source, clazz.fields)?;
+    let slots = JFieldOffset::FieldSlots.value();
+
+    // the array goes on with injected fields and generic signatures, only the first
+    // java_fields_count entries are fields from the class file
+    let field_count = match clazz.java_fields_count {
+        Some(count) => count as i32,
+        None => fields_array.lenght / slots,
+    };
+
+    let has_generic_signature = vmstructs::VM_STRUCTS
+        .lock()
+        .unwrap()
+        .int_constant("JVM_ACC_FIELD_HAS_GENERIC_SIGNATURE")
+        .unwrap_or(0x0800) as u16;
+
+    let packed = (0..field_count)
+        .map(|i| JFieldInfo::from_native(source, fields_array.adr_at(i * slots) as _))
+        .collect::<Result<Vec<JFieldInfo>, _>>()?;
+
+    // the generic signatures of the fields that have one fill up the end of the array
+    let mut generic_signature = fields_array.lenght
+        - packed
+            .iter()
+            .filter(|field| field.access_flags() & has_generic_signature != 0)
+            .count() as i32;
+
+    packed
+        .iter()
+        .map(|field| {
+            let generic_signature_index = if field.access_flags() & has_generic_signature != 0 {
+                generic_signature += 1;
+
+                fields_array
+                    .at(generic_signature - 1, source)
+                    .ok_or_else(|| malformed("generic signature past the fields array"))?
+            } else {
+                0
+            };
+
+            Ok(FieldInfo {
+                access_flags: field.access_flags(),
+                name_index: field.name_idx(),
+                signature_index: field.sig_idx(),
+                initval_index: field.initval_idx(),
+                generic_signature_index,
+                offset: field.offset() as u32,
+            })
+        })
+        .collect()
+}
+
+fn streamed_fields(
+    source: &dyn MemorySource,
+    clazz: &JClass,
+) -> Result<Vec<FieldInfo>, FieldError> {
+    let stream = JArray::from_native(source, clazz.fieldinfo_stream)?;
+
+    let mut bytes: Vec<u8> = vec![0; stream.lenght.max(0) as usize];
+    if !bytes.is_empty() {
+        source.read_bytes(stream.adr_at(0) as usize, bytes.as_mut_slice())?;
+    }
+
+    // bit positions in a field's own flags, each set one has a number following the field
+    let flag = |name: &str, default: u32| -> u32 {
+        vmstructs::VM_STRUCTS
+            .lock()
+            .unwrap()
+            .int_constant(&format!("FieldInfo::FieldFlags::_ff_{}", name))
+            .map_or(default, |bit| bit as u32)
+    };
+    let initialized = 1 << flag("initialized", 0);
+    let generic = 1 << flag("generic", 2);
+    let contended = 1 << flag("contended", 4);
+
+    let mut numbers = Unsigned5Reader::new(&bytes);
+    let mut next = || {
+        numbers
+            .next()
+            .ok_or_else(|| malformed("field info stream ends halfway through a field"))
+    };
+
+    // the injected fields come after the ones from the class file, we stop before them
+    let java_fields = next()?;
+    let _injected_fields = next()?;
+
+    let mut fields = Vec::new();
+
+    for _ in 0..java_fields {
+        let name_index = next()? as u16;
+        let signature_index = next()? as u16;
+        let offset = next()?;
+        let access_flags = next()? as u16;
+        let field_flags = next()?;
+
+        let mut optional = |flag: u32| {
+            if field_flags & flag != 0 {
+                next()
+            } else {
+                Ok(0)
+            }
+        };
+
+        let initval_index = optional(initialized)? as u16;
+        let generic_signature_index = optional(generic)? as u16;
+        let _contention_group = optional(contended)?;
+
+        fields.push(FieldInfo {
+            access_flags,
+            name_index,
+            signature_index,
+            initval_index,
+            generic_signature_index,
+            offset,
+        });
+    }
+
+    Ok(fields)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::api::sdk::{
+        testing::{unsigned5, JvmImage},
+        version::JavaVersion,
+        vmstructs::{VMField, VMStructs},
+    };
+
+    /// The JDK 8 layouts with the JDK 21 `FieldInfoStream` where JDK 8 has no fields
+    fn structs() -> VMStructs {
+        let mut structs = VMStructs::jdk8();
+
+        for (name, offset) in [
+            ("InstanceKlass::_fieldinfo_stream", 0x00E0),
+            ("InstanceKlass::_fields_status", 0x00E8),
+        ] {
+            structs.insert_field(
+                name,
+                VMField {
+                    offset,
+                    is_static: false,
+                    address: 0,
+                    type_string: None,
+                },
+            );
+        }
+
+        structs.set_java_version(JavaVersion { major: 21 });
+        structs
+    }
+
+    #[test]
+    fn reads_unsigned5() {
+        let mut bytes = Vec::new();
+        for value in [0, 190, 191, 200, 0x1234_5678, u32::MAX] {
+            unsigned5(value, &mut bytes);
+        }
+        bytes.push(0);
+
+        // one byte up to L - 1, from there a byte that says another one follows
+        assert_eq!(bytes[..4], [0x01, 0xBF, 0xC0, 0x01]);
+
+        let numbers = Unsigned5Reader::new(&bytes).collect::<Vec<u32>>();
+        assert_eq!(numbers, [0, 190, 191, 200, 0x1234_5678, u32::MAX]);
+
+        // a number cut short isn't one
+        assert_eq!(Unsigned5Reader::new(&[0xC9]).next(), None);
+    }
+
+    #[test]
+    fn reads_streamed_fields() {
+        let mut image = JvmImage::new(0x7f00_0000, 0x2000);
+
+        let slots = [
+            0,
+            image.symbol(b"counter"),
+            image.symbol(b"I"),
+            image.symbol(b"names"),
+            image.symbol(b"Ljava/util/List;"),
+            image.symbol(b"Ljava/util/List<Ljava/lang/String;>;"),
+            0,
+        ];
+
+        let counter = FieldInfo {
+            access_flags: 0x0008,
+            name_index: 1,
+            signature_index: 2,
+            initval_index: 6,
+            generic_signature_index: 0,
+            offset: 0x70,
+        };
+        let names = FieldInfo {
+            access_flags: 0x0012,
+            name_index: 3,
+            signature_index: 4,
+            initval_index: 0,
+            generic_signature_index: 5,
+            offset: 0x0c,
+        };
+
+        let clazz = JClass {
+            symbol: image.symbol(b"Streamed") as _,
+            constant_pool: image.constant_pool(&slots) as _,
+            ..Default::default()
+        };
+        let address = image.class_from(&clazz);
+        let stream = image.field_info_stream(&[counter, names]);
+        image.write(address + 0x00E0, &stream);
+
+        let structs = structs();
+        let clazz = JClass::read_with(&image, address as _, &structs).unwrap();
+        assert_eq!(clazz.fieldinfo_stream as usize, stream);
+
+        assert_eq!(declared_fields(&image, &clazz).unwrap(), [counter, names]);
+
+        let fields = clazz
+            .iterate_fields(&image)
+            .map(|entry| (entry.name, entry._field_info.offset(), entry.sig))
+            .collect::<Vec<(String, u64, String)>>();
+        assert_eq!(
+            fields,
+            [
+                ("counter".to_string(), 0x70, "I".to_string()),
+                ("names".to_string(), 0x0c, "Ljava/util/List;".to_string()),
+            ]
+        );
+    }
+}
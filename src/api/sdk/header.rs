@@ -0,0 +1,206 @@
+//! The header every Java object starts with: the mark word, followed by the pointer to the
+//! object's class (a narrow one with compressed class pointers)
+
+use crate::api::processes::{self, MemorySource, ReadError};
+
+use super::{
+    oops::COMPRESSED_OOPS,
+    vmstructs::{VMStructs, VM_STRUCTS},
+    FromNative, JClass,
+};
+
+/// What the low bits of the mark word say about the object's lock
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockState {
+    Unlocked,
+
+    /// Biased towards a thread, JDK 17 and before
+    Biased,
+
+    /// Locked on a thread's stack, the mark word points at the lock record
+    Locked,
+
+    /// Locked through an ObjectMonitor, the mark word points at the monitor
+    Inflated,
+
+    /// Marked by the GC, usually because it's being moved
+    Marked,
+}
+
+/// The first word of an object: its lock and, while it isn't locked, its identity hash and age
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MarkWord {
+    pub value: u64,
+    pub lock: LockState,
+
+    /// The identity hash, `None` if nothing asked for it yet or a lock displaced it
+    pub hash: Option<u32>,
+
+    /// How many young collections the object survived, `None` if a lock displaced it
+    pub age: Option<u8>,
+}
+
+/// A markWord (markOopDesc before JDK 13) constant, or HotSpot's value for 64 bit VMs
+fn mark_constant(structs: &VMStructs, name: &str, default: u64) -> u64 {
+    ["markWord", "markOopDesc"]
+        .iter()
+        .find_map(|owner| {
+            let name = format!("{}::{}", owner, name);
+
+            structs
+                .long_constant(&name)
+                .or_else(|| structs.int_constant(&name).map(|value| value as u64))
+        })
+        .unwrap_or(default)
+}
+
+impl MarkWord {
+    pub fn decode(value: u64, structs: &VMStructs) -> Self {
+        let constant = |name: &str, default: u64| mark_constant(structs, name, default);
+
+        let biased_lock_mask = constant("biased_lock_mask_in_place", 0x7);
+        let lock = if value & biased_lock_mask == constant("biased_lock_pattern", 0x5) {
+            LockState::Biased
+        } else {
+            match value & constant("lock_mask_in_place", 0x3) {
+                lock if lock == constant("locked_value", 0x0) => LockState::Locked,
+                lock if lock == constant("monitor_value", 0x2) => LockState::Inflated,
+                lock if lock == constant("marked_value", 0x3) => LockState::Marked,
+                _ => LockState::Unlocked,
+            }
+        };
+
+        let age = match lock {
+            LockState::Unlocked | LockState::Biased => {
+                Some(((value >> constant("age_shift", 3)) & constant("age_mask", 0xF)) as u8)
+            }
+            _ => None,
+        };
+
+        // a biased mark word keeps its thread where the hash would go
+        let hash = (value >> constant("hash_shift", 8)) & constant("hash_mask", 0x7FFF_FFFF);
+        let hash = match lock {
+            LockState::Unlocked if hash != constant("no_hash", 0) => Some(hash as u32),
+            _ => None,
+        };
+
+        Self {
+            value,
+            lock,
+            hash,
+            age,
+        }
+    }
+}
+
+/// The header of an object, `klass` is the address of its `Klass`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ObjectHeader {
+    pub mark: MarkWord,
+    pub klass: usize,
+}
+
+impl ObjectHeader {
+    pub fn read(source: &dyn MemorySource, object: usize) -> Result<Self, ReadError> {
+        if object == 0 {
+            return Err(ReadError::UnmappedAddress(object));
+        }
+
+        let structs = VM_STRUCTS.lock().unwrap();
+        let compressed_oops = *COMPRESSED_OOPS.lock().unwrap();
+
+        let offset = |name: &str, default: usize| structs.offset_of(name).unwrap_or(default);
+
+        let mark = processes::try_read_exact::<u64>(source, object + offset("oopDesc::_mark", 0))?;
+
+        let klass = match compressed_oops.klass_pointers {
+            Some(_) => compressed_oops.decode_klass(processes::try_read_exact(
+                source,
+                object + offset("oopDesc::_metadata._compressed_klass", 8),
+            )?),
+            None => {
+                processes::try_read_exact(source, object + offset("oopDesc::_metadata._klass", 8))?
+            }
+        };
+
+        Ok(Self {
+            mark: MarkWord::decode(mark, &structs),
+            klass,
+        })
+    }
+
+    /// The class of the object. Arrays have an `ArrayKlass` which only shares the `Klass` part
+    /// (the name among others) with an `InstanceKlass`.
+    pub fn class(&self, source: &dyn MemorySource) -> Result<JClass, ReadError> {
+        JClass::from_native(source, self.klass as _)
+    }
+}
+
+/// The class of the object at `object` along with its name, e.g. "java/util/ArrayList"
+pub fn class_of(source: &dyn MemorySource, object: usize) -> Result<(JClass, String), ReadError> {
+    let clazz = ObjectHeader::read(source, object)?.class(source)?;
+    let name = clazz.name(source)?;
+
+    Ok((clazz, name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::api::sdk::testing::JvmImage;
+
+    #[test]
+    fn decodes_mark_words() {
+        let structs = VMStructs::default();
+
+        let unlocked = MarkWord::decode((0x1234 << 8) | (3 << 3) | 0x1, &structs);
+        assert_eq!(unlocked.lock, LockState::Unlocked);
+        assert_eq!(unlocked.hash, Some(0x1234));
+        assert_eq!(unlocked.age, Some(3));
+
+        // nobody asked for the hash yet
+        assert_eq!(MarkWord::decode(0x1, &structs).hash, None);
+
+        let biased = MarkWord::decode(0x7f00_1234_5000 | (2 << 3) | 0x5, &structs);
+        assert_eq!(biased.lock, LockState::Biased);
+        assert_eq!(biased.hash, None);
+        assert_eq!(biased.age, Some(2));
+
+        let inflated = MarkWord::decode(0x7f00_1234_5678 | 0x2, &structs);
+        assert_eq!(inflated.lock, LockState::Inflated);
+        assert_eq!(inflated.age, None);
+
+        assert_eq!(
+            MarkWord::decode(0x7f00_1234_5670, &structs).lock,
+            LockState::Locked
+        );
+        assert_eq!(MarkWord::decode(0x3, &structs).lock, LockState::Marked);
+    }
+
+    #[test]
+    fn reads_the_class_of_objects() {
+        let mut image = JvmImage::new(0x7f00_0000, 0x2000);
+
+        let object = image.class("java/lang/Object", 0, &[]);
+        let player = image.class("bjk", object, &[]);
+
+        // the sdk decodes klass pointers zero based until told otherwise
+        let instance = image.alloc(0x18);
+        image.write(instance, &((0x2a << 8) | 0x1u64));
+        image.write(instance + 8, &(player as u32));
+
+        let header = ObjectHeader::read(&image, instance).unwrap();
+        assert_eq!(header.mark.hash, Some(0x2a));
+        assert_eq!(header.klass, player);
+
+        let (clazz, name) = class_of(&image, instance).unwrap();
+        assert_eq!(name, "bjk");
+        assert_eq!(clazz.super_klass as usize, object);
+
+        assert_eq!(
+            class_of(&image, 0).err(),
+            Some(ReadError::UnmappedAddress(0))
+        );
+    }
+}
@@ -1,90 +1,371 @@
-use std::{ops::Mul, usize};
+use std::{marker::PhantomData, ops::Range, sync::Mutex};
 
-use winapi::um::memoryapi::ReadProcessMemory;
+use crate::api::processes::{self, MemorySource, ReadError};
 
-use crate::api::processes::{self, NativeHandle};
+use super::{
+    oops::{self, CompressedOops},
+    remote::RemoteError,
+    vmstructs::VMStructs,
+};
 
-use super::FromNative;
-
-#[repr(C)]
-#[derive(Debug)]
-pub struct JavaBuffer<T> {
-    padding_0000: [u8; 16],
-    array: i32, // "address of the native array"
-    padding_0001: [u8; 12],
-    pub length: i32, // length of the buffer
-    base: *mut Self, // address of ourselves
+lazy_static::lazy_static! {
+    /// Where arrays keep their length and elements, what JDK 8 does until it's been read
+    pub static ref ARRAY_LAYOUT: Mutex<ArrayLayout> = Mutex::new(ArrayLayout::default());
 }
 
-#[repr(C)]
-#[derive(Debug)]
-pub struct JavaArray<T> {
-    pad_0000: [u8; 16],
-    pub length: i32, // length of the array
-    pub array: i32,  // pointer to actual list
-    base: *mut Self,
+/// How the target lays out its arrays (arrayOopDesc), the default being what JDK 8 to 21 do with
+/// compressed class pointers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ArrayLayout {
+    /// Where the length is, right after the mark word and the klass pointer
+    pub length_offset: usize,
+
+    /// Whether the elements are only aligned for their own size (JDK 22 and up) rather than to a
+    /// heap word
+    pub element_aligned: bool,
 }
 
-impl<T> JavaArray<T> {
-    pub fn array_offset(&self) -> i32 {
-        0x10
+impl Default for ArrayLayout {
+    fn default() -> Self {
+        Self {
+            length_offset: 12,
+            element_aligned: false,
+        }
     }
+}
 
-    pub fn get_at(&self, handle: &NativeHandle, idx: i32) -> Option<T> {
-        if idx > self.length || idx < 0 {
-            return None;
+/// Arrays start at a heap word boundary until JDK 22
+const HEAP_WORD_SIZE: usize = 8;
+
+impl ArrayLayout {
+    /// The layout `structs` and the flags of the target describe. Compact object headers keep
+    /// the klass in the mark word, so the length follows it; otherwise it goes in the gap after a
+    /// narrow klass pointer or after the full header.
+    pub fn read(
+        source: &dyn MemorySource,
+        structs: &VMStructs,
+        compressed_oops: &CompressedOops,
+    ) -> Self {
+        let compact_headers = structs
+            .bool_flag(source, "UseCompactObjectHeaders")
+            .unwrap_or(false);
+
+        let length_offset = if compact_headers {
+            structs.offset_of("oopDesc::_mark").unwrap_or(0) + std::mem::size_of::<u64>()
+        } else if compressed_oops.klass_pointers.is_some() {
+            structs
+                .offset_of("oopDesc::_metadata._compressed_klass")
+                .unwrap_or(8)
+                + std::mem::size_of::<u32>()
+        } else {
+            structs.size_of("oopDesc").unwrap_or(16)
+        };
+
+        let element_aligned = compact_headers
+            || structs
+                .java_version()
+                .is_some_and(|version| version.has_element_aligned_arrays());
+
+        Self {
+            length_offset,
+            element_aligned,
         }
+    }
 
-        let address =
-            (self.array + self.array_offset() + idx.mul(std::mem::size_of::<T>() as i32)) as u32;
+    pub fn install(self) {
+        *ARRAY_LAYOUT.lock().unwrap() = self;
+    }
+
+    /// Where the first element of an array of `element_size` byte elements is
+    pub fn base_offset(&self, element_size: usize) -> usize {
+        let alignment = if self.element_aligned {
+            element_size.max(1)
+        } else {
+            HEAP_WORD_SIZE
+        };
+        let end_of_length = self.length_offset + std::mem::size_of::<i32>();
 
-        Some(processes::read_exact::<T>(&handle, address as usize))
+        end_of_length.div_ceil(alignment) * alignment
     }
 }
 
-impl<T> JavaBuffer<T> {
+/// Something an array holds, a primitive or (as `usize`) a reference
+pub trait ArrayElement: Sized {
+    /// Whether an array with the component signature `sig` holds these, e.g. "I" for an int[]
+    fn matches(sig: &str) -> bool;
+
+    fn size(compressed_oops: &CompressedOops) -> usize;
+
+    /// The element `bytes` hold, as many as `size` says
+    fn decode(bytes: &[u8], compressed_oops: &CompressedOops) -> Self;
+}
+
+macro_rules! primitive_element {
+    ($($type:ty => $sig:literal),*) => {
+        $(
+            impl ArrayElement for $type {
+                fn matches(sig: &str) -> bool {
+                    sig == $sig
+                }
+
+                fn size(_: &CompressedOops) -> usize {
+                    std::mem::size_of::<$type>()
+                }
+
+                fn decode(bytes: &[u8], _: &CompressedOops) -> Self {
+                    let mut value = [0u8; std::mem::size_of::<$type>()];
+                    value.copy_from_slice(bytes);
+
+                    <$type>::from_ne_bytes(value)
+                }
+            }
+        )*
+    };
+}
+
+primitive_element!(
+    i8 => "B", u8 => "B", u16 => "C", i16 => "S", i32 => "I", i64 => "J", f32 => "F", f64 => "D"
+);
+
+impl ArrayElement for bool {
+    fn matches(sig: &str) -> bool {
+        sig == "Z"
+    }
+
+    fn size(_: &CompressedOops) -> usize {
+        1
+    }
+
+    fn decode(bytes: &[u8], _: &CompressedOops) -> Self {
+        bytes[0] != 0
+    }
+}
+
+/// A reference, the address of the object it points at with null as 0
+impl ArrayElement for usize {
+    fn matches(sig: &str) -> bool {
+        sig.starts_with('L') || sig.starts_with('[')
+    }
+
+    fn size(compressed_oops: &CompressedOops) -> usize {
+        compressed_oops.oop_size()
+    }
+
+    fn decode(bytes: &[u8], compressed_oops: &CompressedOops) -> Self {
+        match *bytes {
+            [a, b, c, d] => compressed_oops.decode_oop(u32::from_ne_bytes([a, b, c, d])),
+            _ => {
+                let mut address = [0u8; std::mem::size_of::<usize>()];
+                address.copy_from_slice(bytes);
+
+                usize::from_ne_bytes(address)
+            }
+        }
+    }
+}
+
+/// An array on the Java heap holding `T`s
+#[derive(Debug, Clone)]
+pub struct JavaArray<T> {
+    pub address: usize,
+    length: usize,
+    base_offset: usize,
+    element_size: usize,
+    compressed_oops: CompressedOops,
+    element: PhantomData<T>,
+}
+
+impl<T: ArrayElement> JavaArray<T> {
+    /// The array at `address`, its length read right away
+    pub fn read(source: &dyn MemorySource, address: usize) -> Result<Self, ReadError> {
+        if address == 0 {
+            return Err(ReadError::UnmappedAddress(address));
+        }
+
+        let layout = *ARRAY_LAYOUT.lock().unwrap();
+        let compressed_oops = *oops::COMPRESSED_OOPS.lock().unwrap();
+        let element_size = T::size(&compressed_oops);
+
+        let length = processes::try_read_exact::<i32>(source, address + layout.length_offset)?;
+
+        Ok(Self {
+            address,
+            length: length.max(0) as usize,
+            base_offset: layout.base_offset(element_size),
+            element_size,
+            compressed_oops,
+            element: PhantomData,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.length
+    }
+
     #[allow(dead_code)]
-    pub fn get(&self, handle: &NativeHandle, idx: i32) -> Option<T> {
-        if idx > self.length || idx < 0 {
-            return None;
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    /// Where the element at `index` lives, `None` past the end
+    #[allow(dead_code)]
+    pub fn element_address(&self, index: usize) -> Option<usize> {
+        if index < self.length {
+            Some(self.address + self.base_offset + index * self.element_size)
+        } else {
+            None
         }
+    }
 
-        let address = unsafe { (self.array as *mut T).offset(idx as _) };
-        Some(processes::read_exact::<T>(&handle, address as usize))
+    #[allow(dead_code)]
+    pub fn get(&self, source: &dyn MemorySource, index: usize) -> Result<T, RemoteError> {
+        Ok(self.read_range(source, index..index + 1)?.remove(0))
     }
 
-    pub fn as_vec(&self, handle: &NativeHandle) -> Vec<T> {
-        let mut res: Vec<T> = Vec::with_capacity(self.length as _);
+    /// The elements in `range` in one read
+    pub fn read_range(
+        &self,
+        source: &dyn MemorySource,
+        range: Range<usize>,
+    ) -> Result<Vec<T>, RemoteError> {
+        if range.start > range.end || range.end > self.length {
+            return Err(RemoteError::IndexOutOfBounds {
+                index: range.end.max(range.start + 1) - 1,
+                length: self.length,
+            });
+        }
 
-        unsafe {
-            res.set_len(self.length as _);
-            ReadProcessMemory(
-                handle.get(),
-                self.array as _,
-                res.as_mut_ptr() as _,
-                self.length as usize * std::mem::size_of::<T>() as usize,
-                std::ptr::null_mut(),
-            );
+        if range.start == range.end {
+            return Ok(Vec::new());
         }
 
-        res
+        let mut bytes = vec![0u8; (range.end - range.start) * self.element_size];
+        let start = self.address + self.base_offset + range.start * self.element_size;
+        source.read_bytes(start, &mut bytes)?;
+
+        Ok(bytes
+            .chunks_exact(self.element_size)
+            .map(|element| T::decode(element, &self.compressed_oops))
+            .collect())
+    }
+
+    /// Every element in one read
+    pub fn to_vec(&self, source: &dyn MemorySource) -> Result<Vec<T>, RemoteError> {
+        self.read_range(source, 0..self.length)
     }
 }
 
-impl<T> FromNative for JavaBuffer<T> {
-    fn from_native(handle: &NativeHandle, ptr: *mut Self) -> Self {
-        let mut buffer =  processes::read_class::<JavaBuffer<T>>(handle, ptr as _);
-        buffer.base = ptr;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::api::sdk::{testing::JvmImage, version::JavaVersion};
+
+    #[test]
+    fn lays_out_arrays_like_the_vm() {
+        let image = JvmImage::new(0x7f00_0000, 0x100);
+
+        let mut structs = VMStructs::default();
+        let compressed = CompressedOops::default();
+        let uncompressed = CompressedOops {
+            oops: None,
+            klass_pointers: None,
+        };
+
+        // JDK 8 to 21 start every array at a heap word
+        structs.set_java_version(JavaVersion { major: 17 });
+        let layout = ArrayLayout::read(&image, &structs, &compressed);
+        assert_eq!(layout, ArrayLayout::default());
+        assert_eq!(layout.base_offset(1), 16);
+        assert_eq!(layout.base_offset(8), 16);
+
+        let layout = ArrayLayout::read(&image, &structs, &uncompressed);
+        assert_eq!(layout.length_offset, 16);
+        assert_eq!(layout.base_offset(4), 24);
+
+        // JDK 22 and up only align for the element
+        structs.set_java_version(JavaVersion { major: 22 });
+        let layout = ArrayLayout::read(&image, &structs, &uncompressed);
+        assert_eq!(layout.base_offset(1), 20);
+        assert_eq!(layout.base_offset(4), 20);
+        assert_eq!(layout.base_offset(8), 24);
 
-        buffer
+        // compact object headers put the length right after the mark word
+        let compact = ArrayLayout {
+            length_offset: 8,
+            element_aligned: true,
+        };
+        assert_eq!(compact.base_offset(1), 12);
+        assert_eq!(compact.base_offset(8), 16);
     }
-}
 
-impl<T> FromNative for JavaArray<T> {
-    fn from_native(handle: &NativeHandle, ptr: *mut Self) -> Self {
-        let mut buffer = processes::read_class::<JavaArray<T>>(handle, ptr as _);
-        buffer.base = ptr;
+    #[test]
+    fn reads_arrays() {
+        let mut image = JvmImage::new(0x7f00_0000, 0x1000);
+
+        let ints = image.alloc(16 + 3 * 4);
+        image.write(ints + 12, &3i32);
+        for (index, value) in [7i32, -1, 0x1234_5678].iter().enumerate() {
+            image.write(ints + 16 + index * 4, value);
+        }
+
+        let array = JavaArray::<i32>::read(&image, ints).unwrap();
+        assert_eq!(array.len(), 3);
+        assert_eq!(array.to_vec(&image), Ok(vec![7, -1, 0x1234_5678]));
+        assert_eq!(array.get(&image, 2), Ok(0x1234_5678));
+        assert_eq!(array.read_range(&image, 1..3), Ok(vec![-1, 0x1234_5678]));
+
+        // the length is the first index past the end
+        assert_eq!(
+            array.get(&image, 3),
+            Err(RemoteError::IndexOutOfBounds {
+                index: 3,
+                length: 3
+            })
+        );
+        assert_eq!(array.element_address(3), None);
+
+        let doubles = image.alloc(16 + 2 * 8);
+        image.write(doubles + 12, &2i32);
+        image.write(doubles + 16, &1.5f64);
+        image.write(doubles + 24, &-0.25f64);
+        assert_eq!(
+            JavaArray::<f64>::read(&image, doubles)
+                .unwrap()
+                .to_vec(&image),
+            Ok(vec![1.5, -0.25])
+        );
+
+        let booleans = image.alloc(16 + 3);
+        image.write(booleans + 12, &3i32);
+        image.write_bytes(booleans + 16, &[1, 0, 1]);
+        assert_eq!(
+            JavaArray::<bool>::read(&image, booleans)
+                .unwrap()
+                .to_vec(&image),
+            Ok(vec![true, false, true])
+        );
+
+        // narrow references, zero based
+        let objects = image.alloc(16 + 2 * 4);
+        image.write(objects + 12, &2i32);
+        image.write(objects + 16, &(ints as u32));
+        image.write(objects + 20, &0u32);
+        assert_eq!(
+            JavaArray::<usize>::read(&image, objects)
+                .unwrap()
+                .to_vec(&image),
+            Ok(vec![ints, 0])
+        );
+
+        let empty = image.alloc(16);
+        let array = JavaArray::<i64>::read(&image, empty).unwrap();
+        assert!(array.is_empty());
+        assert_eq!(array.to_vec(&image), Ok(Vec::new()));
 
-        buffer
+        assert_eq!(
+            JavaArray::<u8>::read(&image, 0).err(),
+            Some(ReadError::UnmappedAddress(0))
+        );
     }
 }
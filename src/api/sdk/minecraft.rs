@@ -1,6 +1,6 @@
-use crate::api::processes::{self, NativeHandle};
+use crate::api::processes::MemorySource;
 
-use super::{JClass, world::World, entity::Entity};
+use super::{JClass, world::World, entity::Entity, remote::{RemoteClass, RemoteError}};
 use crate::ether::CLASSES;
 
 // Remote minecraft object
@@ -11,73 +11,45 @@ pub struct Minecraft {
 }
 
 
+#[cfg_attr(not(windows), allow(dead_code))]
 pub fn find_class(name: &str) -> JClass {
     let classes = CLASSES.lock().unwrap();
 
-    classes.get(name).expect("Couldn't find class").clone()
+    classes.find(name).expect("Couldn't find class").clone()
 }
 
+#[cfg_attr(not(windows), allow(dead_code))]
 impl Minecraft {
-    pub fn new(class: &JClass, handle: &NativeHandle) -> Self {
-        let mut address: u32 = 0;
-        processes::read(
-            &handle,
-            class.static_fields as usize
-                + class
-                    .find_field_entry(&handle, "M", "Lbao;")
-                    .expect("Couldn't find minecraft object field...")
-                    ._field_info
-                    .offset() as usize,
-            &mut address,
-        );
+    pub fn new(class: &JClass, source: &dyn MemorySource) -> Self {
+        let address = class
+            .read_static_reference_field(source, "M", "Lbao;")
+            .expect("Couldn't read minecraft object field...");
 
         Self {
             _clazz: class.clone(),
-            _address: address as _,
+            _address: address,
         }
     }
 
-    pub fn get_world(&self, handle: &NativeHandle) -> World {
-        World::new(&find_class("bjf"), self.get_world_pointer(handle))
+    pub fn get_world(&self, source: &dyn MemorySource) -> World {
+        World::read(source, self.get_world_pointer(source)).expect("Couldn't read world object...")
     }
 
-    #[allow(unused)]
-    pub fn get_player(&self, handle: &NativeHandle) -> Entity {
-        Entity::new(self.get_player_pointer(handle))
+    #[allow(dead_code)]
+    pub fn get_player(&self, source: &dyn MemorySource) -> Result<Entity, RemoteError> {
+        Entity::read(source, self.get_player_pointer(source))
     }
 
-    #[allow(unused)]
-    pub fn get_player_pointer(&self, handle: &NativeHandle) -> u32 {
-        let mut result: u32 = 0;
-
-        processes::read(
-            &handle,
-            self._address
-                + self._clazz
-                    .find_field_entry(&handle, "h", "Lbjk;")
-                    .expect("Couldn't find player object field...")
-                    ._field_info
-                    .offset() as usize,
-            &mut result,
-        );
-
-        result
+    #[allow(dead_code)]
+    pub fn get_player_pointer(&self, source: &dyn MemorySource) -> usize {
+        self._clazz
+            .read_reference_field(source, self._address, "h", "Lbjk;")
+            .expect("Couldn't read player object field...")
     }
 
-    pub fn get_world_pointer(&self, handle: &NativeHandle) -> u32 {
-        let mut result: u32 = 0;
-
-        processes::read(
-            &handle,
-            self._address
-                + self._clazz
-                    .find_field_entry(&handle, "f", "Lbjf;")
-                    .expect("Couldn't find world object field...")
-                    ._field_info
-                    .offset() as usize,
-            &mut result,
-        );
-
-        result
+    pub fn get_world_pointer(&self, source: &dyn MemorySource) -> usize {
+        self._clazz
+            .read_reference_field(source, self._address, "f", "Lbjf;")
+            .expect("Couldn't read world object field...")
     }
 }
@@ -1,20 +1,36 @@
 use std::ops::Mul;
 
-use winapi::um::memoryapi::ReadProcessMemory;
-
-use super::processes::{self, NativeHandle};
+use super::processes::{self, MemorySource, ReadError};
 
 pub mod activerenderinfo;
+pub mod buffer;
+pub mod classfile;
+pub mod collections;
+pub mod constantpool;
 pub mod entity;
+pub mod fieldinfo;
+pub mod header;
 pub mod java;
 pub mod minecraft;
+pub mod oops;
+pub mod remote;
+pub mod report;
+pub mod string;
+#[cfg(test)]
+pub mod testing;
+pub mod version;
+pub mod vmstructs;
 pub mod world;
 
-pub trait FromNative {
-    fn from_native(handle: &NativeHandle, ptr: *mut Self) -> Self;
+pub trait FromNative: Sized {
+    fn from_native(source: &dyn MemorySource, ptr: *mut Self) -> Result<Self, ReadError>;
 }
 
-#[repr(C)]
+// the views mirror everything the JVM tells us about, not just what we use
+
+/// View of a `Dictionary`, the hashtable the JDK 8 SystemDictionary keeps its classes in
+#[allow(dead_code)]
+#[derive(Debug)]
 pub struct JVMDictionary {
     pub table_size: i32,
     pub entries: *mut *mut DictionaryEntry,
@@ -25,7 +41,7 @@ pub struct JVMDictionary {
     pub num_entries: i32,
 }
 
-#[repr(C)]
+#[allow(dead_code)]
 #[derive(Debug)]
 pub struct DictionaryEntry {
     pub hash: u64,
@@ -34,12 +50,22 @@ pub struct DictionaryEntry {
     pub loader: usize,
 }
 
-#[repr(C)]
+/// View of a `ClassLoaderData`, the metadata of one class loader and the classes it defined
+#[allow(dead_code)]
+#[derive(Debug)]
+pub struct JClassLoaderData {
+    /// The `java.lang.ClassLoader` oop, 0 for the boot loader
+    pub class_loader: usize,
+    pub klasses: *mut JClass,
+    pub next: *mut JClassLoaderData,
+    pub base: *mut Self,
+}
+
+#[allow(dead_code)]
 #[derive(Debug)]
 pub struct JSymbol {
     pub lenght: i16,
     pub identity: i16,
-    padding_0000: [u8; 4],
     pub text: *mut u8, //ptr to array that contains the actual unicode text
     pub base: *mut Self,
 }
@@ -69,16 +95,16 @@ pub struct JArray<T> {
     base: *mut Self,
 }
 
-#[repr(C)]
+/// View of an `InstanceKlass`, every field is read from wherever the running JVM keeps it
+#[allow(dead_code)]
 #[derive(Debug, Clone)]
 pub struct JClass {
-    padding_0000: [u8; 8],
+    pub base: *mut JClass,
     pub layout_helper: i32,
     pub super_check_offset: i32,
     pub symbol: *mut JSymbol,
     pub secondary_super_cache: *mut JClass,
     pub secondary_super_array: *mut usize,
-    padding_0001: [u8; 64],
     pub static_fields: *mut usize,
     pub super_klass: *mut JClass,
     pub sub_klass: *mut JClass,
@@ -87,11 +113,7 @@ pub struct JClass {
     pub classloader_data: *mut usize,
     pub modifier_flags: i32,
     pub access_flags: i32,
-    padding_0002: [u8; 56],
     pub constant_pool: *mut JConstantPool,
-    padding_0003: [u8; 143],
-    n0000081_f: i8,
-    padding_0004: [u8; 16],
     pub methods: *mut usize,
     pub default_methods: *mut usize,
     pub _local_interfaces: *mut usize,
@@ -99,7 +121,16 @@ pub struct JClass {
     pub _method_ordering: *mut usize,
     pub _default_vtable_indices: *mut usize,
     pub fields: *mut JArray<u16>,
-} //Size: 0x01B0
+
+    /// `None` if the JVM doesn't tell us, the fields array is all we have then
+    pub java_fields_count: Option<u16>,
+
+    /// JDK 21 and up keep the fields compressed in here instead, null before that
+    pub fieldinfo_stream: *mut JArray<u8>,
+
+    /// What changes about the fields at runtime, next to `fieldinfo_stream`
+    pub fields_status: *mut JArray<u8>,
+}
 
 impl Default for JClass {
     fn default() -> Self {
@@ -113,15 +144,16 @@ impl Default for JClass {
 
 unsafe impl Send for JClass {}
 
-#[repr(C)]
+#[allow(dead_code)]
 #[derive(Debug)]
 pub struct JConstantPool {
-    padding_0000: [u8; 8],
     pub tags: *const JArray<u8>,
     pub cache: *const usize,
     pub instance_klass: *const JClass,
     pub operands: *const JArray<u16>,
     pub resolved: *const JArray<JClass>,
+    /// The `Klass*` of every resolved class entry, NULL on JVMs that keep them in the slots
+    pub resolved_klasses: *const JArray<*mut JClass>,
     pub major: u16,
     pub minor: u16,
     pub generic_signature_index: u16,
@@ -131,109 +163,875 @@ pub struct JConstantPool {
     pub saved: i32,
     lock: *const usize,
     base: *mut Self,
-} //0x50
+    header_size: usize,
+    /// Whether class entries are a name index and an index into `resolved_klasses` (JDK 10 and
+    /// up) instead of the `Klass*` or name `Symbol*` itself
+    klass_slots: bool,
+}
+
+/// View of a `Method`, the runtime half of a Java method
+#[allow(dead_code)]
+#[derive(Debug)]
+pub struct JMethod {
+    pub const_method: *mut JConstMethod,
+    /// The class file flags in the low 16 bits, HotSpot's own flags above them
+    pub access_flags: i32,
+    pub base: *mut Self,
+}
+
+/// View of a `ConstMethod`, the part of a method that never changes once the class is loaded
+#[allow(dead_code)]
+#[derive(Debug)]
+pub struct JConstMethod {
+    pub constants: *mut JConstantPool,
+    pub stackmap_data: *const JArray<u8>,
+    /// Size of the `ConstMethod` and the tables inlined behind it, in words
+    pub const_method_size: i32,
+    /// A u2 up to JDK 20, a u4 from JDK 21 on
+    pub flags: u32,
+    pub code_size: u16,
+    pub name_index: u16,
+    pub signature_index: u16,
+    pub max_stack: u16,
+    pub max_locals: u16,
+    pub size_of_parameters: u16,
+    /// The bytecode, right behind the `ConstMethod` itself
+    pub code: *mut u8,
+    pub base: *mut Self,
+}
 
 pub struct FieldEntry {
     pub _field_info: JFieldInfo,
     pub name: String,
     pub sig: String,
+    pub access_flags: AccessFlags,
+}
+
+/// The class file flags of a field, without the ones HotSpot keeps next to them
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AccessFlags(u16);
+
+/// Why a field couldn't be read
+#[derive(Debug, Clone, PartialEq)]
+pub enum FieldError {
+    /// The class (and its supers) don't have a field with this name and signature
+    NotFound {
+        name: String,
+        sig: String,
+    },
+
+    /// An instance field was read as a static one
+    NotStatic(String),
+
+    /// What the JVM keeps about the fields doesn't add up
+    Malformed(String),
+
+    Read(ReadError),
+}
+
+#[allow(dead_code)]
+pub struct MethodEntry {
+    pub _method: JMethod,
+    pub name: String,
+    pub sig: String,
+    pub access_flags: u16,
+    pub max_locals: u16,
+    pub max_stack: u16,
+    pub code_size: u16,
+    /// The bytecode as the interpreter runs it, HotSpot rewrites some instructions and operands
+    /// once a class is linked
+    pub code: Vec<u8>,
 }
 
 impl FromNative for JFieldInfo {
-    fn from_native(handle: &NativeHandle, ptr: *mut Self) -> Self {
-        processes::read_class::<JFieldInfo>(handle, ptr as _)
+    fn from_native(source: &dyn MemorySource, ptr: *mut Self) -> Result<Self, ReadError> {
+        processes::try_read_class::<JFieldInfo>(source, ptr as _)
     }
 }
 
 impl FromNative for JConstantPool {
-    fn from_native(handle: &NativeHandle, ptr: *mut Self) -> Self {
-        let mut constant_pool = processes::read_class::<JConstantPool>(handle, ptr as _);
-        constant_pool.base = ptr;
+    fn from_native(source: &dyn MemorySource, ptr: *mut Self) -> Result<Self, ReadError> {
+        let structs = vmstructs::VM_STRUCTS.lock().unwrap();
+        let view = structs.read_struct(source, "ConstantPool", ptr as _)?;
+
+        // JDK 8 keeps what it knows about the class file in the InstanceKlass, not the pool
+        let holder = match structs.offset_of("ConstantPool::_major_version") {
+            Some(_) => None,
+            None => structs
+                .read_struct(source, "InstanceKlass", view.field("ConstantPool::_pool_holder"))
+                .ok(),
+        };
+
+        let class_file_field = |name: &str| -> u16 {
+            match &holder {
+                Some(holder) => holder.field(&format!("InstanceKlass::{}", name)),
+                None => view.field(&format!("ConstantPool::{}", name)),
+            }
+        };
 
-        constant_pool
+        Ok(Self {
+            tags: view.field::<usize>("ConstantPool::_tags") as _,
+            cache: view.field::<usize>("ConstantPool::_cache") as _,
+            instance_klass: view.field::<usize>("ConstantPool::_pool_holder") as _,
+            operands: view.field::<usize>("ConstantPool::_operands") as _,
+            resolved: view.field::<usize>("ConstantPool::_resolved_references") as _,
+            resolved_klasses: view.field::<usize>("ConstantPool::_resolved_klasses") as _,
+            major: class_file_field("_major_version"),
+            minor: class_file_field("_minor_version"),
+            generic_signature_index: class_file_field("_generic_signature_index"),
+            source_file_name_index: class_file_field("_source_file_name_index"),
+            flags: view.field("ConstantPool::_flags"),
+            length: view.field("ConstantPool::_length"),
+            saved: view.field("ConstantPool::_saved"),
+            lock: view.field::<usize>("ConstantPool::_lock") as _,
+            base: ptr,
+            header_size: structs.size_of("ConstantPool").unwrap_or_default(),
+            klass_slots: structs
+                .offset_of("ConstantPool::_resolved_klasses")
+                .is_some(),
+        })
     }
 }
 
 impl FromNative for JClass {
-    fn from_native(handle: &NativeHandle, ptr: *mut Self) -> Self {
-        processes::read_class::<JClass>(handle, ptr as _)
+    fn from_native(source: &dyn MemorySource, ptr: *mut Self) -> Result<Self, ReadError> {
+        JClass::read_with(source, ptr, &vmstructs::VM_STRUCTS.lock().unwrap())
+    }
+}
+
+impl JClass {
+    fn read_with(
+        source: &dyn MemorySource,
+        ptr: *mut Self,
+        structs: &vmstructs::VMStructs,
+    ) -> Result<Self, ReadError> {
+        let view = structs.read_struct(source, "InstanceKlass", ptr as _)?;
+
+        // JDK 21 compressed the fields array into a stream, go by the version if we know it
+        let streams_fields = match structs.java_version() {
+            Some(version) => version.has_field_info_stream(),
+            None => structs
+                .offset_of("InstanceKlass::_fieldinfo_stream")
+                .is_some(),
+        };
+
+        Ok(Self {
+            base: ptr,
+            layout_helper: view.field("Klass::_layout_helper"),
+            super_check_offset: view.field("Klass::_super_check_offset"),
+            symbol: view.field::<usize>("Klass::_name") as _,
+            secondary_super_cache: view.field::<usize>("Klass::_secondary_super_cache") as _,
+            secondary_super_array: view.field::<usize>("Klass::_secondary_supers") as _,
+            static_fields: resolve_handle(
+                source,
+                structs,
+                "Klass::_java_mirror",
+                view.field("Klass::_java_mirror"),
+            ) as _,
+            super_klass: view.field::<usize>("Klass::_super") as _,
+            sub_klass: view.field::<usize>("Klass::_subklass") as _,
+            next_sibling: view.field::<usize>("Klass::_next_sibling") as _,
+            next_link: view.field::<usize>("Klass::_next_link") as _,
+            classloader_data: view.field::<usize>("Klass::_class_loader_data") as _,
+            modifier_flags: view.field("Klass::_modifier_flags"),
+            access_flags: view.field("Klass::_access_flags"),
+            constant_pool: view.field::<usize>("InstanceKlass::_constants") as _,
+            methods: view.field::<usize>("InstanceKlass::_methods") as _,
+            default_methods: view.field::<usize>("InstanceKlass::_default_methods") as _,
+            _local_interfaces: view.field::<usize>("InstanceKlass::_local_interfaces") as _,
+            _transitive_interfaces: view.field::<usize>("InstanceKlass::_transitive_interfaces")
+                as _,
+            _method_ordering: view.field::<usize>("InstanceKlass::_method_ordering") as _,
+            _default_vtable_indices: view.field::<usize>("InstanceKlass::_default_vtable_indices")
+                as _,
+            fields: view.field::<usize>("InstanceKlass::_fields") as _,
+            java_fields_count: structs
+                .offset_of("InstanceKlass::_java_fields_count")
+                .map(|_| view.field("InstanceKlass::_java_fields_count")),
+            fieldinfo_stream: if streams_fields {
+                view.field::<usize>("InstanceKlass::_fieldinfo_stream") as _
+            } else {
+                std::ptr::null_mut()
+            },
+            fields_status: view.field::<usize>("InstanceKlass::_fields_status") as _,
+        })
+    }
+}
+
+impl FromNative for JClassLoaderData {
+    fn from_native(source: &dyn MemorySource, ptr: *mut Self) -> Result<Self, ReadError> {
+        let structs = vmstructs::VM_STRUCTS.lock().unwrap();
+        let view = structs.read_struct(source, "ClassLoaderData", ptr as _)?;
+
+        Ok(Self {
+            class_loader: resolve_handle(
+                source,
+                &structs,
+                "ClassLoaderData::_class_loader",
+                view.field("ClassLoaderData::_class_loader"),
+            ),
+            klasses: view.field::<usize>("ClassLoaderData::_klasses") as _,
+            next: view.field::<usize>("ClassLoaderData::_next") as _,
+            base: ptr,
+        })
+    }
+}
+
+/// The oop held by the field `name`. Newer JVMs keep oops like `Klass::_java_mirror` in an
+/// `OopHandle`, a pointer to the slot holding the oop rather than the oop itself.
+fn resolve_handle(
+    source: &dyn MemorySource,
+    structs: &vmstructs::VMStructs,
+    name: &str,
+    value: usize,
+) -> usize {
+    let is_handle = structs
+        .field(name)
+        .and_then(|field| field.type_string.as_deref())
+        == Some("OopHandle");
+
+    if !is_handle || value == 0 {
+        return value;
+    }
+
+    processes::try_read_exact::<usize>(source, value).unwrap_or_default()
+}
+
+/// The bit of the `ConstMethod` flag `name`, e.g. "ConstMethod::_has_exception_table". JDK 21
+/// exports it as "ConstMethodFlags::_misc_has_exception_table" instead.
+fn const_method_flag(structs: &vmstructs::VMStructs, name: &str, default: i32) -> i32 {
+    let renamed = name.replacen("ConstMethod::_", "ConstMethodFlags::_misc_", 1);
+
+    structs
+        .int_constant(&renamed)
+        .or_else(|| structs.int_constant(name))
+        .unwrap_or(default)
+}
+
+impl FromNative for JMethod {
+    fn from_native(source: &dyn MemorySource, ptr: *mut Self) -> Result<Self, ReadError> {
+        let structs = vmstructs::VM_STRUCTS.lock().unwrap();
+        let view = structs.read_struct(source, "Method", ptr as _)?;
+
+        Ok(Self {
+            const_method: view.field::<usize>("Method::_constMethod") as _,
+            access_flags: view.field("Method::_access_flags"),
+            base: ptr,
+        })
+    }
+}
+
+impl FromNative for JConstMethod {
+    fn from_native(source: &dyn MemorySource, ptr: *mut Self) -> Result<Self, ReadError> {
+        JConstMethod::read_with(source, ptr, &vmstructs::VM_STRUCTS.lock().unwrap())
+    }
+}
+
+impl JConstMethod {
+    fn read_with(
+        source: &dyn MemorySource,
+        ptr: *mut Self,
+        structs: &vmstructs::VMStructs,
+    ) -> Result<Self, ReadError> {
+        let view = structs.read_struct(source, "ConstMethod", ptr as _)?;
+
+        Ok(Self {
+            constants: view.field::<usize>("ConstMethod::_constants") as _,
+            stackmap_data: view.field::<usize>("ConstMethod::_stackmap_data") as _,
+            const_method_size: view.field("ConstMethod::_constMethod_size"),
+            // JDK 21 moved them into a ConstMethodFlags
+            flags: match structs.offset_of("ConstMethod::_flags._flags") {
+                Some(_) => view.field("ConstMethod::_flags._flags"),
+                None => view.field::<u16>("ConstMethod::_flags") as u32,
+            },
+            code_size: view.field("ConstMethod::_code_size"),
+            name_index: view.field("ConstMethod::_name_index"),
+            signature_index: view.field("ConstMethod::_signature_index"),
+            max_stack: view.field("ConstMethod::_max_stack"),
+            max_locals: view.field("ConstMethod::_max_locals"),
+            size_of_parameters: view.field("ConstMethod::_size_of_parameters"),
+            code: (ptr as usize + structs.size_of("ConstMethod").unwrap_or_default()) as _,
+            base: ptr,
+        })
     }
 }
 
 impl FromNative for JSymbol {
-    fn from_native(handle: &NativeHandle, ptr: *mut Self) -> Self {
-        let mut symbol =  processes::read_class::<JSymbol>(handle, ptr as _);
-        symbol.base = ptr;
+    fn from_native(source: &dyn MemorySource, ptr: *mut Self) -> Result<Self, ReadError> {
+        let structs = vmstructs::VM_STRUCTS.lock().unwrap();
+        let view = structs.read_struct(source, "Symbol", ptr as _)?;
 
-        symbol
+        Ok(Self {
+            lenght: view.field("Symbol::_length"),
+            identity: view.field("Symbol::_identity_hash"),
+            text: (ptr as usize + structs.offset_of("Symbol::_body").unwrap_or_default()) as _,
+            base: ptr,
+        })
+    }
+}
+
+impl FromNative for JVMDictionary {
+    fn from_native(source: &dyn MemorySource, ptr: *mut Self) -> Result<Self, ReadError> {
+        let structs = vmstructs::VM_STRUCTS.lock().unwrap();
+        let view = structs.read_struct(source, "BasicHashtable<mtInternal>", ptr as _)?;
+
+        Ok(Self {
+            table_size: view.field("BasicHashtable<mtInternal>::_table_size"),
+            entries: view.field::<usize>("BasicHashtable<mtInternal>::_buckets") as _,
+            no_clue_what_the_hell_this_is: view.field::<usize>("BasicHashtable<mtInternal>::_free_list")
+                as _,
+            free_entry: view.field::<usize>("BasicHashtable<mtInternal>::_first_free_entry") as _,
+            end_block: view.field::<usize>("BasicHashtable<mtInternal>::_end_block") as _,
+            entry_size: view.field("BasicHashtable<mtInternal>::_entry_size"),
+            num_entries: view.field("BasicHashtable<mtInternal>::_number_of_entries"),
+        })
+    }
+}
+
+impl FromNative for DictionaryEntry {
+    fn from_native(source: &dyn MemorySource, ptr: *mut Self) -> Result<Self, ReadError> {
+        let structs = vmstructs::VM_STRUCTS.lock().unwrap();
+        let view = structs.read_struct(source, "DictionaryEntry", ptr as _)?;
+
+        Ok(Self {
+            hash: view.field::<u32>("BasicHashtableEntry<mtInternal>::_hash") as _,
+            next: view.field("BasicHashtableEntry<mtInternal>::_next"),
+            klass: view.field::<usize>("IntptrHashtableEntry::_literal") as _,
+            loader: view.field("DictionaryEntry::_loader_data"),
+        })
     }
 }
 
 impl<T> FromNative for JArray<T> {
-    fn from_native(handle: &NativeHandle, ptr: *mut Self) -> Self {
-        let mut array = processes::read_class::<JArray<T>>(handle, ptr as _);
+    fn from_native(source: &dyn MemorySource, ptr: *mut Self) -> Result<Self, ReadError> {
+        let mut array = processes::try_read_class::<JArray<T>>(source, ptr as _)?;
         array.base = ptr;
 
-        array
+        Ok(array)
     }
 }
 
 impl FieldEntry {
-    pub fn new(jinfo: JFieldInfo, constant_pool: &JConstantPool, handle: &NativeHandle) -> Self {
-        let name = constant_pool
-            .symbol(&handle, jinfo.name_idx() as _)
-            .expect("Unable to get symbol");
-        let signature = constant_pool
-            .symbol(&handle, jinfo.sig_idx() as _)
-            .expect("Unable to get signature symbol");
+    pub fn new(
+        jinfo: JFieldInfo,
+        constant_pool: &JConstantPool,
+        source: &dyn MemorySource,
+    ) -> Result<Self, ReadError> {
+        let name = constant_pool.read_symbol(source, jinfo.name_idx() as _)?;
+        let signature = constant_pool.read_symbol(source, jinfo.sig_idx() as _)?;
 
-        Self {
+        Ok(Self {
+            access_flags: AccessFlags::from_bits(jinfo.access_flags()),
             _field_info: jinfo,
-            name: name.to_string(&handle),
-            sig: signature.to_string(&handle),
+            name: name.to_string(source),
+            sig: signature.to_string(source),
+        })
+    }
+
+    pub fn is_static(&self) -> bool {
+        self.access_flags.is_static()
+    }
+
+    /// Where the field lives, in the mirror of `clazz` for static fields and in `object` for
+    /// instance fields. Statics live in the mirror of the class declaring them, for those
+    /// `clazz` has to be that class and not a subclass.
+    pub fn address(&self, clazz: &JClass, object: usize) -> usize {
+        let base = if self.is_static() {
+            clazz.static_fields as usize
+        } else {
+            object
+        };
+
+        base + self._field_info.offset() as usize
+    }
+
+    /// The value of the field, from the mirror of `clazz` or `object` (see `address`)
+    pub fn read<T>(
+        &self,
+        source: &dyn MemorySource,
+        clazz: &JClass,
+        object: usize,
+    ) -> Result<T, ReadError> {
+        processes::try_read_exact(source, self.address(clazz, object))
+    }
+
+    /// The address of the object the field refers to, decoded like the target encodes it
+    pub fn read_reference(
+        &self,
+        source: &dyn MemorySource,
+        clazz: &JClass,
+        object: usize,
+    ) -> Result<usize, ReadError> {
+        oops::read_oop(source, self.address(clazz, object))
+    }
+}
+
+#[allow(dead_code)]
+impl AccessFlags {
+    pub const PUBLIC: u16 = 0x0001;
+    pub const PRIVATE: u16 = 0x0002;
+    pub const PROTECTED: u16 = 0x0004;
+    pub const STATIC: u16 = 0x0008;
+    pub const FINAL: u16 = 0x0010;
+    pub const VOLATILE: u16 = 0x0040;
+    pub const TRANSIENT: u16 = 0x0080;
+    pub const SYNTHETIC: u16 = 0x1000;
+    pub const ENUM: u16 = 0x4000;
+
+    /// `bits` as the JVM keeps them, HotSpot's own flags are dropped
+    pub fn from_bits(bits: u16) -> Self {
+        Self(bits & classfile::JVM_RECOGNIZED_FIELD_MODIFIERS)
+    }
+
+    pub fn bits(&self) -> u16 {
+        self.0
+    }
+
+    fn has(&self, flag: u16) -> bool {
+        self.0 & flag != 0
+    }
+
+    pub fn is_public(&self) -> bool {
+        self.has(Self::PUBLIC)
+    }
+
+    pub fn is_private(&self) -> bool {
+        self.has(Self::PRIVATE)
+    }
+
+    pub fn is_protected(&self) -> bool {
+        self.has(Self::PROTECTED)
+    }
+
+    pub fn is_static(&self) -> bool {
+        self.has(Self::STATIC)
+    }
+
+    pub fn is_final(&self) -> bool {
+        self.has(Self::FINAL)
+    }
+
+    pub fn is_volatile(&self) -> bool {
+        self.has(Self::VOLATILE)
+    }
+
+    pub fn is_transient(&self) -> bool {
+        self.has(Self::TRANSIENT)
+    }
+
+    pub fn is_synthetic(&self) -> bool {
+        self.has(Self::SYNTHETIC)
+    }
+
+    pub fn is_enum(&self) -> bool {
+        self.has(Self::ENUM)
+    }
+}
+
+impl std::fmt::Display for FieldError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FieldError::NotFound { name, sig } => write!(f, "no field {} {}", name, sig),
+            FieldError::NotStatic(name) => write!(f, "field {} isn't static", name),
+            FieldError::Malformed(reason) => write!(f, "malformed field info: {}", reason),
+            FieldError::Read(error) => write!(f, "{}", error),
         }
     }
 }
 
+impl std::error::Error for FieldError {}
+
+impl From<ReadError> for FieldError {
+    fn from(error: ReadError) -> Self {
+        FieldError::Read(error)
+    }
+}
+
+impl MethodEntry {
+    /// `None` if the method's `ConstMethod`, its name or its signature can't be read
+    pub fn new(
+        method: JMethod,
+        constant_pool: &JConstantPool,
+        source: &dyn MemorySource,
+    ) -> Option<Self> {
+        let const_method = JConstMethod::from_native(source, method.const_method).ok()?;
+
+        let name = constant_pool.symbol(source, const_method.name_index as _)?;
+        let signature = constant_pool.symbol(source, const_method.signature_index as _)?;
+
+        let code = const_method.code(source).ok()?;
+
+        Some(Self {
+            access_flags: method.access_flags as u16,
+            _method: method,
+            name: name.to_string(source),
+            sig: signature.to_string(source),
+            max_locals: const_method.max_locals,
+            max_stack: const_method.max_stack,
+            code_size: const_method.code_size,
+            code,
+        })
+    }
+}
+
+/// One entry of a method's exception table, as in the class file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExceptionTableEntry {
+    pub start_pc: u16,
+    pub end_pc: u16,
+    pub handler_pc: u16,
+    /// Constant pool index of the caught class, 0 catches everything
+    pub catch_type_index: u16,
+}
+
+impl JConstMethod {
+    pub fn code(&self, source: &dyn MemorySource) -> Result<Vec<u8>, ReadError> {
+        let mut buffer: Vec<u8> = vec![0; self.code_size as usize];
+
+        source.read_bytes(self.code as usize, buffer.as_mut_slice())?;
+
+        Ok(buffer)
+    }
+
+    /// The StackMapTable attribute as it was in the class file, empty if the method has none
+    pub fn stackmap_data(&self, source: &dyn MemorySource) -> Result<Vec<u8>, ReadError> {
+        if self.stackmap_data.is_null() {
+            return Ok(Vec::new());
+        }
+
+        let stackmap = JArray::from_native(source, self.stackmap_data as *mut JArray<u8>)?;
+
+        let mut buffer: Vec<u8> = vec![0; stackmap.lenght.max(0) as usize];
+        if !buffer.is_empty() {
+            source.read_bytes(stackmap.adr_at(0) as usize, buffer.as_mut_slice())?;
+        }
+
+        Ok(buffer)
+    }
+
+    /// Whether HotSpot made this method up to resolve a default method conflict, it's not in
+    /// the class file
+    pub fn is_overpass(&self) -> bool {
+        self.has_flag("ConstMethod::_is_overpass", 0x0040)
+    }
+
+    pub fn generic_signature_index(
+        &self,
+        source: &dyn MemorySource,
+    ) -> Result<Option<u16>, ReadError> {
+        if !self.has_flag("ConstMethod::_has_generic_signature", 0x0010) {
+            return Ok(None);
+        }
+
+        processes::try_read_exact::<u16>(source, self.last_u2_element()).map(Some)
+    }
+
+    /// Constant pool indices of the classes in the method's throws clause
+    pub fn checked_exceptions(&self, source: &dyn MemorySource) -> Result<Vec<u16>, ReadError> {
+        if !self.has_flag("ConstMethod::_has_checked_exceptions", 0x0002) {
+            return Ok(Vec::new());
+        }
+
+        let length_address = self.checked_exceptions_length_address(source)?;
+        let length = processes::try_read_exact::<u16>(source, length_address)? as usize;
+
+        self.read_u2s(source, length_address - length * 2, length)
+    }
+
+    pub fn exception_table(
+        &self,
+        source: &dyn MemorySource,
+    ) -> Result<Vec<ExceptionTableEntry>, ReadError> {
+        if !self.has_flag("ConstMethod::_has_exception_table", 0x0008) {
+            return Ok(Vec::new());
+        }
+
+        // the table sits right in front of whichever of these tables comes first
+        let length_address = if self.has_flag("ConstMethod::_has_checked_exceptions", 0x0002) {
+            let checked_length = self.checked_exceptions_length_address(source)?;
+            let length = processes::try_read_exact::<u16>(source, checked_length)? as usize;
+
+            checked_length - length * 2 - 2
+        } else if self.has_flag("ConstMethod::_has_method_parameters", 0x0020) {
+            self.method_parameters_start(source)? - 2
+        } else {
+            self.u2s_end()
+        };
+
+        let length = processes::try_read_exact::<u16>(source, length_address)? as usize;
+        let table = self.read_u2s(source, length_address - length * 8, length * 4)?;
+
+        Ok(table
+            .chunks(4)
+            .map(|entry| ExceptionTableEntry {
+                start_pc: entry[0],
+                end_pc: entry[1],
+                handler_pc: entry[2],
+                catch_type_index: entry[3],
+            })
+            .collect())
+    }
+
+    // everything below mirrors how ConstMethod finds the tables it inlines behind the bytecode,
+    // they're laid out back to front starting at the end of the ConstMethod
+
+    fn has_flag(&self, name: &str, default: i32) -> bool {
+        let flag = const_method_flag(&vmstructs::VM_STRUCTS.lock().unwrap(), name, default);
+
+        self.flags as i32 & flag != 0
+    }
+
+    /// Address of the last u2 of the inlined tables, the annotation pointers come after it
+    fn last_u2_element(&self) -> usize {
+        let annotations = [
+            ("ConstMethod::_has_method_annotations", 0x0080),
+            ("ConstMethod::_has_parameter_annotations", 0x0100),
+            ("ConstMethod::_has_type_annotations", 0x0200),
+            ("ConstMethod::_has_default_annotations", 0x0400),
+        ]
+        .iter()
+        .filter(|(name, default)| self.has_flag(name, *default))
+        .count();
+
+        let end =
+            self.base as usize + self.const_method_size as usize * std::mem::size_of::<usize>();
+
+        end - annotations * std::mem::size_of::<usize>() - 2
+    }
+
+    /// Where the u2 tables end, the generic signature index is behind them
+    fn u2s_end(&self) -> usize {
+        if self.has_flag("ConstMethod::_has_generic_signature", 0x0010) {
+            self.last_u2_element() - 2
+        } else {
+            self.last_u2_element()
+        }
+    }
+
+    fn method_parameters_start(&self, source: &dyn MemorySource) -> Result<usize, ReadError> {
+        let length_address = self.u2s_end();
+        let length = processes::try_read_exact::<u16>(source, length_address)? as usize;
+
+        Ok(length_address - length * 4)
+    }
+
+    fn checked_exceptions_length_address(
+        &self,
+        source: &dyn MemorySource,
+    ) -> Result<usize, ReadError> {
+        if self.has_flag("ConstMethod::_has_method_parameters", 0x0020) {
+            return Ok(self.method_parameters_start(source)? - 2);
+        }
+
+        Ok(self.u2s_end())
+    }
+
+    fn read_u2s(
+        &self,
+        source: &dyn MemorySource,
+        address: usize,
+        count: usize,
+    ) -> Result<Vec<u16>, ReadError> {
+        let mut buffer: Vec<u8> = vec![0; count * 2];
+
+        source.read_bytes(address, buffer.as_mut_slice())?;
+
+        Ok(buffer
+            .chunks(2)
+            .map(|pair| u16::from_ne_bytes([pair[0], pair[1]]))
+            .collect())
+    }
+}
+
 impl JConstantPool {
+    /// Size of the pool header, the slots start right after it
     pub fn size(&self) -> usize {
-        0x50
+        self.header_size
     }
 
-    // pub fn symbol_offset(&self, handle: &NativeHandle, which: isize) -> usize {
+    // pub fn symbol_offset(&self, source: &dyn MemorySource, which: isize) -> usize {
     //     unsafe { ((self.base as usize + self.size()) as *mut *mut JSymbol).offset(which) as _}
     // }
-    pub fn symbol(&self, handle: &NativeHandle, which: isize) -> Option<JSymbol> {
+    pub fn symbol(&self, source: &dyn MemorySource, which: isize) -> Option<JSymbol> {
+        self.read_symbol(source, which).ok()
+    }
+
+    /// The Symbol in slot `which`, an empty slot reads as nothing mapped at 0
+    pub fn read_symbol(
+        &self,
+        source: &dyn MemorySource,
+        which: isize,
+    ) -> Result<JSymbol, ReadError> {
         let address = unsafe {
             ((self.base as usize + self.size()) as *mut *mut JSymbol).offset(which) as usize
         };
-        let mut symbol_addy: usize = 0usize;
+        let symbol_addy = processes::try_read_exact::<usize>(source, address)?;
+
+        if symbol_addy == 0usize {
+            return Err(ReadError::UnmappedAddress(0));
+        }
+
+        JSymbol::from_native(source, symbol_addy as *mut _)
+    }
+}
+
+impl JClassLoaderData {
+    /// Every klass this loader defined, array klasses included
+    pub fn klasses(&self, source: &dyn MemorySource) -> Vec<*mut JClass> {
+        let mut klasses: Vec<*mut JClass> = Vec::new();
+
+        let next_link = vmstructs::VM_STRUCTS
+            .lock()
+            .unwrap()
+            .offset_of("Klass::_next_link");
+
+        let next_link = match next_link {
+            Some(next_link) => next_link,
+            None => return klasses,
+        };
 
-        processes::read(handle, address, &mut symbol_addy);
+        let mut klass = self.klasses as usize;
 
-        if symbol_addy != 0usize {
-            //return Some(processes::read_class::<JSymbol>(handle, symbol_addy).get() as *mut Jsy);
-            return Some(JSymbol::from_native(&handle, symbol_addy as *mut _));
+        while klass != 0 {
+            klasses.push(klass as _);
+
+            // a klass we can't read ends the list, there's no way to get to the next one
+            klass = match processes::try_read_exact::<usize>(source, klass + next_link) {
+                Ok(klass) => klass,
+                Err(_) => break,
+            };
         }
 
-        None
+        klasses
     }
 }
 
 impl JClass {
+    /// The name of the class, packages separated by '/'
+    pub fn name(&self, source: &dyn MemorySource) -> Result<String, ReadError> {
+        JSymbol::from_native(source, self.symbol)?
+            .as_bytes(source)
+            .map(|name| String::from_utf8_lossy(&name).into_owned())
+    }
+
+    /// The oop of the loader that defined this class, 0 for the boot loader
+    pub fn class_loader(&self, source: &dyn MemorySource) -> Result<usize, ReadError> {
+        if self.classloader_data.is_null() {
+            return Ok(0);
+        }
+
+        JClassLoaderData::from_native(source, self.classloader_data as _)
+            .map(|class_loader_data| class_loader_data.class_loader)
+    }
+
+    #[allow(dead_code)]
     pub fn find_field_entry(
         &self,
-        handle: &NativeHandle,
+        source: &dyn MemorySource,
         name: &str,
         sig: &str,
     ) -> Option<FieldEntry> {
-        self.iterate_fields(&handle)
+        self.iterate_fields(source)
             .find(|entry| entry.name.eq(name) && entry.sig.eq(sig))
     }
 
-    #[allow(unused)]
-    pub fn dump_all_fields(&self, handle: &NativeHandle) {
-        self.iterate_fields(&handle).for_each(|entry| {
+    /// The field `name` with its signature `sig`, along with the class declaring it (this one
+    /// or one of its supers)
+    fn find_declared_field(
+        &self,
+        source: &dyn MemorySource,
+        name: &str,
+        sig: &str,
+    ) -> Result<(JClass, FieldEntry), FieldError> {
+        let mut clazz = self.clone();
+
+        loop {
+            let mut fields = Vec::new();
+            clazz.collect_fields(source, &mut fields);
+
+            if let Some(field) = fields
+                .into_iter()
+                .find(|field| field.name == name && field.sig == sig)
+            {
+                return Ok((clazz, field));
+            }
+
+            if clazz.super_klass.is_null() {
+                return Err(FieldError::NotFound {
+                    name: name.to_string(),
+                    sig: sig.to_string(),
+                });
+            }
+
+            clazz = JClass::from_native(source, clazz.super_klass)?;
+        }
+    }
+
+    /// The field `name` of `object`, an instance of this class. Static fields come from the
+    /// mirror of the class declaring them instead, whatever `object` is.
+    #[allow(dead_code)]
+    pub fn read_field<T>(
+        &self,
+        source: &dyn MemorySource,
+        object: usize,
+        name: &str,
+        sig: &str,
+    ) -> Result<T, FieldError> {
+        let (holder, field) = self.find_declared_field(source, name, sig)?;
+
+        Ok(field.read(source, &holder, object)?)
+    }
+
+    /// The static field `name`, read from the mirror of the class declaring it
+    #[allow(dead_code)]
+    pub fn read_static_field<T>(
+        &self,
+        source: &dyn MemorySource,
+        name: &str,
+        sig: &str,
+    ) -> Result<T, FieldError> {
+        let (holder, field) = self.find_declared_field(source, name, sig)?;
+
+        if !field.is_static() {
+            return Err(FieldError::NotStatic(field.name));
+        }
+
+        Ok(field.read(source, &holder, 0)?)
+    }
+
+    /// Like `read_field`, for a field holding a reference. Returns the address of the object
+    /// it refers to, 0 for null.
+    pub fn read_reference_field(
+        &self,
+        source: &dyn MemorySource,
+        object: usize,
+        name: &str,
+        sig: &str,
+    ) -> Result<usize, FieldError> {
+        let (holder, field) = self.find_declared_field(source, name, sig)?;
+
+        Ok(field.read_reference(source, &holder, object)?)
+    }
+
+    /// Like `read_static_field`, for a field holding a reference
+    pub fn read_static_reference_field(
+        &self,
+        source: &dyn MemorySource,
+        name: &str,
+        sig: &str,
+    ) -> Result<usize, FieldError> {
+        let (holder, field) = self.find_declared_field(source, name, sig)?;
+
+        if !field.is_static() {
+            return Err(FieldError::NotStatic(field.name));
+        }
+
+        Ok(field.read_reference(source, &holder, 0)?)
+    }
+
+    #[allow(dead_code)]
+    pub fn dump_all_fields(&self, source: &dyn MemorySource) {
+        self.iterate_fields(source).for_each(|entry| {
             println!(
                 "  Field: {}({}) @ {:p}",
                 entry.name,
@@ -243,54 +1041,97 @@ impl JClass {
         });
     }
 
-    pub fn iterate_fields(&self, handle: &NativeHandle) -> impl Iterator<Item = FieldEntry> {
-        let mut fields: Vec<FieldEntry> = Vec::new();
-        let fields_array = JArray::from_native(&handle, self.fields);
+    #[allow(dead_code)]
+    pub fn find_method_entry(
+        &self,
+        source: &dyn MemorySource,
+        name: &str,
+        sig: &str,
+    ) -> Option<MethodEntry> {
+        self.iterate_methods(source)
+            .find(|entry| entry.name.eq(name) && entry.sig.eq(sig))
+    }
 
-        let constant_pool = JConstantPool::from_native(&handle, self.constant_pool);
+    /// The methods declared by this class, unlike fields methods of the supers aren't included.
+    /// Methods we can't read are skipped.
+    pub fn iterate_methods(&self, source: &dyn MemorySource) -> impl Iterator<Item = MethodEntry> {
+        let mut methods: Vec<MethodEntry> = Vec::new();
 
-        for i in 0..fields_array.lenght {
-            if fields_array.adr_at(i * JFieldOffset::FieldSlots.value()) as usize == 0usize {
-                continue;
-            }
+        let methods_array = match JArray::<*mut JMethod>::from_native(source, self.methods as _) {
+            Ok(methods_array) => methods_array,
+            Err(_) => return methods.into_iter(),
+        };
 
-            let field_info = JFieldInfo::from_native(
-                handle,
-                fields_array.adr_at(i * JFieldOffset::FieldSlots.value()) as _,
-            );
-            fields.push(FieldEntry::new(field_info, &constant_pool, handle));
+        let constant_pool = match JConstantPool::from_native(source, self.constant_pool) {
+            Ok(constant_pool) => constant_pool,
+            Err(_) => return methods.into_iter(),
+        };
+
+        for i in 0..methods_array.lenght {
+            let method = match methods_array.at(i, source) {
+                Some(method) if !method.is_null() => method,
+                _ => continue,
+            };
+
+            if let Some(entry) = JMethod::from_native(source, method)
+                .ok()
+                .and_then(|method| MethodEntry::new(method, &constant_pool, source))
+            {
+                methods.push(entry);
+            }
         }
 
+        methods.into_iter()
+    }
+
+    pub fn iterate_fields(&self, source: &dyn MemorySource) -> impl Iterator<Item = FieldEntry> {
+        let mut fields: Vec<FieldEntry> = Vec::new();
+
+        self.collect_fields(source, &mut fields);
+
         if !self.super_klass.is_null() {
-            let mut clazz = JClass::from_native(&handle, self.super_klass);
+            // a super class we can't read cuts the walk short, we still have what we found so far
+            let mut clazz = match JClass::from_native(source, self.super_klass) {
+                Ok(clazz) => clazz,
+                Err(_) => return fields.into_iter(),
+            };
 
             loop {
-                let fields_array = JArray::from_native(&handle, clazz.fields);
-                let constant_pool = JConstantPool::from_native(&handle, clazz.constant_pool);
-
-                for i in 0..fields_array.lenght {
-                    if fields_array.adr_at(i * JFieldOffset::FieldSlots.value()) as usize == 0usize
-                    {
-                        continue;
-                    }
-
-                    let field_info = JFieldInfo::from_native(
-                        handle,
-                        fields_array.adr_at(i * JFieldOffset::FieldSlots.value()) as _,
-                    );
-                    fields.push(FieldEntry::new(field_info, &constant_pool, handle));
-                }
+                clazz.collect_fields(source, &mut fields);
 
                 if clazz.super_klass.is_null() {
                     break;
                 }
 
-                clazz = JClass::from_native(&handle, clazz.super_klass)
+                clazz = match JClass::from_native(source, clazz.super_klass) {
+                    Ok(clazz) => clazz,
+                    Err(_) => break,
+                };
             }
         }
 
         fields.into_iter()
     }
+
+    /// Push the fields declared by this class (not its supers), none if we can't read them and
+    /// without the ones whose name or signature we can't read
+    fn collect_fields(&self, source: &dyn MemorySource, fields: &mut Vec<FieldEntry>) {
+        let infos = match fieldinfo::declared_fields(source, self) {
+            Ok(infos) => infos,
+            Err(_) => return,
+        };
+
+        let constant_pool = match JConstantPool::from_native(source, self.constant_pool) {
+            Ok(constant_pool) => constant_pool,
+            Err(_) => return,
+        };
+
+        for info in infos {
+            if let Ok(entry) = FieldEntry::new(info.packed(), &constant_pool, source) {
+                fields.push(entry);
+            }
+        }
+    }
 }
 
 impl JFieldOffset {
@@ -331,17 +1172,30 @@ impl JFieldInfo {
     pub fn sig_idx(&self) -> u16 {
         self._shorts[JFieldOffset::SignatureIndexOffset.value() as usize]
     }
+
+    /// The class file flags plus HotSpot's own ones above them
+    pub fn access_flags(&self) -> u16 {
+        self._shorts[JFieldOffset::AccessFlagsOffset.value() as usize]
+    }
+
+    /// Constant pool index of the ConstantValue of a static field, 0 if it has none
+    pub fn initval_idx(&self) -> u16 {
+        self._shorts[JFieldOffset::InitvalIndexOffset.value() as usize]
+    }
 }
 
 impl<T> JArray<T> {
+    /// The elements follow the length, aligned for `T` (so pointers start at 8, shorts at 4)
+    fn data_offset() -> usize {
+        std::mem::size_of::<i32>().max(std::mem::align_of::<T>())
+    }
+
     #[allow(dead_code)]
-    pub fn at(&self, i: i32, handle: &NativeHandle) -> Option<T> {
+    pub fn at(&self, i: i32, source: &dyn MemorySource) -> Option<T> {
         if i >= 0 && i < self.lenght {
             let result = processes::read_exact::<T>(
-                handle,
-                self.base as usize
-                    + std::mem::size_of::<i32>()
-                    + std::mem::size_of::<T>().mul(i as usize),
+                source,
+                self.base as usize + Self::data_offset() + std::mem::size_of::<T>().mul(i as usize),
             );
             return Some(result);
         }
@@ -358,7 +1212,7 @@ impl<T> JArray<T> {
         if i >= 0 && i < self.lenght {
             //return unsafe { self.data.as_ptr().offset(i as _) as *const T };
             return (self.base as usize
-                + std::mem::size_of::<i32>()
+                + Self::data_offset()
                 + std::mem::size_of::<T>().mul(i as usize)) as _;
         }
 
@@ -367,23 +1221,21 @@ impl<T> JArray<T> {
 }
 
 impl JSymbol {
-    pub fn to_string(&self, handle: &NativeHandle) -> String {
-        // buffer for our string
+    /// The raw bytes of the symbol, (modified) UTF-8 in any sane symbol
+    pub fn as_bytes(&self, source: &dyn MemorySource) -> Result<Vec<u8>, ReadError> {
         // note that these strings don't seem to have an end denominator?
-        let mut buffer: Vec<u8> = Vec::new();
-        buffer.resize(self.lenght as _, 0);
+        let mut buffer: Vec<u8> = vec![0; self.lenght as u16 as usize];
 
-        unsafe {
-            ReadProcessMemory(
-                handle.get(),
-                (self.base as usize + 0x0008) as _,
-                buffer.as_mut_ptr() as _,
-                buffer.len(),
-                std::ptr::null_mut(),
-            );
+        source.read_bytes(self.text as usize, buffer.as_mut_slice())?;
 
-            String::from_utf8_lossy(buffer.as_slice()).to_string()
-        }
+        Ok(buffer)
+    }
+
+    pub fn to_string(&self, source: &dyn MemorySource) -> String {
+        // buffer for our string
+        let buffer = self.as_bytes(source).unwrap_or_default();
+
+        String::from_utf8_lossy(buffer.as_slice()).to_string()
     }
 }
 
@@ -408,3 +1260,349 @@ impl Default for JVMDictionary {
 }
 
 unsafe impl Send for JVMDictionary {}
+
+#[cfg(test)]
+mod tests {
+    use super::testing::{field_info, FakeField, FakeMethod, JvmImage};
+    use super::*;
+
+    /// `Object` declaring nothing, `Entity` declaring `posX`, `Player` extending it with `name`
+    fn player_image() -> (JvmImage, usize) {
+        let mut image = JvmImage::new(0x7f00_0000, 0x4000);
+
+        let object = image.class("java/lang/Object", 0, &[]);
+        let entity = image.class(
+            "pk",
+            object,
+            &[FakeField {
+                name: "posX",
+                sig: "D",
+                offset: 0x10,
+            }],
+        );
+        let player = image.class(
+            "wn",
+            entity,
+            &[
+                FakeField {
+                    name: "name",
+                    sig: "Ljava/lang/String;",
+                    offset: 0x18,
+                },
+                FakeField {
+                    name: "health",
+                    sig: "F",
+                    offset: 0x2_0004,
+                },
+            ],
+        );
+
+        (image, player)
+    }
+
+    #[test]
+    fn decodes_field_entry() {
+        let (image, player) = player_image();
+
+        let clazz = JClass::from_native(&image, player as _).unwrap();
+        let constant_pool = JConstantPool::from_native(&image, clazz.constant_pool).unwrap();
+        let fields = JArray::from_native(&image, clazz.fields).unwrap();
+
+        assert_eq!(fields.lenght, 2 * JFieldOffset::FieldSlots.value());
+
+        let info = JFieldInfo::from_native(
+            &image,
+            fields.adr_at(JFieldOffset::FieldSlots.value()) as _,
+        )
+        .unwrap();
+        let entry = FieldEntry::new(info, &constant_pool, &image).unwrap();
+
+        assert_eq!(entry.name, "health");
+        assert_eq!(entry.sig, "F");
+        // needs both packed shorts
+        assert_eq!(entry._field_info.offset(), 0x2_0004);
+        assert!(entry._field_info.has_offset());
+    }
+
+    #[test]
+    fn finds_own_and_inherited_fields() {
+        let (image, player) = player_image();
+
+        let clazz = JClass::from_native(&image, player as _).unwrap();
+
+        let name = clazz
+            .find_field_entry(&image, "name", "Ljava/lang/String;")
+            .expect("own field not found");
+        assert_eq!(name._field_info.offset(), 0x18);
+
+        let position = clazz
+            .find_field_entry(&image, "posX", "D")
+            .expect("inherited field not found");
+        assert_eq!(position._field_info.offset(), 0x10);
+
+        assert!(clazz.find_field_entry(&image, "posX", "F").is_none());
+        assert_eq!(clazz.iterate_fields(&image).count(), 3);
+    }
+
+    #[test]
+    fn skips_unreadable_field_entries() {
+        let (mut image, player) = player_image();
+
+        let clazz = JClass::from_native(&image, player as _).unwrap();
+        let constant_pool = JConstantPool::from_native(&image, clazz.constant_pool).unwrap();
+        let fields = JArray::from_native(&image, clazz.fields).unwrap();
+        let info = JFieldInfo::from_native(
+            &image,
+            fields.adr_at(JFieldOffset::FieldSlots.value()) as _,
+        )
+        .unwrap();
+
+        // the name of `health` goes missing from the constant pool
+        let slot = constant_pool.base as usize
+            + constant_pool.size()
+            + info.name_idx() as usize * std::mem::size_of::<usize>();
+        image.write(slot, &0usize);
+
+        assert_eq!(
+            FieldEntry::new(info, &constant_pool, &image).err(),
+            Some(ReadError::UnmappedAddress(0))
+        );
+
+        assert!(clazz.find_field_entry(&image, "health", "F").is_none());
+        assert_eq!(clazz.iterate_fields(&image).count(), 2);
+    }
+
+    #[test]
+    fn unreadable_super_keeps_own_fields() {
+        let mut image = JvmImage::new(0x7f00_0000, 0x1000);
+
+        let clazz = image.class(
+            "wn",
+            0xdead_0000,
+            &[FakeField {
+                name: "name",
+                sig: "Ljava/lang/String;",
+                offset: 0x18,
+            }],
+        );
+        let clazz = JClass::from_native(&image, clazz as _).unwrap();
+
+        let fields = clazz.iterate_fields(&image).collect::<Vec<FieldEntry>>();
+        assert_eq!(fields.len(), 1);
+        assert_eq!(fields[0].name, "name");
+    }
+
+    #[test]
+    fn reads_static_and_instance_fields() {
+        let mut image = JvmImage::new(0x7f00_0000, 0x2000);
+
+        let base_mirror = image.alloc(0x80);
+        let mirror = image.alloc(0x80);
+        let object = image.alloc(0x20);
+        image.write(base_mirror + 0x70, &42i32);
+        image.write(mirror + 0x70, &-1i32);
+        image.write(object + 0x0c, &7i32);
+
+        let count_name = image.symbol(b"count");
+        let int_sig = image.symbol(b"I");
+        let health_name = image.symbol(b"health");
+        let constant_pool = image.constant_pool(&[0, count_name, int_sig, health_name]);
+
+        // public static plus HotSpot's JVM_ACC_FIELD_ACCESS_WATCHED
+        let mut count = field_info(1, 2, 0x70);
+        count[0] = AccessFlags::PUBLIC | AccessFlags::STATIC | 0x2000;
+        let mut health = field_info(3, 2, 0x0c);
+        health[0] = AccessFlags::PRIVATE | AccessFlags::FINAL;
+
+        let base = JClass {
+            symbol: image.symbol(b"pk") as _,
+            constant_pool: constant_pool as _,
+            fields: image.fields(&[count]) as _,
+            static_fields: base_mirror as _,
+            ..Default::default()
+        };
+        let base = image.class_from(&base);
+
+        let clazz = JClass {
+            symbol: image.symbol(b"wn") as _,
+            super_klass: base as _,
+            constant_pool: constant_pool as _,
+            fields: image.fields(&[health]) as _,
+            static_fields: mirror as _,
+            ..Default::default()
+        };
+        let clazz = image.class_from(&clazz);
+        let clazz = JClass::from_native(&image, clazz as _).unwrap();
+
+        let count = clazz.find_field_entry(&image, "count", "I").unwrap();
+        assert!(count.is_static());
+        assert!(count.access_flags.is_public());
+        assert_eq!(count.access_flags.bits(), 0x0009);
+
+        let health = clazz.find_field_entry(&image, "health", "I").unwrap();
+        assert!(!health.is_static());
+        assert!(health.access_flags.is_private() && health.access_flags.is_final());
+
+        // statics come from the mirror of the class declaring them
+        assert_eq!(clazz.read_static_field::<i32>(&image, "count", "I"), Ok(42));
+        assert_eq!(
+            clazz.read_field::<i32>(&image, object, "count", "I"),
+            Ok(42)
+        );
+        assert_eq!(
+            clazz.read_field::<i32>(&image, object, "health", "I"),
+            Ok(7)
+        );
+
+        assert_eq!(
+            clazz.read_static_field::<i32>(&image, "health", "I"),
+            Err(FieldError::NotStatic("health".to_string()))
+        );
+        assert!(matches!(
+            clazz.read_field::<i32>(&image, object, "health", "J"),
+            Err(FieldError::NotFound { .. })
+        ));
+    }
+
+    #[test]
+    fn enumerates_declared_methods() {
+        let mut image = JvmImage::new(0x7f00_0000, 0x2000);
+
+        let object = image.class_with_methods(
+            "java/lang/Object",
+            0,
+            &[],
+            &[FakeMethod {
+                name: "hashCode",
+                sig: "()I",
+                access_flags: 0x0101,
+                max_stack: 0,
+                max_locals: 1,
+                code: &[],
+                exception_table: &[],
+            }],
+        );
+        let minecraft = image.class_with_methods(
+            "ave",
+            object,
+            &[],
+            &[
+                FakeMethod {
+                    name: "<init>",
+                    sig: "()V",
+                    access_flags: 0x0001,
+                    max_stack: 1,
+                    max_locals: 1,
+                    // aload_0, invokespecial #1, return
+                    code: &[0x2a, 0xb7, 0x00, 0x01, 0xb1],
+                    exception_table: &[],
+                },
+                FakeMethod {
+                    name: "getMinecraft",
+                    sig: "()Lave;",
+                    access_flags: 0x0009,
+                    max_stack: 1,
+                    max_locals: 0,
+                    // getstatic #2, areturn
+                    code: &[0xb2, 0x00, 0x02, 0xb0],
+                    exception_table: &[],
+                },
+            ],
+        );
+
+        let clazz = JClass::from_native(&image, minecraft as _).unwrap();
+
+        let methods = clazz.iterate_methods(&image).collect::<Vec<MethodEntry>>();
+        assert_eq!(methods.len(), 2);
+        assert_eq!(methods[0].name, "<init>");
+        assert_eq!(methods[0].code, vec![0x2a, 0xb7, 0x00, 0x01, 0xb1]);
+
+        let entry = clazz
+            .find_method_entry(&image, "getMinecraft", "()Lave;")
+            .expect("method not found");
+        assert_eq!(entry.access_flags, 0x0009);
+        assert_eq!(entry.max_stack, 1);
+        assert_eq!(entry.max_locals, 0);
+        assert_eq!(entry.code_size, 4);
+        assert_eq!(entry.code, vec![0xb2, 0x00, 0x02, 0xb0]);
+
+        // methods aren't inherited
+        assert!(clazz.find_method_entry(&image, "hashCode", "()I").is_none());
+    }
+
+    #[test]
+    fn reads_jdk21_const_method_flags() {
+        let mut image = JvmImage::new(0x7f00_0000, 0x1000);
+
+        let method = image.method(
+            1,
+            2,
+            &FakeMethod {
+                name: "run",
+                sig: "()V",
+                access_flags: 0x0001,
+                max_stack: 0,
+                max_locals: 1,
+                code: &[0xb1],
+                exception_table: &[[0, 1, 0, 0]],
+            },
+        );
+        let method = JMethod::from_native(&image, method as _).unwrap();
+        let const_method = method.const_method as usize;
+
+        // JDK 21 keeps the flags in a u4 of its own, the old u2 doesn't exist anymore
+        let mut structs = vmstructs::VMStructs::jdk8();
+        structs.insert_field(
+            "ConstMethod::_flags._flags",
+            vmstructs::VMField {
+                offset: 0x002C,
+                is_static: false,
+                address: 0,
+                type_string: Some("u4".to_string()),
+            },
+        );
+        image.write(const_method + 0x001C, &0u16);
+        image.write(const_method + 0x002C, &0x0008u32);
+
+        let jdk8 = JConstMethod::from_native(&image, const_method as _).unwrap();
+        assert!(jdk8.exception_table(&image).unwrap().is_empty());
+
+        let jdk21 = JConstMethod::read_with(&image, const_method as _, &structs).unwrap();
+        assert_eq!(jdk21.flags, 0x0008);
+        assert_eq!(
+            jdk21.exception_table(&image).unwrap(),
+            [ExceptionTableEntry {
+                start_pc: 0,
+                end_pc: 1,
+                handler_pc: 0,
+                catch_type_index: 0,
+            }]
+        );
+
+        // and names the flag constants after ConstMethodFlags
+        structs.add_int_constant("ConstMethodFlags::_misc_has_exception_table", 0x1000);
+        assert_eq!(
+            const_method_flag(&structs, "ConstMethod::_has_exception_table", 0x0008),
+            0x1000
+        );
+        assert_eq!(
+            const_method_flag(&structs, "ConstMethod::_is_overpass", 0x0040),
+            0x0040
+        );
+    }
+
+    #[test]
+    fn reads_symbols_from_constant_pool() {
+        let mut image = JvmImage::new(0x7f00_0000, 0x1000);
+
+        let symbol = image.symbol(b"getMinecraft");
+        let constant_pool = image.constant_pool(&[0, 0, symbol]);
+        let constant_pool = JConstantPool::from_native(&image, constant_pool as _).unwrap();
+
+        assert!(constant_pool.symbol(&image, 1).is_none());
+        assert_eq!(
+            constant_pool.symbol(&image, 2).unwrap().to_string(&image),
+            "getMinecraft"
+        );
+    }
+}
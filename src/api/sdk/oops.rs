@@ -0,0 +1,256 @@
+//! References as the Java heap holds them. With compressed oops (the default for heaps below
+//! 32 GB) a reference is a u4 that is shifted and added to a base to get an address, and the same
+//! goes for the klass pointer in an object's header with compressed class pointers.
+
+use std::sync::Mutex;
+
+use crate::api::processes::{self, MemorySource, ReadError};
+
+use super::vmstructs::VMStructs;
+
+lazy_static::lazy_static! {
+    /// How the target encodes its references, zero based and unscaled until it's been read
+    pub static ref COMPRESSED_OOPS: Mutex<CompressedOops> = Mutex::new(CompressedOops::default());
+}
+
+// where the base and shift live, JDK 8 keeps them in Universe and later JDKs moved them out
+
+const NARROW_OOP: &[(&str, &str)] = &[
+    (
+        "CompressedOops::_narrow_oop._base",
+        "CompressedOops::_narrow_oop._shift",
+    ),
+    ("CompressedOops::_base", "CompressedOops::_shift"),
+    (
+        "Universe::_narrow_oop._base",
+        "Universe::_narrow_oop._shift",
+    ),
+];
+
+const NARROW_KLASS: &[(&str, &str)] = &[
+    (
+        "CompressedKlassPointers::_narrow_klass._base",
+        "CompressedKlassPointers::_narrow_klass._shift",
+    ),
+    (
+        "CompressedKlassPointers::_base",
+        "CompressedKlassPointers::_shift",
+    ),
+    (
+        "Universe::_narrow_klass._base",
+        "Universe::_narrow_klass._shift",
+    ),
+];
+
+/// How a narrow pointer turns into an address, `base + (narrow << shift)`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NarrowEncoding {
+    pub base: usize,
+    pub shift: u32,
+}
+
+impl NarrowEncoding {
+    /// The address `narrow` stands for, null stays null
+    pub fn decode(&self, narrow: u32) -> usize {
+        if narrow == 0 {
+            return 0;
+        }
+
+        self.base.wrapping_add((narrow as usize) << self.shift)
+    }
+}
+
+/// The encodings the target uses, `None` where it keeps full pointers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompressedOops {
+    pub oops: Option<NarrowEncoding>,
+    pub klass_pointers: Option<NarrowEncoding>,
+}
+
+impl Default for CompressedOops {
+    /// What the sdk assumed all along, a heap in the low 4 GB read straight from the u4
+    fn default() -> Self {
+        Self {
+            oops: Some(NarrowEncoding::default()),
+            klass_pointers: Some(NarrowEncoding::default()),
+        }
+    }
+}
+
+fn read_encoding(
+    source: &dyn MemorySource,
+    structs: &VMStructs,
+    names: &[(&str, &str)],
+) -> NarrowEncoding {
+    names
+        .iter()
+        .find_map(|(base, shift)| {
+            let base = structs.static_address(base)?;
+            let shift = structs.static_address(shift)?;
+
+            Some(NarrowEncoding {
+                base: processes::try_read_exact::<usize>(source, base).ok()?,
+                shift: processes::try_read_exact::<i32>(source, shift).ok()? as u32,
+            })
+        })
+        .unwrap_or_default()
+}
+
+impl CompressedOops {
+    /// Read the encodings out of the target. A JVM that won't tell whether compression is on is
+    /// taken to have it on, like it is by default.
+    pub fn read(source: &dyn MemorySource, structs: &VMStructs) -> Self {
+        let compressed_oops = structs
+            .bool_flag(source, "UseCompressedOops")
+            .unwrap_or(true);
+        // JDK 8 can't compress class pointers without compressing oops
+        let compressed_klass_pointers = structs
+            .bool_flag(source, "UseCompressedClassPointers")
+            .unwrap_or(compressed_oops);
+
+        Self {
+            oops: if compressed_oops {
+                Some(read_encoding(source, structs, NARROW_OOP))
+            } else {
+                None
+            },
+            klass_pointers: if compressed_klass_pointers {
+                Some(read_encoding(source, structs, NARROW_KLASS))
+            } else {
+                None
+            },
+        }
+    }
+
+    pub fn install(self) {
+        *COMPRESSED_OOPS.lock().unwrap() = self;
+    }
+
+    /// How many bytes a reference takes up in an object or array
+    pub fn oop_size(&self) -> usize {
+        match self.oops {
+            Some(_) => std::mem::size_of::<u32>(),
+            None => std::mem::size_of::<usize>(),
+        }
+    }
+
+    /// The address of the object a narrow reference points at
+    pub fn decode_oop(&self, narrow: u32) -> usize {
+        match self.oops {
+            Some(encoding) => encoding.decode(narrow),
+            None => narrow as usize,
+        }
+    }
+
+    /// The address of the `Klass` a narrow klass pointer points at
+    pub fn decode_klass(&self, narrow: u32) -> usize {
+        match self.klass_pointers {
+            Some(encoding) => encoding.decode(narrow),
+            None => narrow as usize,
+        }
+    }
+
+    /// Follow the reference at `address`, a field or an array element
+    pub fn read_oop(&self, source: &dyn MemorySource, address: usize) -> Result<usize, ReadError> {
+        match self.oops {
+            Some(encoding) => Ok(encoding.decode(processes::try_read_exact(source, address)?)),
+            None => processes::try_read_exact(source, address),
+        }
+    }
+}
+
+/// Follow the reference at `address` with the encoding of the target
+pub fn read_oop(source: &dyn MemorySource, address: usize) -> Result<usize, ReadError> {
+    let compressed_oops = *COMPRESSED_OOPS.lock().unwrap();
+
+    compressed_oops.read_oop(source, address)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::api::sdk::{testing::JvmImage, vmstructs::VMField};
+
+    fn static_field(structs: &mut VMStructs, name: &str, address: usize) {
+        structs.insert_field(
+            name,
+            VMField {
+                offset: 0,
+                is_static: true,
+                address,
+                type_string: None,
+            },
+        );
+    }
+
+    #[test]
+    fn decodes_with_base_and_shift() {
+        let encoding = NarrowEncoding {
+            base: 0x7f00_0000_0000,
+            shift: 3,
+        };
+
+        assert_eq!(encoding.decode(0), 0);
+        assert_eq!(encoding.decode(0x1234), 0x7f00_0000_91a0);
+        assert_eq!(NarrowEncoding::default().decode(0xfeed_0000), 0xfeed_0000);
+    }
+
+    #[test]
+    fn reads_encodings_and_oops() {
+        let mut image = JvmImage::new(0x7f00_0000, 0x1000);
+
+        let narrow_oop = image.alloc(0x10);
+        image.write(narrow_oop, &0x0800_0000_0000usize);
+        image.write(narrow_oop + 8, &3i32);
+
+        let narrow_klass = image.alloc(0x10);
+        image.write(narrow_klass, &0x0008_0000_0000usize);
+
+        // a JDK 17 that won't tell about its flags
+        let mut structs = VMStructs::default();
+        static_field(
+            &mut structs,
+            "CompressedOops::_narrow_oop._base",
+            narrow_oop,
+        );
+        static_field(
+            &mut structs,
+            "CompressedOops::_narrow_oop._shift",
+            narrow_oop + 8,
+        );
+        static_field(
+            &mut structs,
+            "CompressedKlassPointers::_narrow_klass._base",
+            narrow_klass,
+        );
+        static_field(
+            &mut structs,
+            "CompressedKlassPointers::_narrow_klass._shift",
+            narrow_klass + 8,
+        );
+
+        let compressed_oops = CompressedOops::read(&image, &structs);
+        assert_eq!(compressed_oops.oop_size(), 4);
+        assert_eq!(compressed_oops.decode_klass(0x10), 0x0008_0000_0010);
+
+        let reference = image.alloc(8);
+        image.write(reference, &0x0020_0000u32);
+        assert_eq!(
+            compressed_oops.read_oop(&image, reference),
+            Ok(0x0800_0100_0000)
+        );
+
+        // full pointers are read as they are
+        let uncompressed = CompressedOops {
+            oops: None,
+            klass_pointers: None,
+        };
+        image.write(reference, &0x7f00_dead_0000usize);
+        assert_eq!(uncompressed.oop_size(), 8);
+        assert_eq!(
+            uncompressed.read_oop(&image, reference),
+            Ok(0x7f00_dead_0000)
+        );
+    }
+}
@@ -0,0 +1,794 @@
+//! Objects in the target read field by field, by name, the way reflection would. The fields are
+//! looked up through the object's runtime class and its supers once, after that their offsets
+//! come from a cache kept per memory source.
+//!
+//! Views of classes the sdk knows ahead of time are declared with `#[derive(RemoteClass)]`
+//! instead, those look their fields up in the class they name.
+
+use std::{collections::HashMap, sync::Mutex};
+
+use crate::{
+    api::processes::{self, MemorySource, ReadError, SourceId},
+    ether::CLASSES,
+};
+
+use super::{
+    header,
+    java::{ArrayElement, JavaArray},
+    oops, string, FieldError, FromNative, JClass,
+};
+
+pub use ethe_rs_derive::RemoteClass;
+
+lazy_static::lazy_static! {
+    /// The fields looked up so far, by the source and klass they were looked up from and their
+    /// name
+    static ref FIELD_CACHE: Mutex<HashMap<(SourceId, usize, String), ResolvedField>> =
+        Mutex::new(HashMap::new());
+}
+
+/// A field found by name, `holder` is the klass declaring it if it's static
+#[derive(Debug, Clone)]
+struct ResolvedField {
+    sig: String,
+    offset: u64,
+    holder: Option<usize>,
+}
+
+impl ResolvedField {
+    /// Where the field lives for `object`. Static fields are in the mirror of the class declaring
+    /// them, which the GC moves around, so it's looked up every time.
+    fn address(&self, source: &dyn MemorySource, object: usize) -> Result<usize, ReadError> {
+        let base = match self.holder {
+            Some(holder) => JClass::from_native(source, holder as _)?.static_fields as usize,
+            None => object,
+        };
+
+        Ok(base + self.offset as usize)
+    }
+}
+
+/// Why a field of a remote object couldn't be read
+#[derive(Debug, Clone, PartialEq)]
+pub enum RemoteError {
+    /// The class a `RemoteClass` is declared for isn't loaded
+    NoSuchClass(String),
+
+    /// An array was read past its end
+    IndexOutOfBounds {
+        index: usize,
+        length: usize,
+    },
+
+    /// The object is an instance of `found`, which isn't what it was read as
+    WrongClass {
+        expected: &'static str,
+        found: String,
+    },
+
+    /// Neither the class nor its supers have a field by this name
+    NoSuchField {
+        class: String,
+        name: String,
+    },
+
+    /// An instance field was read as a static one
+    NotStatic(String),
+
+    /// The field holds something other than what was asked for, `found` is its signature
+    WrongType {
+        name: String,
+        expected: &'static str,
+        found: String,
+    },
+
+    Read(ReadError),
+}
+
+impl std::fmt::Display for RemoteError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RemoteError::NoSuchClass(class) => write!(f, "class {} isn't loaded", class),
+            RemoteError::IndexOutOfBounds { index, length } => {
+                write!(f, "index {} is out of bounds for length {}", index, length)
+            }
+            RemoteError::WrongClass { expected, found } => {
+                write!(f, "object is a {}, not a {}", found, expected)
+            }
+            RemoteError::NoSuchField { class, name } => {
+                write!(f, "{} has no field {}", class, name)
+            }
+            RemoteError::NotStatic(name) => write!(f, "field {} isn't static", name),
+            RemoteError::WrongType {
+                name,
+                expected,
+                found,
+            } => write!(f, "field {} is a {}, not a {}", name, found, expected),
+            RemoteError::Read(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for RemoteError {}
+
+impl From<ReadError> for RemoteError {
+    fn from(error: ReadError) -> Self {
+        RemoteError::Read(error)
+    }
+}
+
+/// What a field can be read as
+pub trait RemoteValue: Sized {
+    /// The signature (or kind of signature) a field needs, for `RemoteError::WrongType`
+    const EXPECTED: &'static str;
+
+    fn matches(sig: &str) -> bool {
+        sig == Self::EXPECTED
+    }
+
+    /// Read the value of the field living at `address`
+    fn read(source: &dyn MemorySource, address: usize) -> Result<Self, RemoteError>;
+}
+
+macro_rules! primitive_value {
+    ($($type:ty => $sig:literal),*) => {
+        $(
+            impl RemoteValue for $type {
+                const EXPECTED: &'static str = $sig;
+
+                fn read(source: &dyn MemorySource, address: usize) -> Result<Self, RemoteError> {
+                    Ok(processes::try_read_exact(source, address)?)
+                }
+            }
+        )*
+    };
+}
+
+primitive_value!(i8 => "B", u16 => "C", i16 => "S", i32 => "I", i64 => "J", f32 => "F", f64 => "D");
+
+impl RemoteValue for bool {
+    const EXPECTED: &'static str = "Z";
+
+    fn read(source: &dyn MemorySource, address: usize) -> Result<Self, RemoteError> {
+        Ok(processes::try_read_exact::<u8>(source, address)? != 0)
+    }
+}
+
+/// The object a reference field points at, `None` for null
+impl RemoteValue for Option<RemoteObject> {
+    const EXPECTED: &'static str = "reference";
+
+    fn matches(sig: &str) -> bool {
+        sig.starts_with('L') || sig.starts_with('[')
+    }
+
+    fn read(source: &dyn MemorySource, address: usize) -> Result<Self, RemoteError> {
+        match oops::read_oop(source, address)? {
+            0 => Ok(None),
+            object => RemoteObject::new(source, object).map(Some),
+        }
+    }
+}
+
+/// The java.lang.String a field points at, `None` for null
+impl RemoteValue for Option<String> {
+    const EXPECTED: &'static str = "Ljava/lang/String;";
+
+    fn read(source: &dyn MemorySource, address: usize) -> Result<Self, RemoteError> {
+        match oops::read_oop(source, address)? {
+            0 => Ok(None),
+            string => string::read_string(source, string).map(Some),
+        }
+    }
+}
+
+/// The array a field points at, `None` for null
+impl<T: ArrayElement> RemoteValue for Option<JavaArray<T>> {
+    const EXPECTED: &'static str = "array";
+
+    fn matches(sig: &str) -> bool {
+        sig.starts_with('[') && T::matches(&sig[1..])
+    }
+
+    fn read(source: &dyn MemorySource, address: usize) -> Result<Self, RemoteError> {
+        match oops::read_oop(source, address)? {
+            0 => Ok(None),
+            array => Ok(Some(JavaArray::read(source, array)?)),
+        }
+    }
+}
+
+/// A view of a Java class with its fields declared up front, see `#[derive(RemoteClass)]`
+#[allow(dead_code)]
+pub trait RemoteClass: Sized {
+    /// The internal name of the class, e.g. "java/lang/Thread"
+    const CLASS: &'static str;
+
+    /// Every declared field of the object at `address`
+    fn read(source: &dyn MemorySource, address: usize) -> Result<Self, RemoteError>;
+
+    fn address(&self) -> usize;
+}
+
+/// A field a `RemoteClass` declares, looked up in its class the first time it's read from a source
+pub struct RemoteField {
+    class: &'static str,
+    name: &'static str,
+    sig: &'static str,
+    resolved: Mutex<Option<(SourceId, ResolvedField)>>,
+}
+
+impl RemoteField {
+    pub const fn new(class: &'static str, name: &'static str, sig: &'static str) -> Self {
+        Self {
+            class,
+            name,
+            sig,
+            resolved: Mutex::new(None),
+        }
+    }
+
+    fn resolve(&self, source: &dyn MemorySource) -> Result<ResolvedField, RemoteError> {
+        if let Some((id, field)) = self.resolved.lock().unwrap().as_ref() {
+            if *id == source.id() {
+                return Ok(field.clone());
+            }
+        }
+
+        let clazz = CLASSES
+            .lock()
+            .unwrap()
+            .find(self.class)
+            .cloned()
+            .ok_or_else(|| RemoteError::NoSuchClass(self.class.to_string()))?;
+
+        let (holder, field) = match clazz.find_declared_field(source, self.name, self.sig) {
+            Ok(found) => found,
+            Err(FieldError::Read(error)) => return Err(RemoteError::Read(error)),
+            Err(_) => {
+                return Err(RemoteError::NoSuchField {
+                    class: self.class.to_string(),
+                    name: self.name.to_string(),
+                })
+            }
+        };
+
+        let field = ResolvedField {
+            offset: field._field_info.offset(),
+            holder: if field.is_static() {
+                Some(holder.base as usize)
+            } else {
+                None
+            },
+            sig: field.sig,
+        };
+
+        *self.resolved.lock().unwrap() = Some((source.id(), field.clone()));
+
+        Ok(field)
+    }
+
+    /// The field of `object`, an instance of the class or one of its subclasses
+    pub fn read<T: RemoteValue>(
+        &self,
+        source: &dyn MemorySource,
+        object: usize,
+    ) -> Result<T, RemoteError> {
+        if !T::matches(self.sig) {
+            return Err(RemoteError::WrongType {
+                name: self.name.to_string(),
+                expected: T::EXPECTED,
+                found: self.sig.to_string(),
+            });
+        }
+
+        let address = self.resolve(source)?.address(source, object)?;
+
+        T::read(source, address)
+    }
+
+    /// The field of the class itself, which has to be static
+    pub fn read_static<T: RemoteValue>(&self, source: &dyn MemorySource) -> Result<T, RemoteError> {
+        if self.resolve(source)?.holder.is_none() {
+            return Err(RemoteError::NotStatic(self.name.to_string()));
+        }
+
+        // static fields are found through their holder, there's no object to read them from
+        self.read(source, 0)
+    }
+}
+
+/// An object in the target and the class it really is
+#[derive(Debug, Clone)]
+pub struct RemoteObject {
+    pub address: usize,
+    pub klass: JClass,
+}
+
+impl RemoteObject {
+    /// The object at `address`, its class read from its header
+    pub fn new(source: &dyn MemorySource, address: usize) -> Result<Self, RemoteError> {
+        let klass = header::ObjectHeader::read(source, address)?.class(source)?;
+
+        Ok(Self { address, klass })
+    }
+
+    /// The name of the object's class, e.g. "java/lang/String"
+    pub fn class_name(&self, source: &dyn MemorySource) -> Result<String, RemoteError> {
+        Ok(self.klass.name(source)?)
+    }
+
+    /// Whether the object is an instance of `class`, e.g. "java/util/HashMap", or of a subclass
+    pub fn is_instance_of(
+        &self,
+        source: &dyn MemorySource,
+        class: &str,
+    ) -> Result<bool, RemoteError> {
+        let mut clazz = self.klass.clone();
+
+        loop {
+            if clazz.name(source)? == class {
+                return Ok(true);
+            }
+
+            if clazz.super_klass.is_null() {
+                return Ok(false);
+            }
+
+            clazz = JClass::from_native(source, clazz.super_klass)?;
+        }
+    }
+
+    /// The signature of the field `name`, e.g. "[B" for the value of a JDK 9 String
+    pub fn field_sig(&self, source: &dyn MemorySource, name: &str) -> Result<String, RemoteError> {
+        Ok(self.resolve(source, name)?.sig)
+    }
+
+    /// The first field called `name` in the class or its supers, whatever its type
+    fn resolve(&self, source: &dyn MemorySource, name: &str) -> Result<ResolvedField, RemoteError> {
+        let key = (source.id(), self.klass.base as usize, name.to_string());
+        if let Some(field) = FIELD_CACHE.lock().unwrap().get(&key) {
+            return Ok(field.clone());
+        }
+
+        let mut clazz = self.klass.clone();
+
+        let field = loop {
+            let mut fields = Vec::new();
+            clazz.collect_fields(source, &mut fields);
+
+            if let Some(field) = fields.into_iter().find(|field| field.name == name) {
+                let holder = if field.is_static() {
+                    Some(clazz.base as usize)
+                } else {
+                    None
+                };
+
+                break ResolvedField {
+                    offset: field._field_info.offset(),
+                    holder,
+                    sig: field.sig,
+                };
+            }
+
+            if clazz.super_klass.is_null() {
+                return Err(RemoteError::NoSuchField {
+                    class: self.class_name(source)?,
+                    name: name.to_string(),
+                });
+            }
+
+            clazz = JClass::from_native(source, clazz.super_klass)?;
+        };
+
+        FIELD_CACHE.lock().unwrap().insert(key, field.clone());
+
+        Ok(field)
+    }
+
+    /// Where the field `name` lives, after checking its signature `matches` what's `expected`
+    fn field_address(
+        &self,
+        source: &dyn MemorySource,
+        name: &str,
+        expected: &'static str,
+        matches: impl Fn(&str) -> bool,
+    ) -> Result<usize, RemoteError> {
+        let field = self.resolve(source, name)?;
+
+        if !matches(&field.sig) {
+            return Err(RemoteError::WrongType {
+                name: name.to_string(),
+                expected,
+                found: field.sig,
+            });
+        }
+
+        Ok(field.address(source, self.address)?)
+    }
+
+    /// The field `name` as whatever it's asked for, if its signature agrees
+    pub fn get<T: RemoteValue>(
+        &self,
+        source: &dyn MemorySource,
+        name: &str,
+    ) -> Result<T, RemoteError> {
+        let address = self.field_address(source, name, T::EXPECTED, T::matches)?;
+
+        T::read(source, address)
+    }
+
+    #[allow(dead_code)]
+    pub fn get_bool(&self, source: &dyn MemorySource, name: &str) -> Result<bool, RemoteError> {
+        self.get(source, name)
+    }
+
+    pub fn get_byte(&self, source: &dyn MemorySource, name: &str) -> Result<i8, RemoteError> {
+        self.get(source, name)
+    }
+
+    #[allow(dead_code)]
+    pub fn get_char(&self, source: &dyn MemorySource, name: &str) -> Result<u16, RemoteError> {
+        self.get(source, name)
+    }
+
+    #[allow(dead_code)]
+    pub fn get_short(&self, source: &dyn MemorySource, name: &str) -> Result<i16, RemoteError> {
+        self.get(source, name)
+    }
+
+    pub fn get_int(&self, source: &dyn MemorySource, name: &str) -> Result<i32, RemoteError> {
+        self.get(source, name)
+    }
+
+    pub fn get_long(&self, source: &dyn MemorySource, name: &str) -> Result<i64, RemoteError> {
+        self.get(source, name)
+    }
+
+    #[allow(dead_code)]
+    pub fn get_float(&self, source: &dyn MemorySource, name: &str) -> Result<f32, RemoteError> {
+        self.get(source, name)
+    }
+
+    #[allow(dead_code)]
+    pub fn get_double(&self, source: &dyn MemorySource, name: &str) -> Result<f64, RemoteError> {
+        self.get(source, name)
+    }
+
+    /// The address of the object the reference field `name` points at, 0 for null
+    pub fn get_reference(
+        &self,
+        source: &dyn MemorySource,
+        name: &str,
+    ) -> Result<usize, RemoteError> {
+        let address = self.field_address(
+            source,
+            name,
+            <Option<RemoteObject>>::EXPECTED,
+            <Option<RemoteObject>>::matches,
+        )?;
+
+        Ok(oops::read_oop(source, address)?)
+    }
+
+    /// The object the reference field `name` points at, `None` for null
+    pub fn get_object(
+        &self,
+        source: &dyn MemorySource,
+        name: &str,
+    ) -> Result<Option<RemoteObject>, RemoteError> {
+        self.get(source, name)
+    }
+
+    /// The java.lang.String the field `name` points at, `None` for null
+    #[allow(dead_code)]
+    pub fn get_string(
+        &self,
+        source: &dyn MemorySource,
+        name: &str,
+    ) -> Result<Option<String>, RemoteError> {
+        self.get(source, name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::api::sdk::testing::{FakeField, JvmImage};
+
+    #[derive(Debug, PartialEq, RemoteClass)]
+    #[class("pk")]
+    struct Entity {
+        #[address]
+        address: usize,
+
+        #[field("s", "D")]
+        pos_x: f64,
+
+        #[field("F", "Z")]
+        on_ground: bool,
+    }
+
+    #[derive(Debug, RemoteClass)]
+    #[class("bjk")]
+    #[allow(dead_code)]
+    struct Player {
+        #[address]
+        address: usize,
+
+        // the field is an int
+        #[field("bH", "I")]
+        experience: i64,
+    }
+
+    #[derive(Debug, RemoteClass)]
+    #[class("net/minecraft/client/Minecraft")]
+    #[allow(dead_code)]
+    struct Unloaded {
+        #[address]
+        address: usize,
+
+        #[field("theWorld", "Lbjf;")]
+        world: Option<RemoteObject>,
+    }
+
+    #[test]
+    fn reads_fields_by_name() {
+        let mut image = JvmImage::new(0x7f00_0000, 0x4000);
+
+        let object = image.class("java/lang/Object", 0, &[]);
+        let entity = image.class(
+            "pk",
+            object,
+            &[
+                FakeField {
+                    name: "s",
+                    sig: "D",
+                    offset: 0x10,
+                },
+                FakeField {
+                    name: "F",
+                    sig: "Z",
+                    offset: 0x0c,
+                },
+            ],
+        );
+        let player = image.class(
+            "bjk",
+            entity,
+            &[
+                FakeField {
+                    name: "bH",
+                    sig: "I",
+                    offset: 0x18,
+                },
+                FakeField {
+                    name: "bI",
+                    sig: "Lpk;",
+                    offset: 0x1c,
+                },
+            ],
+        );
+
+        let riding = image.instance(entity, 0x18);
+        let the_player = image.instance(player, 0x20);
+        image.write(the_player + 0x0c, &1u8);
+        image.write(the_player + 0x10, &64.5f64);
+        image.write(the_player + 0x18, &20i32);
+        image.write(the_player + 0x1c, &(riding as u32));
+
+        let remote = RemoteObject::new(&image, the_player).unwrap();
+        assert_eq!(remote.class_name(&image).unwrap(), "bjk");
+
+        // inherited fields work the same
+        assert_eq!(remote.get_double(&image, "s"), Ok(64.5));
+        assert_eq!(remote.get_bool(&image, "F"), Ok(true));
+        assert_eq!(remote.get_int(&image, "bH"), Ok(20));
+
+        let ridden = remote.get_object(&image, "bI").unwrap().unwrap();
+        assert_eq!(ridden.address, riding);
+        assert_eq!(ridden.class_name(&image).unwrap(), "pk");
+        assert_eq!(ridden.get_bool(&image, "F"), Ok(false));
+
+        assert_eq!(
+            remote.get_long(&image, "bH"),
+            Err(RemoteError::WrongType {
+                name: "bH".to_string(),
+                expected: "J",
+                found: "I".to_string(),
+            })
+        );
+        assert_eq!(
+            remote.get_int(&image, "health"),
+            Err(RemoteError::NoSuchField {
+                class: "bjk".to_string(),
+                name: "health".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn keeps_fields_per_source() {
+        // the next process can have a class at the very same address, laid out differently
+        let mut images = Vec::new();
+        for offset in [0x0c, 0x18] {
+            let mut image = JvmImage::new(0x7f00_0000, 0x4000);
+
+            let object = image.class("java/lang/Object", 0, &[]);
+            let player = image.class(
+                "bjk",
+                object,
+                &[FakeField {
+                    name: "bH",
+                    sig: "I",
+                    offset,
+                }],
+            );
+
+            let the_player = image.instance(player, 0x20);
+            image.write(the_player + offset as usize, &(offset as i32));
+            images.push((image, player, the_player, offset));
+        }
+
+        assert_eq!(images[0].1, images[1].1);
+        for (image, _, the_player, offset) in &images {
+            let remote = RemoteObject::new(image, *the_player).unwrap();
+            assert_eq!(remote.get_int(image, "bH"), Ok(*offset as i32));
+        }
+    }
+
+    #[test]
+    fn reads_declared_classes() {
+        let mut image = JvmImage::new(0x7f00_0000, 0x4000);
+
+        let object = image.class("java/lang/Object", 0, &[]);
+        let entity = image.class(
+            "pk",
+            object,
+            &[
+                FakeField {
+                    name: "s",
+                    sig: "D",
+                    offset: 0x10,
+                },
+                FakeField {
+                    name: "F",
+                    sig: "Z",
+                    offset: 0x0c,
+                },
+            ],
+        );
+        let player = image.class(
+            "bjk",
+            entity,
+            &[FakeField {
+                name: "bH",
+                sig: "I",
+                offset: 0x18,
+            }],
+        );
+
+        for (name, klass) in [("pk", entity), ("bjk", player)] {
+            let clazz = JClass::from_native(&image, klass as _).unwrap();
+            CLASSES.lock().unwrap().insert(0, name.to_string(), clazz);
+        }
+
+        // a subclass has the fields where its super declares them
+        let the_player = image.instance(player, 0x20);
+        image.write(the_player + 0x0c, &1u8);
+        image.write(the_player + 0x10, &64.5f64);
+
+        let read = Entity::read(&image, the_player).unwrap();
+        assert_eq!(
+            read,
+            Entity {
+                address: the_player,
+                pos_x: 64.5,
+                on_ground: true,
+            }
+        );
+        assert_eq!(read.address(), the_player);
+
+        // the accessors read what the target has now, the struct what it had then
+        image.write(the_player + 0x10, &70.25f64);
+        assert_eq!(read.pos_x(&image), Ok(70.25));
+        assert_eq!(read.pos_x, 64.5);
+
+        assert_eq!(
+            Player::read(&image, the_player).err(),
+            Some(RemoteError::WrongType {
+                name: "bH".to_string(),
+                expected: "J",
+                found: "I".to_string(),
+            })
+        );
+        assert_eq!(
+            RemoteField::new("pk", "s", "D").read_static::<f64>(&image),
+            Err(RemoteError::NotStatic("s".to_string()))
+        );
+        assert_eq!(
+            Unloaded::read(&image, the_player).err(),
+            Some(RemoteError::NoSuchClass(
+                "net/minecraft/client/Minecraft".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn reads_strings() {
+        let mut image = JvmImage::new(0x7f00_0000, 0x4000);
+
+        let object = image.class("java/lang/Object", 0, &[]);
+        let bytes = image.class("[B", 0, &[]);
+        let string = image.class(
+            "java/lang/String",
+            object,
+            &[
+                FakeField {
+                    name: "value",
+                    sig: "[B",
+                    offset: 0x0c,
+                },
+                FakeField {
+                    name: "coder",
+                    sig: "B",
+                    offset: 0x14,
+                },
+            ],
+        );
+        let profile = image.class(
+            "com/mojang/authlib/GameProfile",
+            object,
+            &[
+                FakeField {
+                    name: "name",
+                    sig: "Ljava/lang/String;",
+                    offset: 0x0c,
+                },
+                FakeField {
+                    name: "legacy",
+                    sig: "Ljava/lang/String;",
+                    offset: 0x10,
+                },
+            ],
+        );
+
+        let value = image.instance(bytes, 0x18);
+        image.write(value + 12, &5i32);
+        image.write_bytes(value + 16, b"Notch");
+
+        let name = image.instance(string, 0x18);
+        image.write(name + 0x0c, &(value as u32));
+
+        let owner = image.instance(profile, 0x18);
+        image.write(owner + 0x0c, &(name as u32));
+
+        let remote = RemoteObject::new(&image, owner).unwrap();
+        assert_eq!(
+            remote.get_string(&image, "name"),
+            Ok(Some("Notch".to_string()))
+        );
+
+        // the bytes behind it, which aren't chars
+        let string_object = RemoteObject::new(&image, name).unwrap();
+        let array = string_object
+            .get::<Option<JavaArray<u8>>>(&image, "value")
+            .unwrap()
+            .unwrap();
+        assert_eq!(array.to_vec(&image), Ok(b"Notch".to_vec()));
+        assert!(string_object
+            .get::<Option<JavaArray<u16>>>(&image, "value")
+            .is_err());
+        assert_eq!(remote.get_string(&image, "legacy"), Ok(None));
+
+        // the same bytes as UTF-16
+        image.write(name + 0x14, &1u8);
+        image.write(value + 12, &4i32);
+        image.write_bytes(value + 16, &[0x48, 0, 0xe9, 0]);
+        assert_eq!(
+            remote.get_string(&image, "name"),
+            Ok(Some("Hé".to_string()))
+        );
+    }
+}
@@ -0,0 +1,500 @@
+//! What there is to know about a loaded class in one place, printed the way javap would
+
+use std::fmt;
+
+use super::{
+    classfile::{JVM_RECOGNIZED_CLASS_MODIFIERS, JVM_RECOGNIZED_METHOD_MODIFIERS},
+    processes::{MemorySource, ReadError},
+    FromNative, JArray, JClass, JConstantPool,
+};
+
+const ACC_STATIC: u16 = 0x0008;
+const ACC_INTERFACE: u16 = 0x0200;
+const ACC_ABSTRACT: u16 = 0x0400;
+
+// in the order javap prints them
+
+const CLASS_MODIFIERS: &[(u16, &str)] =
+    &[(0x0001, "public"), (0x0400, "abstract"), (0x0010, "final")];
+
+const FIELD_MODIFIERS: &[(u16, &str)] = &[
+    (0x0001, "public"),
+    (0x0002, "private"),
+    (0x0004, "protected"),
+    (0x0008, "static"),
+    (0x0010, "final"),
+    (0x0040, "volatile"),
+    (0x0080, "transient"),
+];
+
+const METHOD_MODIFIERS: &[(u16, &str)] = &[
+    (0x0001, "public"),
+    (0x0002, "private"),
+    (0x0004, "protected"),
+    (0x0008, "static"),
+    (0x0010, "final"),
+    (0x0020, "synchronized"),
+    (0x0100, "native"),
+    (0x0400, "abstract"),
+    (0x0800, "strictfp"),
+];
+
+fn modifiers(table: &[(u16, &str)], flags: u16) -> String {
+    table
+        .iter()
+        .filter(|(flag, _)| flags & flag != 0)
+        .map(|(_, modifier)| format!("{} ", modifier))
+        .collect()
+}
+
+/// `java/lang/String` as `java.lang.String`
+fn java_name(name: &str) -> String {
+    name.replace('/', ".")
+}
+
+/// The Java type the field descriptor at the start of `descriptor` stands for, and whatever
+/// comes after it
+fn java_type(descriptor: &str) -> Option<(String, &str)> {
+    let primitive = match descriptor.chars().next()? {
+        'B' => "byte",
+        'C' => "char",
+        'D' => "double",
+        'F' => "float",
+        'I' => "int",
+        'J' => "long",
+        'S' => "short",
+        'Z' => "boolean",
+        'V' => "void",
+        'L' => {
+            let end = descriptor.find(';')?;
+
+            return Some((java_name(&descriptor[1..end]), &descriptor[end + 1..]));
+        }
+        '[' => {
+            let (element, rest) = java_type(&descriptor[1..])?;
+
+            return Some((format!("{}[]", element), rest));
+        }
+        _ => return None,
+    };
+
+    Some((primitive.to_string(), &descriptor[1..]))
+}
+
+/// The parameter types and return type of a method descriptor
+fn method_types(descriptor: &str) -> Option<(Vec<String>, String)> {
+    let mut rest = descriptor.strip_prefix('(')?;
+    let mut parameters = Vec::new();
+
+    while !rest.starts_with(')') {
+        let (parameter, next) = java_type(rest)?;
+
+        parameters.push(parameter);
+        rest = next;
+    }
+
+    let (returns, rest) = java_type(&rest[1..])?;
+
+    if !rest.is_empty() {
+        return None;
+    }
+
+    Some((parameters, returns))
+}
+
+fn klass_name(source: &dyn MemorySource, klass: *mut JClass) -> Result<String, ReadError> {
+    JClass::from_native(source, klass)?.name(source)
+}
+
+/// The names of the classes in an `Array<Klass*>`, nothing if there's no array
+fn klass_names(source: &dyn MemorySource, array: *mut usize) -> Result<Vec<String>, ReadError> {
+    if array.is_null() {
+        return Ok(Vec::new());
+    }
+
+    let klasses = JArray::<*mut JClass>::from_native(source, array as _)?;
+
+    (0..klasses.lenght)
+        .filter_map(|i| klasses.at(i, source))
+        .map(|klass| klass_name(source, klass))
+        .collect()
+}
+
+/// A field declared by the class
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldReport {
+    pub name: String,
+    pub descriptor: String,
+    /// As in the class file, HotSpot's own flags are masked off
+    pub access_flags: u16,
+    /// In the mirror for static fields, in the object for instance fields
+    pub offset: u64,
+}
+
+impl FieldReport {
+    pub fn is_static(&self) -> bool {
+        self.access_flags & ACC_STATIC != 0
+    }
+}
+
+/// A method declared by the class
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MethodReport {
+    pub name: String,
+    pub descriptor: String,
+    /// As in the class file, HotSpot's own flags are masked off
+    pub access_flags: u16,
+}
+
+/// A loaded class, its place in the hierarchy and what it declares. Printing it gives about
+/// what `javap -p -s` would for the class file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClassReport {
+    /// With '/' between packages, like the JVM has it
+    pub name: String,
+    /// The SourceFile attribute, if the class had one
+    pub source_file: Option<String>,
+    /// As in the class file, HotSpot's own flags are masked off
+    pub access_flags: u16,
+    /// The super class first, up to `java/lang/Object`
+    pub supers: Vec<String>,
+    /// The interfaces the class itself declares
+    pub interfaces: Vec<String>,
+    /// Every interface the class implements, through its supers and super interfaces as well
+    pub all_interfaces: Vec<String>,
+    pub fields: Vec<FieldReport>,
+    pub methods: Vec<MethodReport>,
+}
+
+impl ClassReport {
+    pub fn new(source: &dyn MemorySource, clazz: &JClass) -> Result<Self, ReadError> {
+        let name = clazz.name(source)?;
+
+        let mut supers = Vec::new();
+        let mut super_klass = clazz.super_klass;
+
+        while !super_klass.is_null() {
+            supers.push(klass_name(source, super_klass)?);
+            super_klass = JClass::from_native(source, super_klass)?.super_klass;
+        }
+
+        let mut declared_fields = Vec::new();
+        clazz.collect_fields(source, &mut declared_fields);
+
+        let fields = declared_fields
+            .into_iter()
+            .map(|field| FieldReport {
+                access_flags: field.access_flags.bits(),
+                offset: field._field_info.offset(),
+                name: field.name,
+                descriptor: field.sig,
+            })
+            .collect();
+
+        let methods = clazz
+            .iterate_methods(source)
+            .map(|method| MethodReport {
+                access_flags: method.access_flags & JVM_RECOGNIZED_METHOD_MODIFIERS as u16,
+                name: method.name,
+                descriptor: method.sig,
+            })
+            .collect();
+
+        let constant_pool = JConstantPool::from_native(source, clazz.constant_pool)?;
+        let source_file = match constant_pool.source_file_name_index {
+            0 => None,
+            index => constant_pool
+                .symbol(source, index as _)
+                .map(|symbol| symbol.to_string(source)),
+        };
+
+        Ok(Self {
+            name,
+            source_file,
+            access_flags: (clazz.access_flags & JVM_RECOGNIZED_CLASS_MODIFIERS) as u16,
+            supers,
+            interfaces: klass_names(source, clazz._local_interfaces)?,
+            all_interfaces: klass_names(source, clazz._transitive_interfaces)?,
+            fields,
+            methods,
+        })
+    }
+
+    pub fn is_interface(&self) -> bool {
+        self.access_flags & ACC_INTERFACE != 0
+    }
+}
+
+impl fmt::Display for FieldReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let java = java_type(&self.descriptor)
+            .filter(|(_, rest)| rest.is_empty())
+            .map(|(java, _)| java)
+            .unwrap_or_else(|| self.descriptor.clone());
+
+        writeln!(
+            f,
+            "  {}{} {}; // {} offset {:#x}",
+            modifiers(FIELD_MODIFIERS, self.access_flags),
+            java,
+            self.name,
+            if self.is_static() { "mirror" } else { "object" },
+            self.offset
+        )?;
+        writeln!(f, "    descriptor: {}", self.descriptor)
+    }
+}
+
+impl MethodReport {
+    fn fmt_in(&self, f: &mut fmt::Formatter<'_>, class_name: &str) -> fmt::Result {
+        let mut flags = self.access_flags;
+
+        // a class initializer is nothing but `static {}` to javap
+        if self.name == "<clinit>" {
+            flags &= ACC_STATIC;
+        }
+
+        write!(f, "  {}", modifiers(METHOD_MODIFIERS, flags))?;
+
+        match (self.name.as_str(), method_types(&self.descriptor)) {
+            ("<clinit>", _) => write!(f, "{{}}")?,
+            (name, Some((parameters, returns))) => {
+                if name == "<init>" {
+                    write!(f, "{}", java_name(class_name))?;
+                } else {
+                    write!(f, "{} {}", returns, name)?;
+                }
+
+                write!(f, "({})", parameters.join(", "))?;
+            }
+            (name, None) => write!(f, "{}", name)?,
+        }
+
+        writeln!(f, ";")?;
+        writeln!(f, "    descriptor: {}", self.descriptor)
+    }
+}
+
+impl fmt::Display for ClassReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(source_file) = &self.source_file {
+            writeln!(f, "Compiled from \"{}\"", source_file)?;
+        }
+
+        // every interface is abstract, javap doesn't say so
+        let mut flags = self.access_flags;
+        if self.is_interface() {
+            flags &= !ACC_ABSTRACT;
+        }
+
+        write!(
+            f,
+            "{}{} {}",
+            modifiers(CLASS_MODIFIERS, flags),
+            if self.is_interface() {
+                "interface"
+            } else {
+                "class"
+            },
+            java_name(&self.name)
+        )?;
+
+        // interfaces "extend" the interfaces they implement, their super is always Object
+        let (extends, implements) = if self.is_interface() {
+            (self.interfaces.iter().collect::<Vec<&String>>(), Vec::new())
+        } else {
+            let extends = self
+                .supers
+                .first()
+                .filter(|super_class| *super_class != "java/lang/Object");
+
+            (
+                extends.into_iter().collect(),
+                self.interfaces.iter().collect(),
+            )
+        };
+
+        for (keyword, names) in [("extends", extends), ("implements", implements)] {
+            if !names.is_empty() {
+                let names = names
+                    .iter()
+                    .map(|name| java_name(name))
+                    .collect::<Vec<String>>();
+
+                write!(f, " {} {}", keyword, names.join(", "))?;
+            }
+        }
+
+        writeln!(f)?;
+
+        if !self.supers.is_empty() {
+            let supers = self
+                .supers
+                .iter()
+                .map(|name| java_name(name))
+                .collect::<Vec<String>>();
+
+            writeln!(f, "  // supers: {}", supers.join(" -> "))?;
+        }
+
+        if !self.all_interfaces.is_empty() {
+            let interfaces = self
+                .all_interfaces
+                .iter()
+                .map(|name| java_name(name))
+                .collect::<Vec<String>>();
+
+            writeln!(f, "  // all interfaces: {}", interfaces.join(", "))?;
+        }
+
+        writeln!(f, "{{")?;
+
+        for field in &self.fields {
+            write!(f, "{}", field)?;
+        }
+
+        if !self.fields.is_empty() && !self.methods.is_empty() {
+            writeln!(f)?;
+        }
+
+        for method in &self.methods {
+            method.fmt_in(f, &self.name)?;
+        }
+
+        writeln!(f, "}}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::api::sdk::testing::{field_info, FakeMethod, JvmImage};
+
+    #[test]
+    fn reads_java_types() {
+        assert_eq!(
+            java_type("[[Ljava/lang/String;I"),
+            Some(("java.lang.String[][]".to_string(), "I"))
+        );
+        assert_eq!(
+            method_types("(IJ[Z)Lave;"),
+            Some((
+                vec![
+                    "int".to_string(),
+                    "long".to_string(),
+                    "boolean[]".to_string()
+                ],
+                "ave".to_string()
+            ))
+        );
+        assert_eq!(method_types("(I"), None);
+        assert_eq!(java_type("Q"), None);
+    }
+
+    #[test]
+    fn reports_like_javap() {
+        let mut image = JvmImage::new(0x7f00_0000, 0x4000);
+
+        let object = image.class("java/lang/Object", 0, &[]);
+        let runnable = image.class("java/lang/Runnable", 0, &[]);
+        let base = image.class("net/minecraft/client/Base", object, &[]);
+
+        let slots = [
+            "",
+            "theMinecraft",
+            "Lnet/minecraft/client/Minecraft;",
+            "running",
+            "Z",
+            "<init>",
+            "(I[Ljava/lang/String;)V",
+            "run",
+            "()V",
+            "<clinit>",
+        ]
+        .iter()
+        .map(|text| {
+            if text.is_empty() {
+                0
+            } else {
+                image.symbol(text.as_bytes())
+            }
+        })
+        .collect::<Vec<usize>>();
+
+        // public static, then private volatile plus HotSpot's JVM_ACC_FIELD_ACCESS_WATCHED
+        let mut static_field = field_info(1, 2, 0x70);
+        static_field[0] = 0x0009;
+        let mut instance_field = field_info(3, 4, 0x0c);
+        instance_field[0] = 0x0042 | 0x2000;
+
+        let method = |image: &mut JvmImage, name_idx, sig_idx, access_flags| {
+            image.method(
+                name_idx,
+                sig_idx,
+                &FakeMethod {
+                    name: "",
+                    sig: "",
+                    access_flags,
+                    max_stack: 0,
+                    max_locals: 1,
+                    code: &[0xb1],
+                    exception_table: &[],
+                },
+            )
+        };
+        let methods = [
+            method(&mut image, 5, 6, 0x0001),
+            method(&mut image, 7, 8, 0x0011),
+            method(&mut image, 9, 8, 0x0008),
+        ];
+
+        let interfaces = image.array(&[runnable]);
+
+        let clazz = JClass {
+            symbol: image.symbol(b"net/minecraft/client/Minecraft") as _,
+            super_klass: base as _,
+            // public final plus JVM_ACC_HAS_FINALIZER
+            access_flags: 0x0011 | 0x4000_0000,
+            constant_pool: image.constant_pool(&slots) as _,
+            methods: image.array(&methods) as _,
+            fields: image.fields(&[static_field, instance_field]) as _,
+            _local_interfaces: interfaces as _,
+            _transitive_interfaces: interfaces as _,
+            ..Default::default()
+        };
+        let address = image.class_from(&clazz);
+        let clazz = JClass::from_native(&image, address as _).unwrap();
+
+        let report = ClassReport::new(&image, &clazz).unwrap();
+
+        assert_eq!(
+            report.supers,
+            vec!["net/minecraft/client/Base", "java/lang/Object"]
+        );
+        assert!(report.fields[0].is_static());
+        assert!(!report.fields[1].is_static());
+
+        assert_eq!(
+            report.to_string(),
+            "public final class net.minecraft.client.Minecraft extends \
+             net.minecraft.client.Base implements java.lang.Runnable
+  // supers: net.minecraft.client.Base -> java.lang.Object
+  // all interfaces: java.lang.Runnable
+{
+  public static net.minecraft.client.Minecraft theMinecraft; // mirror offset 0x70
+    descriptor: Lnet/minecraft/client/Minecraft;
+  private volatile boolean running; // object offset 0xc
+    descriptor: Z
+
+  public net.minecraft.client.Minecraft(int, java.lang.String[]);
+    descriptor: (I[Ljava/lang/String;)V
+  public final void run();
+    descriptor: ()V
+  static {};
+    descriptor: ()V
+}
+"
+        );
+    }
+}
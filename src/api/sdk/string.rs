@@ -0,0 +1,196 @@
+//! java.lang.String objects in the target. JDK 8 keeps the text in a char[] of UTF-16, JDK 9
+//! and up in a byte[] that's LATIN1 if every char fits a byte (compact strings) and UTF-16
+//! otherwise, which the coder field says.
+
+use crate::api::processes::MemorySource;
+
+use super::{
+    java::JavaArray,
+    remote::{RemoteError, RemoteObject},
+};
+
+/// The coder of a String whose bytes are chars
+pub const LATIN1: i8 = 0;
+
+/// The coder of a String with two bytes a char, in the target's byte order
+#[allow(dead_code)]
+pub const UTF16: i8 = 1;
+
+const STRING_CLASS: &str = "java/lang/String";
+
+/// UTF-16 as the target lays it out, unpaired surrogates become U+FFFD
+fn decode_utf16(bytes: &[u8]) -> String {
+    let chars = bytes
+        .chunks_exact(2)
+        .map(|char| u16::from_ne_bytes([char[0], char[1]]))
+        .collect::<Vec<u16>>();
+
+    String::from_utf16_lossy(&chars)
+}
+
+/// The text of a String the way `coder` says its bytes are laid out
+pub fn decode(bytes: &[u8], coder: i8) -> String {
+    match coder {
+        LATIN1 => bytes.iter().map(|&byte| char::from(byte)).collect(),
+        _ => decode_utf16(bytes),
+    }
+}
+
+/// The text of the String at `address`
+pub fn read_string(source: &dyn MemorySource, address: usize) -> Result<String, RemoteError> {
+    string_value(source, &RemoteObject::new(source, address)?)
+}
+
+/// The text of `string`, which has to be a java.lang.String
+pub fn string_value(
+    source: &dyn MemorySource,
+    string: &RemoteObject,
+) -> Result<String, RemoteError> {
+    let class = string.class_name(source)?;
+    if class != STRING_CLASS {
+        return Err(RemoteError::WrongClass {
+            expected: STRING_CLASS,
+            found: class,
+        });
+    }
+
+    let value = string.get_reference(source, "value")?;
+
+    match string.field_sig(source, "value")?.as_str() {
+        "[C" => {
+            let chars = JavaArray::<u16>::read(source, value)?.to_vec(source)?;
+
+            Ok(String::from_utf16_lossy(&chars))
+        }
+        _ => {
+            let bytes = JavaArray::<u8>::read(source, value)?.to_vec(source)?;
+
+            Ok(decode(&bytes, string.get_byte(source, "coder")?))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::api::sdk::testing::{FakeField, JvmImage};
+
+    /// A primitive array of `elements`, length at 12 and elements from 16
+    fn array(image: &mut JvmImage, klass: usize, length: i32, elements: &[u8]) -> usize {
+        let array = image.instance(klass, 16 + elements.len());
+
+        image.write(array + 12, &length);
+        image.write_bytes(array + 16, elements);
+
+        array
+    }
+
+    fn utf16(text: &str) -> Vec<u8> {
+        text.encode_utf16()
+            .flat_map(|char| char.to_ne_bytes())
+            .collect()
+    }
+
+    #[test]
+    fn decodes_by_coder() {
+        assert_eq!(decode(b"Steve", LATIN1), "Steve");
+        assert_eq!(decode(&[0xe9, 0x74, 0xe9], LATIN1), "été");
+        assert_eq!(decode(&utf16("Ωmega ⛏"), UTF16), "Ωmega ⛏");
+
+        // a lone high surrogate
+        assert_eq!(decode(&0xd83du16.to_ne_bytes(), UTF16), "\u{fffd}");
+    }
+
+    #[test]
+    fn reads_jdk9_strings() {
+        let mut image = JvmImage::new(0x7f00_0000, 0x4000);
+
+        let object = image.class("java/lang/Object", 0, &[]);
+        let bytes = image.class("[B", 0, &[]);
+        let string = image.class(
+            STRING_CLASS,
+            object,
+            &[
+                FakeField {
+                    name: "value",
+                    sig: "[B",
+                    offset: 0x10,
+                },
+                FakeField {
+                    name: "hash",
+                    sig: "I",
+                    offset: 0x0c,
+                },
+                FakeField {
+                    name: "coder",
+                    sig: "B",
+                    offset: 0x14,
+                },
+            ],
+        );
+
+        let latin1 = image.instance(string, 0x18);
+        let value = array(&mut image, bytes, 5, b"Notch");
+        image.write(latin1 + 0x10, &(value as u32));
+
+        assert_eq!(read_string(&image, latin1), Ok("Notch".to_string()));
+
+        let text = utf16("jeb_ ☃");
+        let utf16 = image.instance(string, 0x18);
+        let value = array(&mut image, bytes, text.len() as i32, &text);
+        image.write(utf16 + 0x10, &(value as u32));
+        image.write(utf16 + 0x14, &UTF16);
+
+        assert_eq!(read_string(&image, utf16), Ok("jeb_ ☃".to_string()));
+
+        // the empty string shares an empty array
+        let empty = image.instance(string, 0x18);
+        let value = array(&mut image, bytes, 0, &[]);
+        image.write(empty + 0x10, &(value as u32));
+
+        assert_eq!(read_string(&image, empty), Ok(String::new()));
+
+        assert_eq!(
+            read_string(&image, value),
+            Err(RemoteError::WrongClass {
+                expected: STRING_CLASS,
+                found: "[B".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn reads_jdk8_strings() {
+        let mut image = JvmImage::new(0x7f00_0000, 0x4000);
+
+        let object = image.class("java/lang/Object", 0, &[]);
+        let chars = image.class("[C", 0, &[]);
+        let string = image.class(
+            STRING_CLASS,
+            object,
+            &[
+                FakeField {
+                    name: "value",
+                    sig: "[C",
+                    offset: 0x0c,
+                },
+                FakeField {
+                    name: "hash",
+                    sig: "I",
+                    offset: 0x10,
+                },
+            ],
+        );
+
+        let text = utf16("Dinnerbone");
+        let dinnerbone = image.instance(string, 0x18);
+        let value = array(&mut image, chars, 10, &text);
+        image.write(dinnerbone + 0x0c, &(value as u32));
+
+        assert_eq!(
+            read_string(&image, dinnerbone),
+            Ok("Dinnerbone".to_string())
+        );
+    }
+}
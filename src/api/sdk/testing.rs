@@ -0,0 +1,611 @@
+//! A fake JVM address space for the sdk tests, laid out with the offsets in `VM_STRUCTS` so
+//! `JClass` and friends can be read from it like from a live javaw.exe
+
+use crate::api::processes::{MemorySource, ReadError, SourceId};
+
+use super::{
+    fieldinfo::FieldInfo,
+    vmstructs::{VMField, VM_STRUCTS},
+    JClass, JFieldOffset, JVMDictionary,
+};
+
+/// Metadata is 8 byte aligned in the target
+const ALIGNMENT: usize = 8;
+
+/// A field as it's declared in a class, `offset` is where it lives in the instance
+pub struct FakeField<'a> {
+    pub name: &'a str,
+    pub sig: &'a str,
+    pub offset: u32,
+}
+
+/// The field `name` of signature `sig` at `offset`, for declaring classes in a line
+pub fn field<'a>(name: &'a str, sig: &'a str, offset: u32) -> FakeField<'a> {
+    FakeField { name, sig, offset }
+}
+
+/// A method as it's declared in a class, `code` being its bytecode
+pub struct FakeMethod<'a> {
+    pub name: &'a str,
+    pub sig: &'a str,
+    pub access_flags: u16,
+    pub max_stack: u16,
+    pub max_locals: u16,
+    pub code: &'a [u8],
+    /// start, end and handler pc plus the catch type, like in the class file
+    pub exception_table: &'a [[u16; 4]],
+}
+
+/// A `Vec<u8>` pretending to be the memory at `base..base + memory.len()`.
+///
+/// Everything can be put at a chosen address with the `put_*` functions, or wherever the image
+/// has room left with the functions that don't take an address.
+pub struct JvmImage {
+    base: usize,
+    memory: Vec<u8>,
+    cursor: usize,
+    id: SourceId,
+}
+
+impl JvmImage {
+    pub fn new(base: usize, size: usize) -> Self {
+        Self {
+            base,
+            memory: vec![0u8; size],
+            cursor: 0,
+            id: SourceId::next(),
+        }
+    }
+
+    pub fn base(&self) -> usize {
+        self.base
+    }
+
+    /// Everything in the image, as it would be dumped
+    pub fn bytes(&self) -> &[u8] {
+        &self.memory
+    }
+
+    /// Reserve `size` zeroed bytes after everything allocated so far
+    pub fn alloc(&mut self, size: usize) -> usize {
+        let address = self.base + self.cursor;
+
+        self.cursor = (self.cursor + size + ALIGNMENT - 1) & !(ALIGNMENT - 1);
+        assert!(self.cursor <= self.memory.len(), "JvmImage is full");
+
+        address
+    }
+
+    pub fn write_bytes(&mut self, address: usize, bytes: &[u8]) {
+        let offset = address
+            .checked_sub(self.base)
+            .expect("address below the image");
+
+        self.memory[offset..offset + bytes.len()].copy_from_slice(bytes);
+    }
+
+    /// Write the raw bytes of `value`, exactly how the target would hold it
+    pub fn write<T>(&mut self, address: usize, value: &T) {
+        let bytes = unsafe {
+            std::slice::from_raw_parts(value as *const T as *const u8, std::mem::size_of::<T>())
+        };
+
+        self.write_bytes(address, bytes);
+    }
+
+    /// Write `value` to the field `name` of the struct at `address`, fields this JVM doesn't
+    /// have are left alone
+    pub fn write_field<T>(&mut self, address: usize, name: &str, value: &T) {
+        let offset = VM_STRUCTS.lock().unwrap().offset_of(name);
+
+        if let Some(offset) = offset {
+            self.write(address + offset, value);
+        }
+    }
+
+    /// A `Symbol`, its length followed by the text at its body
+    pub fn put_symbol(&mut self, address: usize, text: &[u8]) {
+        self.write_field(address, "Symbol::_length", &(text.len() as u16));
+        self.write_bytes(address + offset_of("Symbol::_body"), text);
+    }
+
+    pub fn symbol(&mut self, text: &[u8]) -> usize {
+        let address = self.alloc(size_of("Symbol").max(offset_of("Symbol::_body") + text.len()));
+        self.put_symbol(address, text);
+
+        address
+    }
+
+    /// A `ConstantPool` with every slot after the header holding the given pointer
+    pub fn put_constant_pool(&mut self, address: usize, slots: &[usize]) {
+        let header_size = size_of("ConstantPool");
+
+        for (idx, slot) in slots.iter().enumerate() {
+            self.write(address + header_size + idx * std::mem::size_of::<usize>(), slot);
+        }
+    }
+
+    pub fn constant_pool(&mut self, slots: &[usize]) -> usize {
+        let address = self.alloc(size_of("ConstantPool") + std::mem::size_of_val(slots));
+        self.put_constant_pool(address, slots);
+
+        address
+    }
+
+    /// A `ConstantPool` with a tag per slot, `_length` and `_tags` set up to match
+    pub fn tagged_constant_pool(&mut self, entries: &[(u8, usize)]) -> usize {
+        let (tags, slots): (Vec<u8>, Vec<usize>) = entries.iter().copied().unzip();
+
+        let tags = self.array(&tags);
+        let address = self.constant_pool(&slots);
+
+        self.write_field(address, "ConstantPool::_tags", &tags);
+        self.write_field(address, "ConstantPool::_length", &(entries.len() as i32));
+
+        address
+    }
+
+    /// A `ConstantPoolCache` whose entries point back at the given constant pool indices, and
+    /// whose reference map maps resolved references onto `references`
+    pub fn constant_pool_cache(&mut self, cp_indices: &[u16], references: &[u16]) -> usize {
+        add_constant_pool_cache_layout();
+
+        let entry_size = size_of("ConstantPoolCacheEntry");
+        let address = self.alloc(size_of("ConstantPoolCache") + cp_indices.len() * entry_size);
+        let reference_map = self.array(references);
+
+        self.write_field(address, "ConstantPoolCache::_length", &(cp_indices.len() as i32));
+        self.write_field(address, "ConstantPoolCache::_reference_map", &reference_map);
+
+        for (idx, cp_index) in cp_indices.iter().enumerate() {
+            let entry = address + size_of("ConstantPoolCache") + idx * entry_size;
+
+            // the bytecodes it was resolved for sit above the index, they shouldn't matter
+            let indices = 0xb6b2_0000usize | *cp_index as usize;
+            self.write_field(entry, "ConstantPoolCacheEntry::_indices", &indices);
+        }
+
+        address
+    }
+
+    /// The `Array<u2>` of a class' fields, six shorts per field
+    pub fn put_fields(&mut self, address: usize, fields: &[[u16; 6]]) {
+        let slots = JFieldOffset::FieldSlots.value() as usize;
+
+        self.write(address, &((fields.len() * slots) as i32));
+
+        for (idx, field) in fields.iter().enumerate() {
+            self.write(address + 0x0004 + idx * slots * std::mem::size_of::<u16>(), field);
+        }
+    }
+
+    pub fn fields(&mut self, fields: &[[u16; 6]]) -> usize {
+        let slots = JFieldOffset::FieldSlots.value() as usize;
+
+        let address = self.alloc(0x0004 + fields.len() * slots * std::mem::size_of::<u16>());
+        self.put_fields(address, fields);
+
+        address
+    }
+
+    /// A JDK 21 `FieldInfoStream` of `fields`, none of them injected. Returns the address of the
+    /// `Array<u1>` holding it.
+    pub fn field_info_stream(&mut self, fields: &[FieldInfo]) -> usize {
+        let mut stream = Vec::new();
+        unsigned5(fields.len() as u32, &mut stream);
+        unsigned5(0, &mut stream);
+
+        for field in fields {
+            // only the initializer and the generic signature bits, as the defaults place them
+            let initialized = (field.initval_index != 0) as u32;
+            let generic = (field.generic_signature_index != 0) as u32;
+
+            for value in [
+                field.name_index as u32,
+                field.signature_index as u32,
+                field.offset,
+                field.access_flags as u32,
+                initialized | generic << 2,
+            ] {
+                unsigned5(value, &mut stream);
+            }
+
+            for optional in [field.initval_index, field.generic_signature_index] {
+                if optional != 0 {
+                    unsigned5(optional as u32, &mut stream);
+                }
+            }
+        }
+
+        // a 0 byte ends the stream
+        stream.push(0);
+
+        self.array(&stream)
+    }
+
+    /// A `Method` and its `ConstMethod` with the bytecode right behind it and the exception
+    /// table at the very end, the name and signature being the given constant pool indices.
+    /// Returns the address of the `Method`.
+    pub fn method(&mut self, name_idx: u16, sig_idx: u16, method: &FakeMethod) -> usize {
+        let tables = if method.exception_table.is_empty() {
+            0
+        } else {
+            std::mem::size_of_val(method.exception_table) + std::mem::size_of::<u16>()
+        };
+
+        let size = size_of("ConstMethod") + method.code.len() + tables;
+        let size = (size + ALIGNMENT - 1) & !(ALIGNMENT - 1);
+        let const_method = self.alloc(size);
+
+        if !method.exception_table.is_empty() {
+            let length = const_method + size - std::mem::size_of::<u16>();
+            let table = length - std::mem::size_of_val(method.exception_table);
+
+            self.write(length, &(method.exception_table.len() as u16));
+
+            for (idx, entry) in method.exception_table.iter().enumerate() {
+                self.write(table + idx * std::mem::size_of_val(entry), entry);
+            }
+
+            self.write_field(const_method, "ConstMethod::_flags", &0x0008u16);
+        }
+
+        self.write_field(
+            const_method,
+            "ConstMethod::_constMethod_size",
+            &((size / std::mem::size_of::<usize>()) as i32),
+        );
+        self.write_field(const_method, "ConstMethod::_code_size", &(method.code.len() as u16));
+        self.write_field(const_method, "ConstMethod::_name_index", &name_idx);
+        self.write_field(const_method, "ConstMethod::_signature_index", &sig_idx);
+        self.write_field(const_method, "ConstMethod::_max_stack", &method.max_stack);
+        self.write_field(const_method, "ConstMethod::_max_locals", &method.max_locals);
+        self.write_bytes(const_method + size_of("ConstMethod"), method.code);
+
+        let address = self.alloc(size_of("Method"));
+        self.write_field(address, "Method::_constMethod", &const_method);
+        self.write_field(address, "Method::_access_flags", &(method.access_flags as i32));
+
+        address
+    }
+
+    /// An `Array<T>`, its length followed by the elements aligned for `T`
+    pub fn array<T>(&mut self, elements: &[T]) -> usize {
+        let data = std::mem::size_of::<i32>().max(std::mem::align_of::<T>());
+        let address = self.alloc(data + std::mem::size_of_val(elements));
+
+        self.write(address, &(elements.len() as i32));
+
+        for (idx, element) in elements.iter().enumerate() {
+            self.write(address + data + idx * std::mem::size_of::<T>(), element);
+        }
+
+        address
+    }
+
+    /// A Java object of class `klass` with room for `size` bytes, its mark word unlocked and its
+    /// klass pointer compressed
+    pub fn instance(&mut self, klass: usize, size: usize) -> usize {
+        let object = self.alloc(size);
+
+        self.write(object, &0x1u64);
+        self.write(object + 8, &(klass as u32));
+
+        object
+    }
+
+    /// An `InstanceKlass` holding everything the sdk reads out of `clazz`
+    pub fn put_class(&mut self, address: usize, clazz: &JClass) {
+        let pointers: &[(&str, usize)] = &[
+            ("Klass::_name", clazz.symbol as _),
+            ("Klass::_secondary_super_cache", clazz.secondary_super_cache as _),
+            ("Klass::_secondary_supers", clazz.secondary_super_array as _),
+            ("Klass::_java_mirror", clazz.static_fields as _),
+            ("Klass::_super", clazz.super_klass as _),
+            ("Klass::_subklass", clazz.sub_klass as _),
+            ("Klass::_next_sibling", clazz.next_sibling as _),
+            ("Klass::_next_link", clazz.next_link as _),
+            ("Klass::_class_loader_data", clazz.classloader_data as _),
+            ("InstanceKlass::_constants", clazz.constant_pool as _),
+            ("InstanceKlass::_methods", clazz.methods as _),
+            ("InstanceKlass::_default_methods", clazz.default_methods as _),
+            ("InstanceKlass::_local_interfaces", clazz._local_interfaces as _),
+            ("InstanceKlass::_transitive_interfaces", clazz._transitive_interfaces as _),
+            ("InstanceKlass::_method_ordering", clazz._method_ordering as _),
+            ("InstanceKlass::_default_vtable_indices", clazz._default_vtable_indices as _),
+            ("InstanceKlass::_fields", clazz.fields as _),
+            ("InstanceKlass::_fieldinfo_stream", clazz.fieldinfo_stream as _),
+            ("InstanceKlass::_fields_status", clazz.fields_status as _),
+        ];
+
+        for (name, value) in pointers {
+            self.write_field(address, name, value);
+        }
+
+        self.write_field(address, "Klass::_layout_helper", &clazz.layout_helper);
+        self.write_field(address, "Klass::_super_check_offset", &clazz.super_check_offset);
+        self.write_field(address, "Klass::_modifier_flags", &clazz.modifier_flags);
+        self.write_field(address, "Klass::_access_flags", &clazz.access_flags);
+
+        if let Some(count) = clazz.java_fields_count {
+            self.write_field(address, "InstanceKlass::_java_fields_count", &count);
+        }
+    }
+
+    /// A class named `name` declaring `fields`, with its own constant pool holding the field names
+    /// and signatures. Returns the address of the class.
+    pub fn class(&mut self, name: &str, super_klass: usize, fields: &[FakeField]) -> usize {
+        self.class_with_methods(name, super_klass, fields, &[])
+    }
+
+    /// Like `class`, with `methods` declared as well
+    pub fn class_with_methods(
+        &mut self,
+        name: &str,
+        super_klass: usize,
+        fields: &[FakeField],
+        methods: &[FakeMethod],
+    ) -> usize {
+        // constant pool index 0 is never used by the JVM
+        let mut slots = vec![0usize];
+        let mut infos = Vec::new();
+        let mut method_indices = Vec::new();
+
+        for field in fields {
+            slots.push(self.symbol(field.name.as_bytes()));
+            slots.push(self.symbol(field.sig.as_bytes()));
+
+            infos.push(field_info(
+                (slots.len() - 2) as u16,
+                (slots.len() - 1) as u16,
+                field.offset,
+            ));
+        }
+
+        for method in methods {
+            slots.push(self.symbol(method.name.as_bytes()));
+            slots.push(self.symbol(method.sig.as_bytes()));
+
+            method_indices.push(((slots.len() - 2) as u16, (slots.len() - 1) as u16));
+        }
+
+        let methods = methods
+            .iter()
+            .zip(method_indices)
+            .map(|(method, (name_idx, sig_idx))| self.method(name_idx, sig_idx, method))
+            .collect::<Vec<usize>>();
+
+        let clazz = JClass {
+            symbol: self.symbol(name.as_bytes()) as _,
+            super_klass: super_klass as _,
+            constant_pool: self.constant_pool(&slots) as _,
+            methods: self.array(&methods) as _,
+            fields: self.fields(&infos) as _,
+            ..Default::default()
+        };
+
+        self.class_from(&clazz)
+    }
+
+    /// Wherever there's room for `clazz`, returns the address of the class
+    pub fn class_from(&mut self, clazz: &JClass) -> usize {
+        let address = self.alloc(size_of("InstanceKlass"));
+        self.put_class(address, clazz);
+
+        address
+    }
+
+    /// A `Dictionary` at `address` with its bucket array at `buckets`, every bucket chaining an
+    /// entry per klass in the same order. Returns the dictionary as the sdk would read it.
+    pub fn put_dictionary(
+        &mut self,
+        address: usize,
+        buckets: usize,
+        chains: &[&[usize]],
+    ) -> JVMDictionary {
+        for (idx, chain) in chains.iter().enumerate() {
+            let mut next = 0usize;
+
+            // build the chain back to front so every entry knows its successor
+            for (hash, klass) in chain.iter().enumerate().rev() {
+                let entry = self.alloc(size_of("DictionaryEntry"));
+
+                self.write_field(entry, "BasicHashtableEntry<mtInternal>::_hash", &(hash as u32));
+                self.write_field(entry, "BasicHashtableEntry<mtInternal>::_next", &next);
+                self.write_field(entry, "IntptrHashtableEntry::_literal", klass);
+
+                next = entry;
+            }
+
+            self.write(buckets + idx * std::mem::size_of::<usize>(), &next);
+        }
+
+        let dictionary = JVMDictionary {
+            table_size: chains.len() as i32,
+            entries: buckets as _,
+            no_clue_what_the_hell_this_is: std::ptr::null_mut(),
+            free_entry: std::ptr::null_mut(),
+            end_block: std::ptr::null_mut(),
+            entry_size: size_of("DictionaryEntry") as i32,
+            num_entries: chains.iter().map(|chain| chain.len() as i32).sum(),
+        };
+
+        let table = "BasicHashtable<mtInternal>";
+        self.write_field(address, &format!("{}::_table_size", table), &dictionary.table_size);
+        self.write_field(address, &format!("{}::_buckets", table), &buckets);
+        self.write_field(address, &format!("{}::_entry_size", table), &dictionary.entry_size);
+        self.write_field(
+            address,
+            &format!("{}::_number_of_entries", table),
+            &dictionary.num_entries,
+        );
+
+        dictionary
+    }
+
+    /// A `ClassLoaderData` for the loader oop `class_loader`, with `klasses` chained through
+    /// `Klass::_next_link` in the same order and pointing back at it
+    pub fn class_loader_data(&mut self, class_loader: usize, klasses: &[usize]) -> usize {
+        add_class_loader_data_layout();
+
+        let address = self.alloc(size_of("ClassLoaderData"));
+
+        for pair in klasses.windows(2) {
+            self.write_field(pair[0], "Klass::_next_link", &pair[1]);
+        }
+
+        for klass in klasses {
+            self.write_field(*klass, "Klass::_class_loader_data", &address);
+        }
+
+        self.write_field(address, "ClassLoaderData::_class_loader", &class_loader);
+        self.write_field(
+            address,
+            "ClassLoaderData::_klasses",
+            &klasses.first().copied().unwrap_or_default(),
+        );
+
+        address
+    }
+
+    /// Chain `loaders` through `ClassLoaderData::_next`, returns the address of the
+    /// `ClassLoaderDataGraph::_head` pointing at the first one
+    pub fn class_loader_data_graph(&mut self, loaders: &[usize]) -> usize {
+        for pair in loaders.windows(2) {
+            self.write_field(pair[0], "ClassLoaderData::_next", &pair[1]);
+        }
+
+        let head = self.alloc(std::mem::size_of::<usize>());
+        self.write(head, &loaders.first().copied().unwrap_or_default());
+
+        head
+    }
+
+    pub fn dictionary(&mut self, chains: &[&[usize]]) -> (usize, JVMDictionary) {
+        let address = self.alloc(size_of("BasicHashtable<mtInternal>"));
+        let buckets = self.alloc(chains.len() * std::mem::size_of::<usize>());
+
+        (address, self.put_dictionary(address, buckets, chains))
+    }
+}
+
+impl MemorySource for JvmImage {
+    fn id(&self) -> SourceId {
+        self.id
+    }
+
+    fn read_bytes(&self, address: usize, buffer: &mut [u8]) -> Result<(), ReadError> {
+        let offset = match address.checked_sub(self.base) {
+            Some(offset) if offset < self.memory.len() => offset,
+            _ => return Err(ReadError::UnmappedAddress(address)),
+        };
+
+        let read = buffer.len().min(self.memory.len() - offset);
+        buffer[..read].copy_from_slice(&self.memory[offset..offset + read]);
+
+        if read < buffer.len() {
+            return Err(ReadError::PartialRead {
+                address,
+                requested: buffer.len(),
+                read,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// The packed `FieldInfo` shorts of a field with an offset, as HotSpot 8 stores them
+pub fn field_info(name_idx: u16, sig_idx: u16, offset: u32) -> [u16; 6] {
+    // the low two bits of the packed offset are the tag, 0b01 meaning "has an offset"
+    let packed = (offset << 2) | 1;
+
+    [0, name_idx, sig_idx, 0, packed as u16, (packed >> 16) as u16]
+}
+
+/// UNSIGNED5 the way HotSpot writes it, the other end of `fieldinfo::Unsigned5Reader`
+pub fn unsigned5(mut value: u32, out: &mut Vec<u8>) {
+    // X = 1, L = 191, H = 64 and at most 5 bytes
+    for _ in 0..4 {
+        if value < 191 {
+            break;
+        }
+
+        value -= 191;
+        out.push((1 + 191 + value % 64) as u8);
+        value >>= 6;
+    }
+
+    out.push((1 + value) as u8);
+}
+
+/// The JDK 8 layouts don't know `ClassLoaderData`, borrow the JDK 17 one
+fn add_class_loader_data_layout() {
+    let mut structs = VM_STRUCTS.lock().unwrap();
+
+    if structs.size_of("ClassLoaderData").is_some() {
+        return;
+    }
+
+    for (name, offset) in [
+        ("ClassLoaderData::_class_loader", 0x0008),
+        ("ClassLoaderData::_klasses", 0x0038),
+        ("ClassLoaderData::_next", 0x0070),
+    ] {
+        structs.insert_field(
+            name,
+            VMField {
+                offset,
+                is_static: false,
+                address: 0,
+                type_string: None,
+            },
+        );
+    }
+
+    structs.add_type("ClassLoaderData", 0x0098, None);
+}
+
+/// The JDK 8 layouts don't know `ConstantPoolCache` either, borrow the JDK 17 one
+fn add_constant_pool_cache_layout() {
+    let mut structs = VM_STRUCTS.lock().unwrap();
+
+    if structs.size_of("ConstantPoolCache").is_some() {
+        return;
+    }
+
+    for (name, offset) in [
+        ("ConstantPoolCache::_length", 0x0000),
+        ("ConstantPoolCache::_reference_map", 0x0018),
+        ("ConstantPoolCacheEntry::_indices", 0x0000),
+    ] {
+        structs.insert_field(
+            name,
+            VMField {
+                offset,
+                is_static: false,
+                address: 0,
+                type_string: None,
+            },
+        );
+    }
+
+    structs.add_type("ConstantPoolCache", 0x0028, None);
+    structs.add_type("ConstantPoolCacheEntry", 0x0020, None);
+}
+
+fn offset_of(name: &str) -> usize {
+    VM_STRUCTS
+        .lock()
+        .unwrap()
+        .offset_of(name)
+        .unwrap_or_else(|| panic!("no offset for {}", name))
+}
+
+fn size_of(name: &str) -> usize {
+    VM_STRUCTS
+        .lock()
+        .unwrap()
+        .size_of(name)
+        .unwrap_or_else(|| panic!("no size for {}", name))
+}
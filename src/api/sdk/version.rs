@@ -0,0 +1,138 @@
+use crate::api::processes::{self, MemorySource};
+
+use super::vmstructs::{self, VMStructs};
+
+/// The Java release a target runs, all that matters to us is the major version
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct JavaVersion {
+    pub major: u32,
+}
+
+impl JavaVersion {
+    /// Ask the JVM which Java it is, first through `JDK_Version::_current` and otherwise through
+    /// the HotSpot version
+    pub fn detect(source: &dyn MemorySource, structs: &VMStructs) -> Option<Self> {
+        let jdk_major = structs
+            .static_address("JDK_Version::_current")
+            .zip(structs.offset_of("JDK_Version::_major"))
+            .and_then(|(current, major)| {
+                processes::try_read_exact::<u8>(source, current + major).ok()
+            })
+            .filter(|&major| major != 0);
+
+        if let Some(major) = jdk_major {
+            return Some(Self {
+                major: major as u32,
+            });
+        }
+
+        let vm_major = structs
+            .static_address("Abstract_VM_Version::_vm_major_version")
+            .and_then(|address| processes::try_read_exact::<i32>(source, address).ok())?;
+
+        // HotSpot only started following the Java version with 9, 8 ran HotSpot 25 and 7 ran 24
+        match vm_major {
+            24 => Some(Self { major: 7 }),
+            25 => Some(Self { major: 8 }),
+            major if major >= 9 => Some(Self {
+                major: major as u32,
+            }),
+            _ => None,
+        }
+    }
+
+    /// Whether loaded classes are kept per class loader (JDK 9 and up) rather than in the one
+    /// SystemDictionary
+    pub fn has_class_loader_data_graph(&self) -> bool {
+        self.major >= 9
+    }
+
+    /// Whether classes keep their fields in a compressed `FieldInfoStream` (JDK 21 and up) rather
+    /// than an array of shorts
+    pub fn has_field_info_stream(&self) -> bool {
+        self.major >= 21
+    }
+
+    /// Whether array elements are only aligned for their size (JDK 22 and up), an int[] without
+    /// compressed class pointers then starting at 20 rather than 24
+    pub fn has_element_aligned_arrays(&self) -> bool {
+        self.major >= 22
+    }
+}
+
+/// The release string of the VM, e.g. "25.292-b10" or "17.0.8+7", for telling the user
+pub fn vm_release(source: &dyn MemorySource, structs: &VMStructs) -> Option<String> {
+    let release = structs.static_address("Abstract_VM_Version::_s_vm_release")?;
+    let release = processes::try_read_exact::<usize>(source, release).ok()?;
+
+    vmstructs::read_c_string(source, release).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::api::sdk::{testing::JvmImage, vmstructs::VMField};
+
+    fn add_static(structs: &mut VMStructs, name: &str, address: usize) {
+        structs.insert_field(
+            name,
+            VMField {
+                offset: 0,
+                is_static: true,
+                address,
+                type_string: None,
+            },
+        );
+    }
+
+    #[test]
+    fn detects_jdk_version() {
+        let mut image = JvmImage::new(0x7f00_0000, 0x1000);
+        let mut structs = VMStructs::default();
+
+        let current = image.alloc(8);
+        image.write(current + 1, &17u8);
+        add_static(&mut structs, "JDK_Version::_current", current);
+        structs.insert_field(
+            "JDK_Version::_major",
+            VMField {
+                offset: 1,
+                is_static: false,
+                address: 0,
+                type_string: None,
+            },
+        );
+
+        let release = image.alloc(16);
+        image.write_bytes(release, b"17.0.8+7");
+        let release_pointer = image.alloc(8);
+        image.write(release_pointer, &release);
+        add_static(&mut structs, "Abstract_VM_Version::_s_vm_release", release_pointer);
+
+        assert_eq!(
+            JavaVersion::detect(&image, &structs),
+            Some(JavaVersion { major: 17 })
+        );
+        assert_eq!(vm_release(&image, &structs).as_deref(), Some("17.0.8+7"));
+    }
+
+    #[test]
+    fn maps_hotspot_version() {
+        let mut image = JvmImage::new(0x7f00_0000, 0x1000);
+        let mut structs = VMStructs::default();
+
+        let vm_major = image.alloc(4);
+        image.write(vm_major, &25i32);
+        add_static(&mut structs, "Abstract_VM_Version::_vm_major_version", vm_major);
+
+        let version = JavaVersion::detect(&image, &structs).unwrap();
+        assert_eq!(version, JavaVersion { major: 8 });
+        assert!(!version.has_class_loader_data_graph());
+
+        image.write(vm_major, &21i32);
+        let version = JavaVersion::detect(&image, &structs).unwrap();
+        assert_eq!(version.major, 21);
+        assert!(version.has_class_loader_data_graph());
+    }
+}
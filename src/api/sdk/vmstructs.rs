@@ -0,0 +1,639 @@
+//! The layout of HotSpot's own data structures, read at runtime from the tables the JVM exports
+//! for the Serviceability Agent (gHotSpotVMStructs, gHotSpotVMTypes, gHotSpotVMIntConstants and
+//! gHotSpotVMLongConstants) instead of guessed from a single build.
+
+use std::{collections::HashMap, sync::Mutex};
+
+use crate::api::processes::{self, MemorySource, ModuleEntry, ReadError};
+
+use super::version::JavaVersion;
+
+lazy_static::lazy_static! {
+    /// The layouts the sdk views are read with, the JDK 8 ones until the target's tables are read
+    pub static ref VM_STRUCTS: Mutex<VMStructs> = Mutex::new(VMStructs::jdk8());
+}
+
+// entry layouts of the exported tables, these haven't changed since JDK 6
+
+#[repr(C)]
+struct VMStructEntry {
+    type_name: usize,
+    field_name: usize,
+    type_string: usize,
+    is_static: i32,
+    offset: u64,
+    address: usize,
+} //Size: 0x0030
+
+#[repr(C)]
+struct VMTypeEntry {
+    type_name: usize,
+    superclass_name: usize,
+    is_oop_type: i32,
+    is_integer_type: i32,
+    is_unsigned: i32,
+    size: u64,
+} //Size: 0x0028
+
+#[repr(C)]
+struct VMIntConstantEntry {
+    name: usize,
+    value: i32,
+} //Size: 0x0010
+
+#[repr(C)]
+struct VMLongConstantEntry {
+    name: usize,
+    value: u64,
+} //Size: 0x0010
+
+/// Addresses of the exported gHotSpot* variables, each holding a pointer to its table. A table
+/// at address 0 is skipped.
+#[derive(Debug, Default, Clone)]
+pub struct VMStructsSymbols {
+    pub structs: usize,
+    pub types: usize,
+    pub int_constants: usize,
+    pub long_constants: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct VMField {
+    pub offset: usize,
+    pub is_static: bool,
+
+    /// Address of the field itself, only set for static fields
+    pub address: usize,
+
+    /// The C++ type of the field, if the JVM bothered to tell
+    pub type_string: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct VMType {
+    pub size: usize,
+    #[allow(dead_code)]
+    pub superclass: Option<String>,
+}
+
+/// Offset database, fields are keyed as "Type::_field" just like the JVM names them
+#[derive(Debug, Default, Clone)]
+pub struct VMStructs {
+    fields: HashMap<String, VMField>,
+    types: HashMap<String, VMType>,
+    int_constants: HashMap<String, i32>,
+    long_constants: HashMap<String, u64>,
+
+    /// The Java release these tables came from, once we've told them
+    java_version: Option<JavaVersion>,
+}
+
+/// A copy of one remote struct, fields are picked out of it by name
+pub struct StructView<'a> {
+    structs: &'a VMStructs,
+    bytes: Vec<u8>,
+}
+
+impl<'a> StructView<'a> {
+    /// The value of `name`, zeroed if this JVM doesn't have the field or it lies outside the struct
+    pub fn field<T: Copy>(&self, name: &str) -> T {
+        let size = std::mem::size_of::<T>();
+
+        match self.structs.offset_of(name) {
+            Some(offset) if offset + size <= self.bytes.len() => unsafe {
+                std::ptr::read_unaligned(self.bytes[offset..].as_ptr() as *const T)
+            },
+            _ => unsafe { core::mem::zeroed() },
+        }
+    }
+}
+
+/// Read a NUL terminated string, a little at a time so we don't run off the end of a mapping
+pub fn read_c_string(source: &dyn MemorySource, address: usize) -> Result<String, ReadError> {
+    let mut result = Vec::new();
+    let mut chunk = [0u8; 32];
+
+    loop {
+        let current = address + result.len();
+
+        let read = match source.read_bytes(current, &mut chunk) {
+            Ok(_) => chunk.len(),
+            Err(ReadError::PartialRead { read, .. }) if read > 0 => read,
+            Err(error) => return Err(error),
+        };
+
+        match chunk[..read].iter().position(|&c| c == 0) {
+            Some(end) => {
+                result.extend_from_slice(&chunk[..end]);
+                break;
+            }
+            None => result.extend_from_slice(&chunk[..read]),
+        }
+    }
+
+    Ok(String::from_utf8_lossy(&result).to_string())
+}
+
+fn read_optional_string(
+    source: &dyn MemorySource,
+    address: usize,
+) -> Result<Option<String>, ReadError> {
+    if address == 0 {
+        return Ok(None);
+    }
+
+    read_c_string(source, address).map(Some)
+}
+
+impl VMStructsSymbols {
+    /// Look the tables up in the exports of jvm.dll / libjvm.so, `None` if the module doesn't
+    /// export the struct and type tables
+    pub fn from_exports(source: &dyn MemorySource, module: &ModuleEntry) -> Option<Self> {
+        let export = |name: &str| processes::find_export(source, module, name);
+
+        Some(Self {
+            structs: export("gHotSpotVMStructs")?,
+            types: export("gHotSpotVMTypes")?,
+            int_constants: export("gHotSpotVMIntConstants").unwrap_or_default(),
+            long_constants: export("gHotSpotVMLongConstants").unwrap_or_default(),
+        })
+    }
+}
+
+impl VMStructs {
+    /// Read all four tables out of the target
+    pub fn read(source: &dyn MemorySource, symbols: &VMStructsSymbols) -> Result<Self, ReadError> {
+        let mut structs = Self::default();
+
+        let table = |symbol: usize| -> Result<usize, ReadError> {
+            if symbol == 0 {
+                return Ok(0);
+            }
+
+            processes::try_read_exact::<usize>(source, symbol)
+        };
+
+        let mut address = table(symbols.structs)?;
+        while address != 0 {
+            let entry = processes::try_read_class::<VMStructEntry>(source, address)?;
+
+            if entry.type_name == 0 {
+                break;
+            }
+
+            let name = format!(
+                "{}::{}",
+                read_c_string(source, entry.type_name)?,
+                read_c_string(source, entry.field_name)?
+            );
+
+            structs.fields.insert(
+                name,
+                VMField {
+                    offset: entry.offset as usize,
+                    is_static: entry.is_static != 0,
+                    address: entry.address,
+                    type_string: read_optional_string(source, entry.type_string)?,
+                },
+            );
+
+            address += std::mem::size_of::<VMStructEntry>();
+        }
+
+        let mut address = table(symbols.types)?;
+        while address != 0 {
+            let entry = processes::try_read_class::<VMTypeEntry>(source, address)?;
+
+            if entry.type_name == 0 {
+                break;
+            }
+
+            structs.types.insert(
+                read_c_string(source, entry.type_name)?,
+                VMType {
+                    size: entry.size as usize,
+                    superclass: read_optional_string(source, entry.superclass_name)?,
+                },
+            );
+
+            address += std::mem::size_of::<VMTypeEntry>();
+        }
+
+        let mut address = table(symbols.int_constants)?;
+        while address != 0 {
+            let entry = processes::try_read_class::<VMIntConstantEntry>(source, address)?;
+
+            if entry.name == 0 {
+                break;
+            }
+
+            structs
+                .int_constants
+                .insert(read_c_string(source, entry.name)?, entry.value);
+
+            address += std::mem::size_of::<VMIntConstantEntry>();
+        }
+
+        let mut address = table(symbols.long_constants)?;
+        while address != 0 {
+            let entry = processes::try_read_class::<VMLongConstantEntry>(source, address)?;
+
+            if entry.name == 0 {
+                break;
+            }
+
+            structs
+                .long_constants
+                .insert(read_c_string(source, entry.name)?, entry.value);
+
+            address += std::mem::size_of::<VMLongConstantEntry>();
+        }
+
+        Ok(structs)
+    }
+
+    /// The layouts of the JDK 8 build the sdk was originally reversed from, for targets that
+    /// don't export their tables
+    pub fn jdk8() -> Self {
+        let mut structs = Self::default();
+
+        let fields: &[(&str, usize)] = &[
+            ("Klass::_layout_helper", 0x0008),
+            ("Klass::_super_check_offset", 0x000C),
+            ("Klass::_name", 0x0010),
+            ("Klass::_secondary_super_cache", 0x0018),
+            ("Klass::_secondary_supers", 0x0020),
+            ("Klass::_java_mirror", 0x0068),
+            ("Klass::_super", 0x0070),
+            ("Klass::_subklass", 0x0078),
+            ("Klass::_next_sibling", 0x0080),
+            ("Klass::_next_link", 0x0088),
+            ("Klass::_class_loader_data", 0x0090),
+            ("Klass::_modifier_flags", 0x0098),
+            ("Klass::_access_flags", 0x009C),
+            ("InstanceKlass::_constants", 0x00D8),
+            ("InstanceKlass::_methods", 0x0180),
+            ("InstanceKlass::_default_methods", 0x0188),
+            ("InstanceKlass::_local_interfaces", 0x0190),
+            ("InstanceKlass::_transitive_interfaces", 0x0198),
+            ("InstanceKlass::_method_ordering", 0x01A0),
+            ("InstanceKlass::_default_vtable_indices", 0x01A8),
+            ("InstanceKlass::_fields", 0x01B0),
+            ("Method::_constMethod", 0x0008),
+            ("Method::_access_flags", 0x0020),
+            ("ConstMethod::_constants", 0x0008),
+            ("ConstMethod::_stackmap_data", 0x0010),
+            ("ConstMethod::_constMethod_size", 0x0018),
+            ("ConstMethod::_flags", 0x001C),
+            ("ConstMethod::_code_size", 0x001E),
+            ("ConstMethod::_name_index", 0x0020),
+            ("ConstMethod::_signature_index", 0x0022),
+            ("ConstMethod::_max_stack", 0x0026),
+            ("ConstMethod::_max_locals", 0x0028),
+            ("ConstMethod::_size_of_parameters", 0x002A),
+            ("ConstantPool::_tags", 0x0008),
+            ("ConstantPool::_cache", 0x0010),
+            ("ConstantPool::_pool_holder", 0x0018),
+            ("ConstantPool::_operands", 0x0020),
+            ("ConstantPool::_resolved_references", 0x0028),
+            ("ConstantPool::_major_version", 0x0030),
+            ("ConstantPool::_minor_version", 0x0032),
+            ("ConstantPool::_generic_signature_index", 0x0034),
+            ("ConstantPool::_source_file_name_index", 0x0036),
+            ("ConstantPool::_flags", 0x0038),
+            ("ConstantPool::_length", 0x003C),
+            ("ConstantPool::_saved", 0x0040),
+            ("ConstantPool::_lock", 0x0048),
+            ("Symbol::_length", 0x0000),
+            ("Symbol::_identity_hash", 0x0002),
+            ("Symbol::_body", 0x0008),
+            ("BasicHashtable<mtInternal>::_table_size", 0x0000),
+            ("BasicHashtable<mtInternal>::_buckets", 0x0008),
+            ("BasicHashtable<mtInternal>::_free_list", 0x0010),
+            ("BasicHashtable<mtInternal>::_first_free_entry", 0x0018),
+            ("BasicHashtable<mtInternal>::_end_block", 0x0020),
+            ("BasicHashtable<mtInternal>::_entry_size", 0x0028),
+            ("BasicHashtable<mtInternal>::_number_of_entries", 0x002C),
+            ("BasicHashtableEntry<mtInternal>::_hash", 0x0000),
+            ("BasicHashtableEntry<mtInternal>::_next", 0x0008),
+            ("IntptrHashtableEntry::_literal", 0x0010),
+            ("DictionaryEntry::_loader_data", 0x0018),
+        ];
+
+        for (name, offset) in fields {
+            structs.insert_field(
+                name,
+                VMField {
+                    offset: *offset,
+                    is_static: false,
+                    address: 0,
+                    type_string: None,
+                },
+            );
+        }
+
+        let types: &[(&str, usize, Option<&str>)] = &[
+            ("InstanceKlass", 0x01B8, Some("Klass")),
+            ("ConstantPool", 0x0050, Some("Metadata")),
+            ("Method", 0x0058, Some("Metadata")),
+            ("ConstMethod", 0x0030, Some("MetaspaceObj")),
+            ("Symbol", 0x0010, None),
+            ("BasicHashtable<mtInternal>", 0x0030, None),
+            ("DictionaryEntry", 0x0020, Some("IntptrHashtableEntry")),
+        ];
+
+        for (name, size, superclass) in types {
+            structs.add_type(name, *size, *superclass);
+        }
+
+        let int_constants: &[(&str, i32)] = &[
+            ("oopSize", 8),
+            ("FieldInfo::access_flags_offset", 0),
+            ("FieldInfo::name_index_offset", 1),
+            ("FieldInfo::signature_index_offset", 2),
+            ("FieldInfo::initval_index_offset", 3),
+            ("FieldInfo::low_packed_offset", 4),
+            ("FieldInfo::high_packed_offset", 5),
+            ("FieldInfo::field_slots", 6),
+            ("FIELDINFO_TAG_SIZE", 2),
+        ];
+
+        for (name, value) in int_constants {
+            structs.add_int_constant(name, *value);
+        }
+
+        structs
+    }
+
+    /// Add (or replace) a field, for layouts the target doesn't tell us about
+    pub fn insert_field(&mut self, name: &str, field: VMField) {
+        self.fields.insert(name.to_string(), field);
+    }
+
+    pub fn add_type(&mut self, name: &str, size: usize, superclass: Option<&str>) {
+        self.types.insert(
+            name.to_string(),
+            VMType {
+                size,
+                superclass: superclass.map(String::from),
+            },
+        );
+    }
+
+    pub fn add_int_constant(&mut self, name: &str, value: i32) {
+        self.int_constants.insert(name.to_string(), value);
+    }
+
+    /// Tell the tables which Java release they came from, the layouts that aren't described in
+    /// them depend on it
+    pub fn set_java_version(&mut self, version: JavaVersion) {
+        self.java_version = Some(version);
+    }
+
+    pub fn java_version(&self) -> Option<JavaVersion> {
+        self.java_version
+    }
+
+    /// Make these the layouts every sdk view is read with from now on
+    pub fn install(self) {
+        *VM_STRUCTS.lock().unwrap() = self;
+    }
+
+    pub fn field(&self, name: &str) -> Option<&VMField> {
+        self.fields.get(name)
+    }
+
+    pub fn offset_of(&self, name: &str) -> Option<usize> {
+        self.field(name)
+            .filter(|field| !field.is_static)
+            .map(|field| field.offset)
+    }
+
+    /// Where a static field like "SystemDictionary::_dictionary" lives in the target
+    pub fn static_address(&self, name: &str) -> Option<usize> {
+        self.field(name)
+            .filter(|field| field.is_static)
+            .map(|field| field.address)
+    }
+
+    pub fn vm_type(&self, name: &str) -> Option<&VMType> {
+        self.types.get(name)
+    }
+
+    pub fn size_of(&self, name: &str) -> Option<usize> {
+        self.vm_type(name).map(|vm_type| vm_type.size)
+    }
+
+    pub fn int_constant(&self, name: &str) -> Option<i32> {
+        self.int_constants.get(name).copied()
+    }
+
+    pub fn long_constant(&self, name: &str) -> Option<u64> {
+        self.long_constants.get(name).copied()
+    }
+
+    /// Where the JVM keeps the value of its command line flag `name`, looked up in the flag table
+    /// it exports (`Flag` in JDK 8, `JVMFlag` from 11 on)
+    pub fn flag_address(&self, source: &dyn MemorySource, name: &str) -> Option<usize> {
+        let flag = ["JVMFlag", "Flag"]
+            .iter()
+            .find(|flag| self.size_of(flag).is_some())?;
+
+        let size = self.size_of(flag)?;
+        let name_field = format!("{}::_name", flag);
+        let addr_field = format!("{}::_addr", flag);
+
+        let static_value = |field: &str| {
+            let address = self.static_address(&format!("{}::{}", flag, field))?;
+
+            processes::try_read_exact::<usize>(source, address).ok()
+        };
+        let flags = static_value("flags")?;
+        let count = static_value("numFlags")?;
+
+        for idx in 0..count {
+            let view = self.read_struct(source, flag, flags + idx * size).ok()?;
+
+            // the table ends with an entry without a name
+            let flag_name = view.field::<usize>(&name_field);
+            if flag_name == 0 {
+                break;
+            }
+
+            if read_c_string(source, flag_name).ok()? == name {
+                return Some(view.field(&addr_field));
+            }
+        }
+
+        None
+    }
+
+    /// The value of the boolean flag `name`, e.g. "UseCompressedOops"
+    pub fn bool_flag(&self, source: &dyn MemorySource, name: &str) -> Option<bool> {
+        let address = self.flag_address(source, name)?;
+
+        processes::try_read_exact::<u8>(source, address)
+            .ok()
+            .map(|value| value != 0)
+    }
+
+    /// Copy the `type_name` at `address` out of the target, a type we don't know the size of
+    /// gives a view where every field reads as zero
+    pub fn read_struct(
+        &self,
+        source: &dyn MemorySource,
+        type_name: &str,
+        address: usize,
+    ) -> Result<StructView<'_>, ReadError> {
+        let mut bytes = vec![0u8; self.size_of(type_name).unwrap_or_default()];
+
+        if address == 0 {
+            return Err(ReadError::UnmappedAddress(address));
+        }
+
+        source.read_bytes(address, bytes.as_mut_slice())?;
+
+        Ok(StructView {
+            structs: self,
+            bytes,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::api::sdk::testing::JvmImage;
+
+    fn c_string(image: &mut JvmImage, text: &str) -> usize {
+        let address = image.alloc(text.len() + 1);
+        image.write_bytes(address, text.as_bytes());
+
+        address
+    }
+
+    /// The tables of a JDK 11 style JVM, whose Symbol differs from JDK 8
+    fn write_tables(image: &mut JvmImage) -> VMStructsSymbols {
+        let mut structs = Vec::new();
+        for (type_name, field_name, is_static, offset, address) in [
+            ("Symbol", "_length", 0, 4u64, 0usize),
+            ("Symbol", "_body", 0, 6, 0),
+            ("SystemDictionary", "_dictionary", 1, 0, 0x7f00_1230),
+        ] {
+            structs.push(VMStructEntry {
+                type_name: c_string(image, type_name),
+                field_name: c_string(image, field_name),
+                type_string: 0,
+                is_static,
+                offset,
+                address,
+            });
+        }
+        structs.push(unsafe { core::mem::zeroed() });
+
+        let types = [
+            VMTypeEntry {
+                type_name: c_string(image, "Symbol"),
+                superclass_name: c_string(image, "MetaspaceObj"),
+                is_oop_type: 0,
+                is_integer_type: 0,
+                is_unsigned: 0,
+                size: 8,
+            },
+            unsafe { core::mem::zeroed() },
+        ];
+
+        let int_constants = [
+            VMIntConstantEntry {
+                name: c_string(image, "oopSize"),
+                value: 8,
+            },
+            unsafe { core::mem::zeroed() },
+        ];
+
+        let long_constants = [
+            VMLongConstantEntry {
+                name: c_string(image, "markOopDesc::hash_mask"),
+                value: 0x7fff_ffff,
+            },
+            unsafe { core::mem::zeroed() },
+        ];
+
+        let table = |image: &mut JvmImage, size: usize, bytes: &dyn Fn(&mut JvmImage, usize)| {
+            let table = image.alloc(size);
+            bytes(image, table);
+
+            // the exported symbol is a pointer to the table
+            let symbol = image.alloc(8);
+            image.write(symbol, &table);
+
+            symbol
+        };
+
+        VMStructsSymbols {
+            structs: table(image, std::mem::size_of_val(&structs[..]), &|image, at| {
+                for (idx, entry) in structs.iter().enumerate() {
+                    image.write(at + idx * std::mem::size_of::<VMStructEntry>(), entry);
+                }
+            }),
+            types: table(image, std::mem::size_of_val(&types), &|image, at| {
+                image.write(at, &types)
+            }),
+            int_constants: table(image, std::mem::size_of_val(&int_constants), &|image, at| {
+                image.write(at, &int_constants)
+            }),
+            long_constants: table(image, std::mem::size_of_val(&long_constants), &|image, at| {
+                image.write(at, &long_constants)
+            }),
+        }
+    }
+
+    #[test]
+    fn reads_exported_tables() {
+        let mut image = JvmImage::new(0x7f00_0000, 0x2000);
+        let symbols = write_tables(&mut image);
+
+        let structs = VMStructs::read(&image, &symbols).unwrap();
+
+        assert_eq!(structs.offset_of("Symbol::_length"), Some(4));
+        assert_eq!(structs.offset_of("Symbol::_body"), Some(6));
+        assert_eq!(
+            structs.static_address("SystemDictionary::_dictionary"),
+            Some(0x7f00_1230)
+        );
+        // static fields have no offset to speak of
+        assert_eq!(structs.offset_of("SystemDictionary::_dictionary"), None);
+
+        assert_eq!(structs.size_of("Symbol"), Some(8));
+        assert_eq!(
+            structs.vm_type("Symbol").unwrap().superclass.as_deref(),
+            Some("MetaspaceObj")
+        );
+        assert_eq!(structs.int_constant("oopSize"), Some(8));
+        assert_eq!(structs.long_constant("markOopDesc::hash_mask"), Some(0x7fff_ffff));
+
+        assert!(structs.offset_of("Klass::_name").is_none());
+    }
+
+    #[test]
+    fn views_follow_the_database() {
+        let mut image = JvmImage::new(0x7f00_0000, 0x2000);
+        let symbols = write_tables(&mut image);
+        let structs = VMStructs::read(&image, &symbols).unwrap();
+
+        let symbol = image.alloc(16);
+        image.write(symbol + 4, &3u16);
+        image.write_bytes(symbol + 6, b"bao");
+
+        let view = structs.read_struct(&image, "Symbol", symbol).unwrap();
+        assert_eq!(view.field::<u16>("Symbol::_length"), 3);
+        // unknown to this JVM
+        assert_eq!(view.field::<u32>("Symbol::_identity_hash"), 0);
+
+        assert_eq!(
+            structs.read_struct(&image, "Symbol", 0x10).err(),
+            Some(ReadError::UnmappedAddress(0x10))
+        );
+    }
+}
@@ -1,74 +1,112 @@
-use std::sync::Mutex;
-
-use crate::api::{
-    processes::{self, NativeHandle},
-    sdk::minecraft::find_class,
-};
+use crate::api::processes::MemorySource;
 
 use super::{
+    collections,
     entity::Entity,
-    java::{self, JavaArray},
-    FromNative, JClass,
+    remote::{RemoteClass, RemoteError, RemoteObject},
 };
 
-#[derive(Debug, Default)]
+#[derive(Debug, RemoteClass)]
+#[class("bjf")]
 pub struct World {
-    _clazz: JClass,
+    #[address]
     pub _address: usize,
-}
 
-lazy_static::lazy_static! {
-    static ref PLAYERS_POINTERS_OFFSET: Mutex<usize> = Mutex::new(0usize);
+    /// playerEntities, read through `player_entities()` as the GC moves it
+    #[field("h", "Ljava/util/List;")]
+    #[allow(dead_code)]
+    pub player_entities: Option<RemoteObject>,
 }
 
 impl World {
-    pub fn new(class: &JClass, address: u32) -> Self {
-        Self {
-            _clazz: class.clone(),
-            _address: address as _,
+    #[cfg_attr(not(windows), allow(dead_code))]
+    pub fn get_players(&self, source: &dyn MemorySource) -> Result<Vec<Entity>, RemoteError> {
+        let mut res = vec![];
+
+        for player in self.get_players_pointers(source)? {
+            res.push(Entity::read(source, player)?);
         }
+
+        Ok(res)
     }
 
-    pub fn get_players(&self, handle: &NativeHandle) -> Vec<Entity> {
-        let mut res = vec![];
+    #[allow(dead_code)]
+    pub fn get_players_pointers(
+        &self,
+        source: &dyn MemorySource,
+    ) -> Result<Vec<usize>, RemoteError> {
+        match self.player_entities(source)? {
+            Some(list) => collections::list_elements(source, list.address),
+            None => Ok(Vec::new()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{
+        api::sdk::{
+            testing::{field, JvmImage},
+            FromNative, JClass,
+        },
+        ether::CLASSES,
+    };
 
-        let players = self.get_players_pointers(handle);
+    #[test]
+    fn reads_players() {
+        let mut image = JvmImage::new(0x7f00_0000, 0x4000);
 
-        for i in 0i32..players.length {
-            let address = players.get_at(handle, i);
+        let object = image.class("java/lang/Object", 0, &[]);
+        let objects = image.class("[Ljava/lang/Object;", 0, &[]);
+        let array_list = image.class(
+            "java/util/ArrayList",
+            object,
+            &[
+                field("size", "I", 0x10),
+                field("elementData", "[Ljava/lang/Object;", 0x14),
+            ],
+        );
+        let world = image.class("bjf", object, &[field("h", "Ljava/util/List;", 0x0c)]);
+        let entity = image.class(
+            "bll",
+            object,
+            &[field("s", "D", 0x10), field("S", "D", 0x28)],
+        );
 
-            res.push(Entity::new(
-                address.expect("Couldn't get address of player"),
-            ));
+        for (name, klass) in [("bjf", world), ("bll", entity)] {
+            let clazz = JClass::from_native(&image, klass as _).unwrap();
+            CLASSES.lock().unwrap().insert(0, name.to_string(), clazz);
         }
 
-        drop(players);
+        let player = image.instance(entity, 0x40);
+        image.write(player + 0x10, &[1.5f64, 0.0, -3.25]);
+        image.write(player + 0x28, &[1.0f64, 0.0, -3.0]);
 
-        res
-    }
+        // room for two players, one of them there
+        let elements = image.instance(objects, 16 + 2 * 4);
+        image.write(elements + 12, &2i32);
+        image.write(elements + 16, &(player as u32));
 
-    #[allow(unused)]
-    pub fn get_players_pointers(&self, handle: &NativeHandle) -> JavaArray<u32> {
-        if *PLAYERS_POINTERS_OFFSET.lock().unwrap() == 0usize {
-            let _clazz = find_class("bjf");
-            *PLAYERS_POINTERS_OFFSET.lock().unwrap() = _clazz
-                .find_field_entry(&handle, "h", "Ljava/util/List;")
-                .expect("Couldn't find playerEntities field")
-                ._field_info
-                .offset() as usize;
-        }
+        let players = image.instance(array_list, 0x18);
+        image.write(players + 0x10, &1i32);
+        image.write(players + 0x14, &(elements as u32));
 
-        let mut player_entities_pointer: u32 = 0;
+        let the_world = image.instance(world, 0x10);
+        image.write(the_world + 0x0c, &(players as u32));
 
-        processes::read(
-            &handle,
-            (self._address + *PLAYERS_POINTERS_OFFSET.lock().unwrap()) as usize,
-            &mut player_entities_pointer,
-        );
+        let world = World::read(&image, the_world).unwrap();
+        let players = world.get_players(&image).unwrap();
+        assert_eq!(players.len(), 1);
+        assert_eq!(players[0]._address, player);
+
+        let head = players[0].get_head_position();
+        assert_eq!((head.x, head.y, head.z), (1.5, 1.8, -3.25));
+        assert_eq!(players[0].last_tick_position.x, 1.0);
 
-        java::JavaArray::from_native(
-            &handle,
-            player_entities_pointer as *mut java::JavaArray<u32>,
-        )
+        // the list is read again every time, there's nobody left once it's gone
+        image.write(the_world + 0x0c, &0u32);
+        assert!(world.get_players(&image).unwrap().is_empty());
     }
 }
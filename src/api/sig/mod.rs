@@ -1,5 +1,6 @@
 use std::{i32, str};
 
+#[cfg(windows)]
 use winapi::{
     shared::minwindef::DWORD,
     um::{
@@ -11,7 +12,7 @@ use winapi::{
     },
 };
 
-use super::processes::ModuleEntry;
+use super::processes::{MemorySource, ModuleEntry};
 
 pub struct Signature {
     sig: String,
@@ -47,8 +48,11 @@ impl Signature {
     }
 }
 
+/// Size of the chunks we read a module in, a page we can't read only costs us that page
+const PAGE_SIZE: usize = 0x1000;
+
 pub fn pattern_scan_module(
-    handle: HANDLE,
+    source: &dyn MemorySource,
     pattern: &Signature,
     module: ModuleEntry,
 ) -> Option<usize> {
@@ -58,87 +62,55 @@ pub fn pattern_scan_module(
         return None;
     }
 
-    unsafe {
-        // Spawn buffer for module
-        let mut module_buffer = vec![0 as i8; module.size];
-        let mut old_protection: DWORD = 0;
+    // Spawn buffer for module
+    let mut module_buffer = vec![0u8; module.size];
 
-        let pattern_to_bytes = pattern.to_bytes();
+    let pattern_to_bytes = pattern.to_bytes();
 
-        // make sure we can access it
-        VirtualProtectEx(
-            handle,
-            module.base as _,
-            module.size,
-            PAGE_EXECUTE_READWRITE,
-            &mut old_protection as *mut _,
-        );
+    if module.size < pattern_to_bytes.len() {
+        return None;
+    }
+
+    // Read the module, guard pages and sections missing from a dump just stay zeroed
+    let mut unreadable = 0usize;
 
-        // Read the module
-        if ReadProcessMemory(
-            handle,
-            module.base as _,
-            module_buffer.as_mut_ptr() as *mut _,
-            module.size,
-            std::ptr::null_mut(),
-        ) != 1
-        {
-            println!(
-                "Unable to read module {} @ {:p} with size {}",
-                module.name, module.base as *mut i8, module.size
-            );
+    for (idx, page) in module_buffer.chunks_mut(PAGE_SIZE).enumerate() {
+        if source.read_bytes(module.base + idx * PAGE_SIZE, page).is_err() {
+            unreadable += 1;
         }
+    }
 
-        let search = || {
-            for i in 0..(module.size - pattern_to_bytes.len()) {
-                let mut found = true;
+    if unreadable != 0 {
+        println!(
+            "Unable to read {} pages of module {} @ {:p} with size {}",
+            unreadable, module.name, module.base as *mut i8, module.size
+        );
+    }
 
-                for j in 0..(pattern_to_bytes.len()) {
-                    if module_buffer[i + j] != pattern_to_bytes[j] as _
-                        && pattern_to_bytes[j] != 0xCC
-                    {
-                        found = false;
-                        break;
-                    }
-                }
+    let search = || {
+        for i in 0..(module.size - pattern_to_bytes.len()) {
+            let mut found = true;
 
-                if found {
-                    return Some(module.base as usize + i);
+            for j in 0..(pattern_to_bytes.len()) {
+                if module_buffer[i + j] as i32 != pattern_to_bytes[j] && pattern_to_bytes[j] != 0xCC
+                {
+                    found = false;
+                    break;
                 }
             }
-            None
-        };
 
-        // search
-        if let Some(address) = search() {
-            // reset page protection
-            VirtualProtectEx(
-                handle,
-                module.base as _,
-                module.size,
-                old_protection,
-                std::ptr::null_mut(),
-            );
-
-            // return address
-            return Some(address);
-        } else {
-            // Do nothing
+            if found {
+                return Some(module.base + i);
+            }
         }
+        None
+    };
 
-        // reset old protection
-        VirtualProtectEx(
-            handle,
-            module.base as _,
-            module.size,
-            old_protection,
-            std::ptr::null_mut(),
-        );
-    }
-
-    None
+    // search
+    search()
 }
 
+#[cfg(windows)]
 #[allow(dead_code)]
 pub fn pattern_scan_memory(handle: HANDLE, pattern: &Signature) -> Option<usize> {
     if pattern.to_bytes().len() == 0 {
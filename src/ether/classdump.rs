@@ -0,0 +1,222 @@
+//! Dumping every loaded class into a jar, laid out by package the way it'd have been on disk
+
+use std::{
+    collections::HashSet,
+    io::{self, Write},
+};
+
+use crate::api::{jar::JarWriter, processes, sdk::classfile};
+
+use super::Classes;
+
+/// Lists what didn't make it into the jar and why, one `name<TAB>loader<TAB>reason` per line
+pub const FAILURES: &str = "META-INF/failed-classes.txt";
+
+const MANIFEST: &str = "META-INF/MANIFEST.MF";
+
+/// Which classes to dump, everything by default
+#[derive(Debug, Default)]
+pub struct ClassFilter {
+    /// Classes whose name starts with this, packages may be separated by '.' or '/'
+    pub prefix: Option<String>,
+
+    /// Only classes defined by this loader (oop), 0 being the boot loader
+    pub class_loader: Option<usize>,
+}
+
+impl ClassFilter {
+    pub fn matches(&self, class_loader: usize, name: &str) -> bool {
+        let in_package = match &self.prefix {
+            Some(prefix) => name.starts_with(&prefix.replace('.', "/")),
+            None => true,
+        };
+
+        in_package && (self.class_loader.is_none() || self.class_loader == Some(class_loader))
+    }
+}
+
+/// A class we couldn't put in the jar
+#[derive(Debug)]
+pub struct Failure {
+    pub name: String,
+    pub class_loader: usize,
+    pub reason: String,
+}
+
+/// What a dump ended up holding
+#[derive(Debug, Default)]
+pub struct Summary {
+    pub written: usize,
+    pub failures: Vec<Failure>,
+}
+
+/// Reconstructs every class in `classes` that passes `filter` and writes it to a jar, the
+/// failures go into the jar as well (see `FAILURES`). A jar can only hold one class per name, the
+/// first loader wins and the others are listed as failures.
+pub fn dump_classes<W: Write>(
+    classes: &Classes,
+    source: &dyn processes::MemorySource,
+    filter: &ClassFilter,
+    writer: W,
+) -> io::Result<(W, Summary)> {
+    let mut jar = JarWriter::new(writer);
+    jar.add(
+        MANIFEST,
+        b"Manifest-Version: 1.0\r\nCreated-By: Ethe-rs\r\n\r\n",
+    )?;
+
+    let mut summary = Summary::default();
+    let mut written = HashSet::new();
+
+    for (class_loader, name, clazz) in classes.iter() {
+        if !filter.matches(class_loader, name) {
+            continue;
+        }
+
+        let mut fail = |reason: String| {
+            summary.failures.push(Failure {
+                name: name.to_string(),
+                class_loader,
+                reason,
+            })
+        };
+
+        if written.contains(name) {
+            fail(
+                "another loader defined a class by this name, filter by loader to get this one"
+                    .to_string(),
+            );
+            continue;
+        }
+
+        match classfile::write_class(source, clazz) {
+            Ok(bytes) => {
+                jar.add(&format!("{}.class", name), &bytes)?;
+
+                written.insert(name);
+                summary.written += 1;
+            }
+            Err(error) => fail(error.to_string()),
+        }
+    }
+
+    let failures = summary
+        .failures
+        .iter()
+        .map(|failure| {
+            format!(
+                "{}\t{:#x}\t{}\n",
+                failure.name, failure.class_loader, failure.reason
+            )
+        })
+        .collect::<String>();
+    jar.add(FAILURES, failures.as_bytes())?;
+
+    Ok((jar.finish()?, summary))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::api::sdk::{constantpool::*, testing::JvmImage, FromNative, JClass};
+
+    /// A class the class file writer can handle, with nothing in it
+    fn writable_class(image: &mut JvmImage, name: &str, super_klass: usize) -> JClass {
+        let slots = vec![
+            (JVM_CONSTANT_INVALID, 0),
+            (JVM_CONSTANT_UTF8, image.symbol(name.as_bytes())),
+        ];
+        let constant_pool = image.tagged_constant_pool(&slots);
+        image.write_field(constant_pool, "ConstantPool::_major_version", &52u16);
+
+        let clazz = JClass {
+            symbol: image.symbol(name.as_bytes()) as _,
+            super_klass: super_klass as _,
+            constant_pool: constant_pool as _,
+            methods: image.array::<usize>(&[]) as _,
+            fields: image.fields(&[]) as _,
+            ..Default::default()
+        };
+
+        let address = image.class_from(&clazz);
+
+        JClass::from_native(image, address as _).unwrap()
+    }
+
+    #[test]
+    fn dumps_by_package_path() {
+        let mut image = JvmImage::new(0x7f00_0000, 0x4000);
+
+        let object = image.class("java/lang/Object", 0, &[]);
+
+        let mut classes = Classes::default();
+        classes.insert(
+            0,
+            "java/lang/Object".to_string(),
+            JClass::from_native(&image, object as _).unwrap(),
+        );
+
+        let vanilla = writable_class(&mut image, "net/minecraft/client/Minecraft", object);
+        let modded = writable_class(&mut image, "net/minecraft/client/Minecraft", object);
+        let other = writable_class(&mut image, "com/example/Mod", object);
+        classes.insert(
+            0x7f00_1000,
+            "net/minecraft/client/Minecraft".to_string(),
+            vanilla,
+        );
+        classes.insert(
+            0x7f00_2000,
+            "net/minecraft/client/Minecraft".to_string(),
+            modded,
+        );
+        classes.insert(0x7f00_2000, "com/example/Mod".to_string(), other);
+
+        let filter = ClassFilter {
+            prefix: Some("net.minecraft.".to_string()),
+            class_loader: None,
+        };
+        let (bytes, summary) = dump_classes(&classes, &image, &filter, Vec::new()).unwrap();
+
+        assert_eq!(summary.written, 1);
+        assert_eq!(summary.failures.len(), 1);
+        assert_eq!(summary.failures[0].class_loader, 0x7f00_2000);
+
+        let contains = |needle: &[u8]| bytes.windows(needle.len()).any(|window| window == needle);
+        assert!(contains(b"net/minecraft/client/Minecraft.class"));
+        assert!(contains(b"net/minecraft/client/Minecraft\t0x7f002000\t"));
+        assert!(!contains(b"com/example/Mod.class"));
+
+        // the other loader gets its class in when asked for
+        let filter = ClassFilter {
+            prefix: None,
+            class_loader: Some(0x7f00_2000),
+        };
+        let (_, summary) = dump_classes(&classes, &image, &filter, Vec::new()).unwrap();
+
+        assert_eq!(summary.written, 2);
+        assert!(summary.failures.is_empty());
+    }
+
+    #[test]
+    fn lists_classes_it_cannot_write() {
+        let mut image = JvmImage::new(0x7f00_0000, 0x2000);
+
+        // its constant pool is nowhere to be found
+        let object = JClass {
+            symbol: image.symbol(b"java/lang/Object") as _,
+            constant_pool: 0xdead_0000usize as _,
+            ..Default::default()
+        };
+
+        let mut classes = Classes::default();
+        classes.insert(0, "java/lang/Object".to_string(), object);
+
+        let (_, summary) =
+            dump_classes(&classes, &image, &ClassFilter::default(), Vec::new()).unwrap();
+
+        assert_eq!(summary.written, 0);
+        assert_eq!(summary.failures[0].name, "java/lang/Object");
+        assert!(!summary.failures[0].reason.is_empty());
+    }
+}
@@ -1,19 +1,140 @@
-use std::{collections::HashMap, fmt::Error, sync::Mutex};
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Mutex,
+};
 
-use winapi::shared::{d3d9types::D3DCOLOR_ARGB, minwindef::MAX_PATH};
+#[cfg(windows)]
+use std::fmt::Error;
 
-use crate::api::{processes, sdk::{*, self, activerenderinfo::world_to_screen, entity::{Vec2, Vec3}, minecraft::find_class}};
+#[cfg(windows)]
+use winapi::shared::d3d9types::D3DCOLOR_ARGB;
+
+use crate::api::{processes, sdk::{*, self, version, vmstructs}};
+
+#[cfg(windows)]
+use crate::api::sdk::{activerenderinfo::world_to_screen, entity::{Vec2, Vec3}, minecraft::find_class};
+
+pub mod classdump;
 
 lazy_static::lazy_static! {
-    pub static ref CLASSES: Mutex<HashMap<String, JClass>> = Mutex::new(HashMap::new());
+    pub static ref CLASSES: Mutex<Classes> = Mutex::new(Classes::default());
+}
+
+/// The loaded classes by name and by the loader (oop) that defined them, 0 being the boot loader.
+/// Mods, plugins and the launcher happily load classes with the same name, only the two together
+/// tell them apart.
+#[derive(Debug, Default)]
+pub struct Classes {
+    by_name: HashMap<String, BTreeMap<usize, JClass>>,
+}
+
+impl Classes {
+    pub fn insert(&mut self, class_loader: usize, name: String, clazz: JClass) {
+        self.by_name
+            .entry(name)
+            .or_default()
+            .insert(class_loader, clazz);
+    }
+
+    /// The class `name` of whichever loader comes first, the boot loader if it has one
+    pub fn find(&self, name: &str) -> Option<&JClass> {
+        self.by_name
+            .get(name)
+            .and_then(|loaders| loaders.values().next())
+    }
+
+    /// The class `name` as defined by `class_loader`
+    pub fn find_in(&self, class_loader: usize, name: &str) -> Option<&JClass> {
+        self.by_name
+            .get(name)
+            .and_then(|loaders| loaders.get(&class_loader))
+    }
+
+    /// Every loader that defined a class `name`, with its class
+    pub fn find_all(&self, name: &str) -> impl Iterator<Item = (usize, &JClass)> {
+        self.by_name
+            .get(name)
+            .into_iter()
+            .flat_map(|loaders| loaders.iter().map(|(loader, clazz)| (*loader, clazz)))
+    }
+
+    /// Every class with the loader that defined it, sorted by name and, within a name, by loader
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &str, &JClass)> {
+        let mut names = self.by_name.keys().collect::<Vec<&String>>();
+        names.sort();
+
+        names.into_iter().flat_map(move |name| {
+            self.by_name[name]
+                .iter()
+                .map(move |(loader, clazz)| (*loader, name.as_str(), clazz))
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_name.values().map(|loaders| loaders.len()).sum()
+    }
+
+    #[allow(dead_code)]
+    pub fn is_empty(&self) -> bool {
+        self.by_name.is_empty()
+    }
+}
+
+/// Where a JVM keeps track of the classes it loaded
+pub enum ClassTable {
+    /// JDK 8 and older, one hashtable for the classes of every loader
+    SystemDictionary(sdk::JVMDictionary),
+
+    /// JDK 9 and newer, every class loader keeps its own classes. Holds the address of
+    /// `ClassLoaderDataGraph::_head`, the list grows at the front so we read it when we need it.
+    ClassLoaderDataGraph(usize),
+}
+
+impl ClassTable {
+    /// Find the class table the way `version` of the JVM keeps it, the layouts of the JVM have
+    /// to be installed already
+    pub fn locate(
+        source: &dyn processes::MemorySource,
+        version: version::JavaVersion,
+    ) -> Option<Self> {
+        let (head, dictionary) = {
+            let structs = vmstructs::VM_STRUCTS.lock().unwrap();
+
+            (
+                structs.static_address("ClassLoaderDataGraph::_head"),
+                structs.static_address("SystemDictionary::_dictionary"),
+            )
+        };
+
+        if version.has_class_loader_data_graph() {
+            return head.map(ClassTable::ClassLoaderDataGraph);
+        }
+
+        let dictionary = processes::try_read_exact::<usize>(source, dictionary?).ok()?;
+
+        sdk::JVMDictionary::from_native(source, dictionary as _)
+            .ok()
+            .map(ClassTable::SystemDictionary)
+    }
+
+    /// Every klass in the table
+    pub fn klasses(&self, source: &dyn processes::MemorySource) -> Vec<*mut JClass> {
+        match self {
+            ClassTable::SystemDictionary(dictionary) => iterate_classes(dictionary, source)
+                .map(|entry| entry.klass)
+                .collect(),
+            ClassTable::ClassLoaderDataGraph(head) => iterate_class_loader_data(*head, source)
+                .flat_map(|class_loader_data| class_loader_data.klasses(source))
+                .collect(),
+        }
+    }
 }
 
-pub fn spawn_instance(
-    dictionary: sdk::JVMDictionary,
-    handle: processes::NativeHandle,
-) -> Option<Error> {
+#[cfg(windows)]
+pub fn spawn_instance(table: ClassTable, handle: processes::NativeHandle) -> Option<Error> {
     {
-        *CLASSES.lock().unwrap() = collect_all_classes(&dictionary, &handle);
+        *CLASSES.lock().unwrap() = collect_all_classes(&table, &handle);
+        println!("Found {} classes", CLASSES.lock().unwrap().len());
 
 
         let minecraft_class = find_class("bao");
@@ -26,33 +147,48 @@ pub fn spawn_instance(
                 .expect("Couldn't find target window.");
         let overlay = win_overlay::Overlay::create_overlay(mc_window);
 
-        let render_info = activerenderinfo::RenderInfo::new();
+        let render_info = activerenderinfo::RenderInfo;
 
-        let model_view_buffer = render_info.get_modelview(&handle);
-        let projection_buffer = render_info.get_projection(&handle);
-        let viewport_buffer = render_info.get_viewport(&handle);
+        let model_view_buffer = render_info
+            .get_modelview(&handle)
+            .expect("Couldn't read modelview buffer");
+        let projection_buffer = render_info
+            .get_projection(&handle)
+            .expect("Couldn't read projection buffer");
+        let viewport_buffer = render_info
+            .get_viewport(&handle)
+            .expect("Couldn't read viewport buffer");
 
 
         overlay.draw(&|| {
 
-             let model_view = model_view_buffer.as_vec(&handle);
-             let projection = projection_buffer.as_vec(&handle);
-             let viewport = viewport_buffer.as_vec(&handle);
+             let (model_view, projection, viewport) = match (
+                 model_view_buffer.to_vec(&handle),
+                 projection_buffer.to_vec(&handle),
+                 viewport_buffer.to_vec(&handle),
+             ) {
+                 (Ok(model_view), Ok(projection), Ok(viewport)) => (model_view, projection, viewport),
+                 // nothing sane to draw with, try again next frame
+                 _ => return,
+             };
 
-             let render_position = render_info.get_render_position(&handle);
+             let render_position = match render_info.get_render_position(&handle) {
+                 Ok(render_position) => render_position,
+                 Err(_) => return,
+             };
 
-             let players = world.get_players(&handle);
+             let players = world.get_players(&handle).unwrap_or_default();
 
             players.iter().for_each(|player| {
 
                 let mut feet_position: Vec2 = unsafe {core::mem::zeroed()};
                 let mut head_position: Vec2 = unsafe { core::mem::zeroed()};
 
-                let player_position = player.get_position(&handle);
-                let last_tick_position = player.get_last_tick_position(&handle);
+                let player_position = player.position;
+                let last_tick_position = player.last_tick_position;
                 let last_tick_head = Vec3 { x: last_tick_position.x, y: last_tick_position.y + 1.8f64, z: last_tick_position.z};
 
-                let player_head_position = player.get_head_position(&handle);
+                let player_head_position = player.get_head_position();
 
                 let x_feet = (last_tick_position.x + (player_position.x - last_tick_position.x) * 1.0f64) - render_position.x;
                 let y_feet = (last_tick_position.y + (player_position.y - last_tick_position.y) * 1.0f64) - render_position.y;
@@ -105,33 +241,46 @@ pub fn spawn_instance(
     None
 }
 
-pub fn collect_all_classes(
-    dictionary: &sdk::JVMDictionary,
-    handle: &processes::NativeHandle,
-) -> HashMap<String, JClass> {
-    let mut classes: HashMap<String, JClass> = HashMap::new();
-
-    for entry in iterate_classes(&dictionary, &handle) {
-        let clazz = JClass::from_native(&handle, entry.klass);
-
-        if clazz.symbol != std::ptr::null_mut() {
-            let symbol = JSymbol::from_native(&handle, clazz.symbol);
-
-            // maybe its because were external, but the JVM seems to leave empty or disposed off pointers dangling, leading
-            // us to the most retarded text you can find, not ideal, so lets limit the length of classnames to MAX_PATH like the god
-            // bill gates intended
-            if symbol.lenght < MAX_PATH as _ {
-                let name = symbol.to_string(&handle);
-
-                if name.eq("bao") {
-                    println!("Minecraft: {:p}", entry.klass);
-                } else if name.contains("baj") {
-                    println!("{}", name);
+pub fn collect_all_classes(table: &ClassTable, source: &dyn processes::MemorySource) -> Classes {
+    let mut classes = Classes::default();
+
+    // every class of a loader points at the same ClassLoaderData, read each one once
+    let mut class_loaders: HashMap<usize, usize> = HashMap::new();
+
+    for klass in table.klasses(source) {
+        // entries we can't follow all the way to a proper name are of no use to us, skip them,
+        // just like array klasses (negative layout helper) which have no fields to speak of
+        let clazz = match JClass::from_native(source, klass) {
+            Ok(clazz) if !clazz.symbol.is_null() && clazz.layout_helper >= 0 => clazz,
+            _ => continue,
+        };
+
+        let name = match JSymbol::from_native(source, clazz.symbol)
+            .and_then(|symbol| symbol.as_bytes(source))
+            .map(String::from_utf8)
+        {
+            Ok(Ok(name)) => name,
+            _ => continue,
+        };
+
+        if name.eq("bao") {
+            println!("Minecraft: {:p}", klass);
+        } else if name.contains("baj") {
+            println!("{}", name);
+        }
+
+        let class_loader = match class_loaders.get(&(clazz.classloader_data as usize)) {
+            Some(class_loader) => *class_loader,
+            None => match clazz.class_loader(source) {
+                Ok(class_loader) => {
+                    class_loaders.insert(clazz.classloader_data as usize, class_loader);
+                    class_loader
                 }
+                Err(_) => continue,
+            },
+        };
 
-                classes.insert(symbol.to_string(&handle), clazz);
-            }
-        }
+        classes.insert(class_loader, name, clazz);
     }
 
     classes
@@ -139,35 +288,196 @@ pub fn collect_all_classes(
 
 pub fn iterate_classes(
     dictionary: &sdk::JVMDictionary,
-    handle: &processes::NativeHandle,
+    source: &dyn processes::MemorySource,
 ) -> impl Iterator<Item = sdk::DictionaryEntry> {
     let mut classes: Vec<sdk::DictionaryEntry> = Vec::new();
 
-    unsafe {
-        (0..dictionary.table_size).for_each(|idx| {
-            let mut entry_native = processes::read_class_original::<DictionaryEntry>(
-                handle,
-                dictionary.entries.offset(idx as _) as _,
-            );
+    (0..dictionary.table_size).for_each(|idx| {
+        // every bucket holds a pointer to the first entry of its chain
+        let mut address = match processes::try_read_exact::<usize>(
+            source,
+            dictionary.entries as usize + idx as usize * std::mem::size_of::<usize>(),
+        ) {
+            Ok(address) => address,
+            Err(_) => return,
+        };
+
+        while address != 0 {
+            // a chain we can't read any further ends here
+            let entry = match DictionaryEntry::from_native(source, address as _) {
+                Ok(entry) => entry,
+                Err(_) => break,
+            };
+
+            address = entry.next();
+
+            if !entry.klass.is_null() {
+                classes.push(entry);
+            }
+        }
+    });
 
-            let mut entry = entry_native.get() as *mut DictionaryEntry;
+    classes.into_iter()
+}
 
-            // push back first class
-            classes.push(entry.read());
+/// Every class loader, following `ClassLoaderData::_next` from `ClassLoaderDataGraph::_head` at
+/// `head`
+pub fn iterate_class_loader_data(
+    head: usize,
+    source: &dyn processes::MemorySource,
+) -> impl Iterator<Item = JClassLoaderData> {
+    let mut class_loaders: Vec<JClassLoaderData> = Vec::new();
+
+    let mut address = processes::try_read_exact::<usize>(source, head).unwrap_or_default();
+
+    while address != 0 {
+        // a loader we can't read ends the walk, there's no way to get to the next one
+        let class_loader_data = match JClassLoaderData::from_native(source, address as _) {
+            Ok(class_loader_data) => class_loader_data,
+            Err(_) => break,
+        };
+
+        address = class_loader_data.next as usize;
+        class_loaders.push(class_loader_data);
+    }
 
-            while entry != std::ptr::null_mut() {
-                if entry.read().next == 0 || entry.read().klass == std::ptr::null_mut() {
-                    break;
-                }
+    class_loaders.into_iter()
+}
 
-                entry_native =
-                    processes::read_class_original::<DictionaryEntry>(handle, entry.read().next() as _);
-                entry = entry_native.get() as *mut DictionaryEntry;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-                classes.push(entry.read());
-            }
-        })
+    use crate::api::sdk::testing::JvmImage;
+
+    #[test]
+    fn walks_every_bucket_chain() {
+        let mut image = JvmImage::new(0x7f00_0000, 0x2000);
+
+        let object = image.class("java/lang/Object", 0, &[]);
+        let minecraft = image.class("bao", object, &[]);
+        let world = image.class("bjf", object, &[]);
+
+        // an empty bucket, a bucket chaining two classes and one holding a dead entry
+        let dictionary = image.put_dictionary(
+            image.base() + 0x1800,
+            image.base() + 0x1900,
+            &[&[], &[object, minecraft], &[0, world]],
+        );
+
+        let klasses = iterate_classes(&dictionary, &image)
+            .map(|entry| entry.klass as usize)
+            .collect::<Vec<usize>>();
+
+        assert_eq!(klasses, vec![object, minecraft, world]);
     }
 
-    classes.into_iter()
+    #[test]
+    fn skips_unreadable_classes() {
+        let mut image = JvmImage::new(0x7f00_0000, 0x2000);
+
+        let minecraft = image.class("bao", 0, &[]);
+
+        // not even modified UTF-8
+        let garbage = JClass {
+            symbol: image.symbol(b"b\xffo") as _,
+            ..Default::default()
+        };
+        let garbage_address = image.class_from(&garbage);
+
+        let (address, _) = image.dictionary(&[&[minecraft, 0xdead_0000], &[garbage_address]]);
+        let dictionary = JVMDictionary::from_native(&image, address as _).unwrap();
+
+        let classes = collect_all_classes(&ClassTable::SystemDictionary(dictionary), &image);
+
+        assert_eq!(classes.len(), 1);
+        assert_eq!(
+            classes.find("bao").unwrap().symbol,
+            JClass::from_native(&image, minecraft as _).unwrap().symbol
+        );
+    }
+
+    #[test]
+    fn walks_class_loader_data_graph() {
+        let mut image = JvmImage::new(0x7f00_0000, 0x3000);
+
+        let object = image.class("java/lang/Object", 0, &[]);
+        let string = image.class("java/lang/String", object, &[]);
+        let minecraft = image.class("bao", object, &[]);
+
+        // array klasses live in the same list, but they aren't classes we can look into
+        let strings = JClass {
+            layout_helper: -0x7ffd_fff4,
+            symbol: image.symbol(b"[Ljava/lang/String;") as _,
+            ..Default::default()
+        };
+        let strings = image.class_from(&strings);
+
+        let boot = image.class_loader_data(0, &[object, string, strings]);
+        let app = image.class_loader_data(0x7f00_dead, &[minecraft]);
+        let head = image.class_loader_data_graph(&[boot, app]);
+
+        let table = ClassTable::ClassLoaderDataGraph(head);
+
+        assert_eq!(
+            table.klasses(&image),
+            vec![object, string, strings, minecraft]
+                .into_iter()
+                .map(|klass| klass as *mut JClass)
+                .collect::<Vec<*mut JClass>>()
+        );
+
+        let classes = collect_all_classes(&table, &image);
+
+        assert_eq!(classes.len(), 3);
+        assert!(classes.find_in(0, "java/lang/String").is_some());
+        assert!(classes.find("[Ljava/lang/String;").is_none());
+        assert_eq!(
+            classes.find_in(0x7f00_dead, "bao").unwrap().base as usize,
+            minecraft
+        );
+    }
+
+    #[test]
+    fn keeps_same_name_from_different_loaders() {
+        let mut image = JvmImage::new(0x7f00_0000, 0x3000);
+
+        let object = image.class("java/lang/Object", 0, &[]);
+        let vanilla = image.class("bao", object, &[]);
+        let modded = image.class("bao", object, &[]);
+
+        let boot = image.class_loader_data(0, &[object]);
+        let launcher = image.class_loader_data(0x7f00_1000, &[vanilla]);
+        let mods = image.class_loader_data(0x7f00_2000, &[modded]);
+        let head = image.class_loader_data_graph(&[mods, launcher, boot]);
+
+        let loaders = iterate_class_loader_data(head, &image)
+            .map(|class_loader_data| class_loader_data.class_loader)
+            .collect::<Vec<usize>>();
+        assert_eq!(loaders, vec![0x7f00_2000, 0x7f00_1000, 0]);
+
+        let classes = collect_all_classes(&ClassTable::ClassLoaderDataGraph(head), &image);
+
+        assert_eq!(classes.len(), 3);
+        assert_eq!(
+            classes.find_in(0x7f00_1000, "bao").unwrap().base as usize,
+            vanilla
+        );
+        assert_eq!(
+            classes.find_in(0x7f00_2000, "bao").unwrap().base as usize,
+            modded
+        );
+        assert!(classes.find_in(0, "bao").is_none());
+
+        // the loader of a class finds its siblings
+        let clazz = JClass::from_native(&image, modded as _).unwrap();
+        assert_eq!(clazz.class_loader(&image), Ok(0x7f00_2000));
+        assert_eq!(
+            classes
+                .find_all("bao")
+                .map(|(loader, _)| loader)
+                .collect::<Vec<usize>>(),
+            vec![0x7f00_1000, 0x7f00_2000]
+        );
+    }
 }
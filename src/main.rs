@@ -1,5 +1,5 @@
-#[cfg(not(windows))]
-compile_error!("Ether-rs is exclusive to Windows at the moment");
+#[cfg(not(any(windows, target_os = "linux")))]
+compile_error!("Ether-rs is exclusive to Windows and Linux at the moment");
 
 // Compiler thinks we're not using it, when, infact, we are.
 #[allow(unused_imports, unused_attributes)]
@@ -7,6 +7,7 @@ compile_error!("Ether-rs is exclusive to Windows at the moment");
 use lazy_static;
 
 // Compiler thinks we're not using it, when, infact, we are.
+#[cfg(windows)]
 #[allow(unused_imports, unused_attributes)]
 #[macro_use]
 use win_overlay::utils;
@@ -19,50 +20,245 @@ mod ether;
 
 use api::*;
 
+const USAGE: &str = "usage: ethe-rs [dump-classes <jar> [--prefix <package>] [--loader <address>]]
+       ethe-rs [inspect <class> [--loader <address>]]
+       ethe-rs --core <file> [command] (Linux, reads a gcore of java instead)";
+
+/// What to do with the classes instead of running the overlay
+enum Command {
+    /// Write the classes that pass the filter into a jar
+    DumpClasses {
+        jar: std::path::PathBuf,
+        filter: ether::classdump::ClassFilter,
+    },
+
+    /// Print a class like javap would, `name` separated by '/'
+    Inspect {
+        name: String,
+        class_loader: Option<usize>,
+    },
+}
+
+/// The core file given with a leading `--core <file>` and the arguments after it
+#[cfg(target_os = "linux")]
+fn split_core(args: &[String]) -> Result<(Option<std::path::PathBuf>, &[String]), String> {
+    match args {
+        [flag, core, rest @ ..] if flag == "--core" => Ok((Some(core.into()), rest)),
+        [flag] if flag == "--core" => Err("--core needs a value".to_string()),
+        args => Ok((None, args)),
+    }
+}
+
+/// The command and its arguments, `None` if we weren't given any
+fn parse_command(args: &[String]) -> Option<Result<Command, String>> {
+    let (command, args) = args.split_first()?;
+
+    Some(parse_arguments(command, args))
+}
+
+fn parse_arguments(command: &str, args: &[String]) -> Result<Command, String> {
+    let mut positional = None;
+    let mut prefix = None;
+    let mut class_loader = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{} needs a value", arg));
+
+        match arg.as_str() {
+            "--prefix" if command == "dump-classes" => prefix = Some(value()?.clone()),
+            "--loader" => {
+                let loader = value()?;
+
+                // the boot loader is 0, everything else an address we printed before
+                class_loader = Some(
+                    usize::from_str_radix(loader.trim_start_matches("0x"), 16)
+                        .map_err(|_| format!("bad class loader {}", loader))?,
+                );
+            }
+            other if positional.is_none() && !other.starts_with("--") => {
+                positional = Some(other.to_string())
+            }
+            other => return Err(format!("unexpected argument {}", other)),
+        }
+    }
+
+    match command {
+        "dump-classes" => Ok(Command::DumpClasses {
+            jar: positional
+                .ok_or("dump-classes needs a jar to write to")?
+                .into(),
+            filter: ether::classdump::ClassFilter {
+                prefix,
+                class_loader,
+            },
+        }),
+        "inspect" => Ok(Command::Inspect {
+            name: positional
+                .ok_or("inspect needs a class to inspect")?
+                .replace('.', "/"),
+            class_loader,
+        }),
+        command => Err(format!("unknown command {}", command)),
+    }
+}
+
+/// Write the classes that pass `filter` into `jar`
+fn dump_classes(
+    classes: &ether::Classes,
+    source: &dyn processes::MemorySource,
+    jar: &std::path::Path,
+    filter: &ether::classdump::ClassFilter,
+) {
+    let file = match std::fs::File::create(jar) {
+        Ok(file) => std::io::BufWriter::new(file),
+        Err(error) => {
+            println!("Couldn't create {}: {}", jar.display(), error);
+            return;
+        }
+    };
+
+    match ether::classdump::dump_classes(classes, source, filter, file) {
+        Ok((_, summary)) => println!(
+            "Wrote {} of {} classes to {}, {} failed (see {})",
+            summary.written,
+            classes.len(),
+            jar.display(),
+            summary.failures.len(),
+            ether::classdump::FAILURES
+        ),
+        Err(error) => println!("Couldn't write {}: {}", jar.display(), error),
+    }
+}
+
+/// Print the class `name`, the one of `class_loader` if given
+fn inspect(
+    classes: &ether::Classes,
+    source: &dyn processes::MemorySource,
+    name: &str,
+    class_loader: Option<usize>,
+) {
+    let clazz = match class_loader {
+        Some(class_loader) => classes.find_in(class_loader, name),
+        None => classes.find(name),
+    };
+
+    let clazz = match clazz {
+        Some(clazz) => clazz,
+        None => {
+            println!("Couldn't find class {}", name);
+            return;
+        }
+    };
+
+    let loaders = classes
+        .find_all(name)
+        .map(|(loader, _)| format!("{:#x}", loader))
+        .collect::<Vec<String>>();
+    if class_loader.is_none() && loaders.len() > 1 {
+        println!(
+            "Defined by loaders {}, showing the first (pick one with --loader)",
+            loaders.join(", ")
+        );
+    }
+
+    match sdk::report::ClassReport::new(source, clazz) {
+        Ok(report) => print!("{}", report),
+        Err(error) => println!("Couldn't read class {}: {}", name, error),
+    }
+}
+
+fn run_command(command: &Command, table: &ether::ClassTable, source: &dyn processes::MemorySource) {
+    let classes = ether::collect_all_classes(table, source);
+
+    match command {
+        Command::DumpClasses { jar, filter } => dump_classes(&classes, source, jar, filter),
+        Command::Inspect { name, class_loader } => inspect(&classes, source, name, *class_loader),
+    }
+}
+
+/// Read the VMStructs tables the JVM exports, and with them find where this Java version keeps
+/// its classes
+fn class_table_from_exports(
+    source: &dyn processes::MemorySource,
+    jvm: &processes::ModuleEntry,
+) -> Option<ether::ClassTable> {
+    use sdk::{java::ArrayLayout, oops::CompressedOops, version::*, vmstructs::*};
+
+    let symbols = VMStructsSymbols::from_exports(source, jvm)?;
+    let mut structs = VMStructs::read(source, &symbols).ok()?;
+
+    let version = JavaVersion::detect(source, &structs)?;
+    println!(
+        "Java {} (VM {})",
+        version.major,
+        vm_release(source, &structs).unwrap_or_else(|| "unknown".to_string())
+    );
+
+    structs.set_java_version(version);
+
+    let compressed_oops = CompressedOops::read(source, &structs);
+    ArrayLayout::read(source, &structs, &compressed_oops).install();
+    compressed_oops.install();
+
+    structs.install();
+
+    ether::ClassTable::locate(source, version)
+}
+
 #[cfg(windows)]
 fn main() {
-    use winapi::um::memoryapi::ReadProcessMemory;
+    use processes::MemorySource;
+    use sdk::FromNative;
 
     println!("Ethe-rs is Ether but Rust, because Rust owns me and all");
 
+    let args = std::env::args().skip(1).collect::<Vec<String>>();
+    let command = match parse_command(&args).transpose() {
+        Ok(command) => command,
+        Err(error) => {
+            println!("{}\n{}", error, USAGE);
+            std::process::exit(0x8);
+        }
+    };
+
     let dictionary_pattern = sig::Signature::new("48 8b 0d ?? ?? ?? ?? 4c 8b cd 44 8b c7");
 
     println!("Searching for: {:?}", dictionary_pattern.to_bytes());
 
     if let Some(javaw) = processes::find_process("javaw.exe") {
         if let Some(handle) = processes::open_process(&javaw) {
-            if let Some(jvm_dll) = processes::find_module("jvm.dll", Some(javaw)) {
+            if let Some(jvm_dll) = handle.find_module("jvm.dll") {
                 println!("Module jvm.dll at address {:p}", jvm_dll.base as *mut i8);
 
-                let dictionary =
-                    sig::pattern_scan_module(handle.get(), &dictionary_pattern, jvm_dll)
-                        .as_mut()
-                        .and_then(|dictionary| {
-                            let mut offset: i32 = 0;
-                            processes::read(&handle, *dictionary + 3, &mut offset);
-                            let end = *dictionary + 7 + offset as usize;
-
-                            let dictionary_buffer: *mut sdk::JVMDictionary =
-                                [0 as i8; std::mem::size_of::<sdk::JVMDictionary>()].as_mut_ptr()
-                                    as *mut _;
-                            let mut address: i32 = 0;
-                            processes::read(&handle, end, &mut address);
-
-                            unsafe {
-                                ReadProcessMemory(
-                                    handle.get(),
-                                    address as _,
-                                    dictionary_buffer as *mut _,
-                                    std::mem::size_of::<sdk::JVMDictionary>(),
-                                    std::ptr::null_mut(),
-                                );
-                                return Some(dictionary_buffer.read());
-                            };
-                        })
-                        .expect("Crap!");
-
-                // Spawn an instance
-                ether::spawn_instance(dictionary, handle);
+                // the signature only matches a handful of builds, exports are the way to go
+                let classes = class_table_from_exports(&handle, &jvm_dll)
+                    .or_else(|| {
+                        println!("No usable VMStructs exports, falling back to the signature");
+
+                        sig::pattern_scan_module(&handle, &dictionary_pattern, jvm_dll)
+                            .as_mut()
+                            .and_then(|dictionary| {
+                                let mut offset: i32 = 0;
+                                processes::read(&handle, *dictionary + 3, &mut offset);
+                                let end = *dictionary + 7 + offset as usize;
+
+                                let mut address: i32 = 0;
+                                processes::read(&handle, end, &mut address);
+
+                                sdk::JVMDictionary::from_native(&handle, address as _).ok()
+                            })
+                            .map(ether::ClassTable::SystemDictionary)
+                    })
+                    .expect("Crap!");
+
+                match command {
+                    Some(command) => run_command(&command, &classes, &handle),
+                    // Spawn an instance
+                    None => {
+                        ether::spawn_instance(classes, handle);
+                    }
+                }
             } else {
                 println!("Couldn't find address of jvm.dll :(");
                 std::thread::sleep(std::time::Duration::from_secs(5));
@@ -81,3 +277,74 @@ fn main() {
         std::process::exit(0x1);
     }
 }
+
+/// Find the classes through the libjvm.so mapped in `source` and run `command` on them, or just
+/// count them without one
+#[cfg(target_os = "linux")]
+fn run_on_libjvm(source: &dyn processes::MemorySource, command: Option<&Command>) {
+    let libjvm = match source.find_module("libjvm.so") {
+        Some(libjvm) => libjvm,
+        None => {
+            println!("Couldn't find address of libjvm.so :(");
+            std::process::exit(0x4);
+        }
+    };
+
+    println!("Module libjvm.so at address {:p}", libjvm.base as *mut i8);
+
+    // the dictionary signature only matches the MSVC build of jvm.dll, there's no fallback if
+    // libjvm.so doesn't export its VMStructs
+    match (class_table_from_exports(source, &libjvm), command) {
+        (Some(classes), Some(command)) => run_command(command, &classes, source),
+        (Some(classes), None) => println!(
+            "Found {} classes",
+            ether::collect_all_classes(&classes, source).len()
+        ),
+        (None, _) => println!("Couldn't find the loaded classes"),
+    }
+
+    println!("Done.");
+}
+
+#[cfg(target_os = "linux")]
+fn main() {
+    println!("Ethe-rs is Ether but Rust, because Rust owns me and all");
+
+    let args = std::env::args().skip(1).collect::<Vec<String>>();
+    let (core, command) = match split_core(&args)
+        .and_then(|(core, args)| Ok((core, parse_command(args).transpose()?)))
+    {
+        Ok(arguments) => arguments,
+        Err(error) => {
+            println!("{}\n{}", error, USAGE);
+            std::process::exit(0x8);
+        }
+    };
+
+    // a snapshot reads the same as the live process, minus everything that moves
+    if let Some(core) = core {
+        match dump::elf::CoreDump::open(&core) {
+            Ok(dump) => run_on_libjvm(&dump, command.as_ref()),
+            Err(error) => {
+                println!("Couldn't open {}: {}", core.display(), error);
+                std::process::exit(0x2);
+            }
+        }
+
+        return;
+    }
+
+    if let Some(java) = processes::find_process("java") {
+        if let Some(handle) = processes::open_process(&java) {
+            println!("Reading process {}", java.pid);
+
+            run_on_libjvm(&handle, command.as_ref());
+        } else {
+            println!("Couldn't open handle to process");
+            std::process::exit(0x2);
+        }
+    } else {
+        println!("Couldn't find Minecraft process, you sure it's running?");
+        std::process::exit(0x1);
+    }
+}